@@ -0,0 +1,770 @@
+//! ABC记谱法导入导出
+//!
+//! 导出时`L:`固定为1/8，音高按高音竖笛的实际音高（`c`为C5）。导入时只读第一首曲子的第一个声部，
+//! 和弦只取最高音，反复记号当作普通小节线，装饰音、和弦名称等忽略。
+
+use crate::notation::parser::Meter;
+use crate::score::{fit_octave, Event, Score, DIVISIONS, MEASURES_PER_LINE};
+use crate::tone::{FingerTone, Tone};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+/// 导出时`L:1/8`对应的时值
+const UNIT: u32 = DIVISIONS / 2;
+
+/// 升号和降号加在调号上的顺序
+const SHARP_ORDER: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+/// 解析错误
+#[derive(Debug, PartialEq)]
+pub struct AbcError(String);
+
+impl Display for AbcError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for AbcError {}
+
+/// 导入设置
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AbcOptions {
+    /// 简谱的调，默认使用`K:`的调
+    pub finger_tone: Option<FingerTone>,
+    /// 整体移高几个八度，默认自动移到竖笛能吹的音域
+    pub octave: Option<i8>,
+}
+
+/// 乐谱转换为ABC
+pub fn write_abc(score: &Score, title: &str) -> String {
+    let flats = score.finger_tone.fifths() < 0;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "X:1");
+    let _ = writeln!(out, "T:{}", title);
+    if let Some(meter) = score.meter {
+        let _ = writeln!(out, "M:{}", meter);
+    }
+    let _ = writeln!(out, "L:1/8");
+    if let Some(tempo) = score.tempo {
+        let _ = writeln!(out, "Q:1/4={}", tempo);
+    }
+    let _ = writeln!(out, "K:{}", key_name(score.finger_tone));
+
+    let measures = score.measures();
+    let count = measures.len();
+    for (index, chunk) in measures.chunks(MEASURES_PER_LINE).enumerate() {
+        let mut line = String::new();
+        let mut lyrics: Vec<Vec<String>> = Vec::new();
+        let mut notes = 0;
+
+        for (offset, measure) in chunk.iter().enumerate() {
+            // 小节内临时升降记号的状态：(音名, 八度) -> 升降
+            let mut accidentals = Vec::new();
+            for event in measure {
+                match event {
+                    Event::Note { tone, duration, lyrics: words } => {
+                        let (step, alter, octave) = tone.spell(flats);
+                        let letter = step.chars().next().unwrap_or('C');
+                        let current = accidentals.iter()
+                            .find(|&&(l, o, _)| l == letter && o == octave)
+                            .map(|&(_, _, alter)| alter)
+                            .unwrap_or_else(|| key_alter(score.finger_tone.fifths(), letter));
+                        if alter != current {
+                            line.push_str(match alter {
+                                1 => "^",
+                                -1 => "_",
+                                _ => "=",
+                            });
+                            accidentals.retain(|&(l, o, _)| l != letter || o != octave);
+                            accidentals.push((letter, octave, alter));
+                        }
+                        line.push_str(&pitch_name(letter, octave));
+                        line.push_str(&length(*duration));
+
+                        for (verse, word) in words.iter().enumerate() {
+                            if lyrics.len() <= verse {
+                                lyrics.resize(verse + 1, Vec::new());
+                            }
+                            let verse = &mut lyrics[verse];
+                            verse.resize(notes, "*".to_owned());
+                            verse.push(word.clone().unwrap_or_else(|| "*".to_owned()));
+                        }
+                        notes += 1;
+                    }
+                    Event::Rest { duration } => {
+                        line.push('z');
+                        line.push_str(&length(*duration));
+                    }
+                    Event::Bar => {}
+                }
+                line.push(' ');
+            }
+            let last = index * MEASURES_PER_LINE + offset + 1 == count;
+            line.push_str(if last { "|]" } else { "| " });
+        }
+
+        let _ = writeln!(out, "{}", line.trim_end());
+        for verse in lyrics {
+            let _ = writeln!(out, "w:{}", verse.iter().map(|word| format!(" {}", word)).collect::<String>());
+        }
+    }
+
+    out
+}
+
+/// 解析ABC，只读第一首曲子
+pub fn read_abc(abc: &str, options: &AbcOptions) -> Result<Score, AbcError> {
+    let mut reader = Reader::default();
+    let mut in_body = false;
+    let mut key = None;
+
+    for (number, line) in abc.lines().enumerate() {
+        let line = line.split('%').next().unwrap_or_default().trim();
+        let error = |message: String| AbcError(format!("第{}行：{}", number + 1, message));
+
+        if line.is_empty() {
+            // 空行结束一首曲子
+            if in_body {
+                break;
+            }
+            continue;
+        }
+
+        let field = line.as_bytes().get(1) == Some(&b':') && line.as_bytes()[0].is_ascii_alphabetic();
+        if field {
+            let value = line[2..].trim();
+            match &line[..1] {
+                "K" => {
+                    let fifths = parse_key(value).map_err(error)?;
+                    if !in_body {
+                        key = Some(fifths);
+                        in_body = true;
+                    }
+                    reader.fifths = fifths;
+                }
+                "M" => {
+                    let meter = parse_meter(value).map_err(error)?;
+                    if reader.meter.is_none() {
+                        reader.meter = meter;
+                    }
+                    if !reader.unit_set && !in_body {
+                        // 没有L:时按拍号决定默认单位时值
+                        reader.unit = match meter {
+                            Some(meter) if (meter.beats as f32) / (meter.beat_type as f32) < 0.75 => DIVISIONS / 4,
+                            _ => DIVISIONS / 2,
+                        };
+                    }
+                }
+                "L" => {
+                    let (a, b) = parse_fraction(value).ok_or_else(|| error(format!("L:有误：{}", value)))?;
+                    reader.unit = DIVISIONS * 4 * a / b;
+                    reader.unit_set = true;
+                }
+                "Q" if reader.tempo.is_none() => {
+                    reader.tempo = Some(parse_tempo(value).ok_or_else(|| error(format!("Q:有误：{}", value)))?);
+                }
+                "V" => {
+                    let id = value.split_whitespace().next().unwrap_or_default().to_owned();
+                    reader.skip_voice = match &reader.voice {
+                        Some(voice) => *voice != id,
+                        None => {
+                            reader.voice = Some(id);
+                            false
+                        }
+                    };
+                }
+                "w" if in_body && !reader.skip_voice => reader.lyrics(value),
+                _ => {}
+            }
+            continue;
+        }
+
+        if in_body && !reader.skip_voice {
+            reader.line(line).map_err(error)?;
+        }
+    }
+
+    let fifths = key.ok_or_else(|| AbcError("没有K:调号".to_owned()))?;
+    reader.finish(fifths, options)
+}
+
+/// 逐行读取乐曲正文
+struct Reader {
+    /// `L:`的单位时值
+    unit: u32,
+    unit_set: bool,
+    meter: Option<Meter>,
+    tempo: Option<u32>,
+    /// 当前调号
+    fifths: i8,
+    /// 只读第一个声部
+    voice: Option<String>,
+    skip_voice: bool,
+    /// 音高为MIDI编号的事件
+    events: Vec<(Option<i32>, Event)>,
+    /// 当前简谱行的音符位置，歌词按顺序对应
+    line_notes: Vec<usize>,
+    verse: usize,
+    /// 小节内的临时升降记号
+    accidentals: Vec<(char, i32, i32)>,
+    tie: bool,
+    /// 附点节奏`>`、`<`：下一个音符的时值倍数（分子, 分母）
+    broken: Option<(u32, u32)>,
+    /// 连音：剩余音符数和倍数
+    tuplet: Option<(u32, u32, u32)>,
+}
+
+impl Default for Reader {
+    fn default() -> Self {
+        Reader {
+            unit: DIVISIONS / 2,
+            unit_set: false,
+            meter: None,
+            tempo: None,
+            fifths: 0,
+            voice: None,
+            skip_voice: false,
+            events: Vec::new(),
+            line_notes: Vec::new(),
+            verse: 0,
+            accidentals: Vec::new(),
+            tie: false,
+            broken: None,
+            tuplet: None,
+        }
+    }
+}
+
+impl Reader {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        let mut line_started = false;
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '"' | '!' | '+' | '{' => {
+                    // 和弦名称、注释、装饰音、倚音
+                    let end = if c == '{' { '}' } else { c };
+                    i += 1;
+                    while i < chars.len() && chars[i] != end {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                '|' | ':' => {
+                    while i < chars.len() && matches!(chars[i], '|' | ':' | ']' | '[' ) {
+                        i += 1;
+                    }
+                    // 反复记号的第几遍
+                    while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == ',' || chars[i] == '-') {
+                        i += 1;
+                    }
+                    self.bar();
+                }
+                '[' => match chars.get(i + 1) {
+                    Some('|') => {
+                        i += 2;
+                        self.bar();
+                    }
+                    Some(d) if d.is_ascii_digit() => i += 2,
+                    Some(f) if f.is_ascii_alphabetic() && chars.get(i + 2) == Some(&':') => {
+                        // 行内字段，只处理调号变化
+                        let end = chars[i..].iter().position(|&c| c == ']').map(|p| i + p).unwrap_or(chars.len());
+                        let field = chars[i + 1..end].iter().collect::<String>();
+                        if let Some(value) = field.strip_prefix("K:") {
+                            self.fifths = parse_key(value.trim())?;
+                        }
+                        i = end + 1;
+                    }
+                    _ => {
+                        // 和弦只取最高音，时值取第一个音
+                        i += 1;
+                        let mut highest: Option<(i32, u32)> = None;
+                        while i < chars.len() && chars[i] != ']' {
+                            match self.note(&chars, &mut i)? {
+                                Some((Some(midi), duration)) => {
+                                    if !matches!(highest, Some((h, _)) if h >= midi) {
+                                        highest = Some((midi, highest.map_or(duration, |(_, d)| d)));
+                                    }
+                                }
+                                _ => i += 1,
+                            }
+                        }
+                        i += 1;
+                        let (num, den) = parse_length(&chars, &mut i);
+                        if let Some((midi, duration)) = highest {
+                            if !line_started {
+                                self.start_line();
+                                line_started = true;
+                            }
+                            self.push(Some(midi), duration * num / den);
+                        }
+                    }
+                },
+                '(' => {
+                    i += 1;
+                    if let Some(p) = chars.get(i).and_then(|c| c.to_digit(10)) {
+                        i += 1;
+                        let q = match p {
+                            2 | 4 | 8 => 3,
+                            3 | 6 => 2,
+                            _ => if self.meter.is_some_and(|m| m.beats % 3 == 0 && m.beats > 3) { 3 } else { 2 },
+                        };
+                        self.tuplet = Some((p, q, p));
+                    }
+                }
+                '>' | '<' => {
+                    let mut count = 0;
+                    while i < chars.len() && chars[i] == c {
+                        count += 1;
+                        i += 1;
+                    }
+                    // `>`前一个音符加附点，后一个音符减去相应时值
+                    let den = 1 << count;
+                    let (prev, next) = if c == '>' { (2 * den - 1, 1) } else { (1, 2 * den - 1) };
+                    if let Some((_, Event::Note { duration, .. })) | Some((_, Event::Rest { duration })) = self.events.last_mut() {
+                        *duration = *duration * prev / den;
+                    }
+                    self.broken = Some((next, den));
+                }
+                '-' => {
+                    self.tie = true;
+                    i += 1;
+                }
+                '&' => return Err("不支持声部叠加`&`，竖笛只能吹单音".to_owned()),
+                _ => match self.note(&chars, &mut i)? {
+                    Some((midi, duration)) => {
+                        if !line_started && midi.is_some() {
+                            self.start_line();
+                            line_started = true;
+                        }
+                        self.push(midi, duration);
+                    }
+                    None => i += 1,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// 读一个音符或休止符，返回音高和时值；不是音符时不移动位置，返回`None`
+    fn note(&mut self, chars: &[char], i: &mut usize) -> Result<Option<(Option<i32>, u32)>, String> {
+        let start = *i;
+        let mut alter = None;
+        while *i < chars.len() && matches!(chars[*i], '^' | '_' | '=') {
+            let delta = match chars[*i] {
+                '^' => 1,
+                '_' => -1,
+                _ => 0,
+            };
+            alter = Some(alter.unwrap_or(0) + delta);
+            *i += 1;
+        }
+
+        let c = match chars.get(*i) {
+            Some(&c) => c,
+            None => {
+                *i = start;
+                return Ok(None);
+            }
+        };
+        let midi = match c {
+            'A'..='G' | 'a'..='g' => {
+                *i += 1;
+                let letter = c.to_ascii_uppercase();
+                let mut octave = if c.is_ascii_uppercase() { 4 } else { 5 };
+                while *i < chars.len() && matches!(chars[*i], '\'' | ',') {
+                    octave += if chars[*i] == '\'' { 1 } else { -1 };
+                    *i += 1;
+                }
+                let alter = match alter {
+                    Some(alter) => {
+                        self.accidentals.retain(|&(l, o, _)| l != letter || o != octave);
+                        self.accidentals.push((letter, octave, alter));
+                        alter
+                    }
+                    None => self.accidentals.iter()
+                        .find(|&&(l, o, _)| l == letter && o == octave)
+                        .map(|&(_, _, alter)| alter)
+                        .unwrap_or_else(|| key_alter(self.fifths, letter) as i32),
+                };
+                Some((octave + 1) * 12 + step(letter) + alter)
+            }
+            'z' | 'x' => {
+                *i += 1;
+                None
+            }
+            'Z' | 'X' => {
+                // 整小节休止，可以跟小节数
+                *i += 1;
+                let (count, _) = parse_length(chars, i);
+                let measure = self.meter.map_or(DIVISIONS * 4, |m| m.beats as u32 * DIVISIONS * 4 / m.beat_type as u32);
+                for index in 0..count {
+                    if index > 0 {
+                        self.bar();
+                    }
+                    self.push(None, measure);
+                }
+                return Ok(Some((None, 0)));
+            }
+            _ => {
+                if alter.is_some() {
+                    return Err(format!("升降记号后面没有音符：{:?}", c));
+                }
+                *i = start;
+                return Ok(None);
+            }
+        };
+
+        let (num, den) = parse_length(chars, i);
+        Ok(Some((midi, self.unit * num / den)))
+    }
+
+    fn push(&mut self, midi: Option<i32>, duration: u32) {
+        if duration == 0 {
+            return;
+        }
+        let mut duration = duration;
+        if let Some((num, den)) = self.broken.take() {
+            duration = duration * num / den;
+        }
+        if let Some((p, q, left)) = self.tuplet {
+            duration = duration * q / p;
+            self.tuplet = if left > 1 { Some((p, q, left - 1)) } else { None };
+        }
+
+        // 简谱没有连音线：同一小节里合并成一个音符，跨过小节线的在小节线处断开
+        let tie = std::mem::replace(&mut self.tie, false);
+        if let (true, Some(midi), Some((Some(last), Event::Note { duration: last_duration, .. }))) = (tie, midi, self.events.last_mut()) {
+            if *last == midi {
+                *last_duration += duration;
+                return;
+            }
+        }
+
+        match midi {
+            Some(_) => {
+                self.line_notes.push(self.events.len());
+                self.events.push((midi, Event::Note { tone: Tone::C, duration, lyrics: Vec::new() }));
+            }
+            None => self.events.push((None, Event::Rest { duration })),
+        }
+    }
+
+    fn bar(&mut self) {
+        self.accidentals.clear();
+        // 连续的小节线和曲首的反复记号不产生空小节
+        if !matches!(self.events.last(), None | Some((_, Event::Bar))) {
+            self.events.push((None, Event::Bar));
+        }
+    }
+
+    fn start_line(&mut self) {
+        self.line_notes.clear();
+        self.verse = 0;
+    }
+
+    /// `w:`歌词行：空格和`-`分隔音节，`*`和`_`跳过一个音符，`~`连接成一个音节
+    fn lyrics(&mut self, value: &str) {
+        let words = value.split(|c: char| c.is_whitespace() || c == '-')
+            .filter(|word| !word.is_empty() && *word != "|")
+            .map(|word| word.replace('~', " "));
+        for (&index, word) in self.line_notes.iter().zip(words) {
+            if word == "*" || word == "_" {
+                continue;
+            }
+            if let (_, Event::Note { lyrics, .. }) = &mut self.events[index] {
+                lyrics.resize(self.verse + 1, None);
+                lyrics[self.verse] = Some(word);
+            }
+        }
+        self.verse += 1;
+    }
+
+    fn finish(self, fifths: i8, options: &AbcOptions) -> Result<Score, AbcError> {
+        let shift = match options.octave {
+            Some(octave) => 12 * octave as i32,
+            None => fit_octave(self.events.iter().filter_map(|(midi, _)| *midi)),
+        };
+
+        let events = self.events.into_iter().map(|(midi, event)| match (midi, event) {
+            (Some(midi), Event::Note { duration, lyrics, .. }) => {
+                let tone = u8::try_from(midi + shift).ok().and_then(Tone::from_midi)
+                    .ok_or_else(|| AbcError(format!("音高超出范围：MIDI {}", midi + shift)))?;
+                Ok(Event::Note { tone, duration, lyrics })
+            }
+            (_, event) => Ok(event),
+        }).collect::<Result<Vec<_>, AbcError>>()?;
+
+        Ok(Score {
+            finger_tone: options.finger_tone.unwrap_or_else(|| FingerTone::from_fifths(fifths)),
+            meter: self.meter,
+            tempo: self.tempo,
+            events,
+        })
+    }
+}
+
+/// `K:`字段转换为五度圈位置，调式换算成同调号的大调
+fn parse_key(value: &str) -> Result<i8, String> {
+    // 谱号、移调等修饰和`exp`后面的临时升降记号不影响调号
+    let value = value.split_whitespace().filter(|word| !is_key_modifier(word)).collect::<Vec<_>>();
+    let tonic = match value.first() {
+        None => return Ok(0),
+        Some(&"none") | Some(&"HP") | Some(&"Hp") => return Ok(0),
+        Some(tonic) => *tonic,
+    };
+
+    let mut chars = tonic.chars();
+    let letter = chars.next().map(|c| c.to_ascii_uppercase()).unwrap_or('C');
+    let mut fifths: i8 = match letter {
+        'C' => 0,
+        'G' => 1,
+        'D' => 2,
+        'A' => 3,
+        'E' => 4,
+        'B' => 5,
+        'F' => -1,
+        _ => return Err(format!("K:有误：{}", tonic)),
+    };
+    let rest = chars.as_str();
+    let (accidental, mode) = match rest.chars().next() {
+        Some('#') => (7, &rest[1..]),
+        Some('b') => (-7, &rest[1..]),
+        _ => (0, rest),
+    };
+    fifths += accidental;
+
+    // 调式可以和主音连写，也可以用空格隔开
+    let mode = if mode.is_empty() { value.get(1).copied().unwrap_or_default() } else { mode };
+    let mode = mode.to_lowercase();
+    let offset = match mode.get(..3).unwrap_or(&mode) {
+        "" | "maj" | "ion" => 0,
+        "m" | "min" | "aeo" => -3,
+        "mix" => -1,
+        "dor" => -2,
+        "phr" => -4,
+        "loc" => -5,
+        "lyd" => 1,
+        _ if mode.starts_with('m') && mode.len() == 1 => -3,
+        _ => return Err(format!("K:的调式无法识别：{}", mode)),
+    };
+    Ok(fifths + offset)
+}
+
+/// `K:`字段里调名和调式以外的部分，例如`clef=treble`、`transpose=-12`、`bass`、`exp`、`^f`
+fn is_key_modifier(word: &str) -> bool {
+    const CLEFS: [&str; 9] = ["treble", "bass", "bass3", "alto", "alto1", "alto2", "tenor", "baritone", "perc"];
+    word.contains('=')
+        || word.starts_with(['^', '_'])
+        || word == "exp"
+        || CLEFS.iter().any(|clef| word.eq_ignore_ascii_case(clef))
+}
+
+fn parse_meter(value: &str) -> Result<Option<Meter>, String> {
+    match value {
+        "" | "none" => Ok(None),
+        "C" => Ok(Some(Meter { beats: 4, beat_type: 4 })),
+        "C|" => Ok(Some(Meter { beats: 2, beat_type: 2 })),
+        _ => {
+            let (beats, beat_type) = parse_fraction(value).ok_or_else(|| format!("M:有误：{}", value))?;
+            match (u8::try_from(beats), u8::try_from(beat_type)) {
+                (Ok(beats), Ok(beat_type)) => Ok(Some(Meter { beats, beat_type })),
+                _ => Err(format!("M:有误：{}", value)),
+            }
+        }
+    }
+}
+
+/// `Q:`字段转换为每分钟四分音符数，例如`1/4=120`、`3/8=40`、`120`
+fn parse_tempo(value: &str) -> Option<u32> {
+    let value = value.split('"').rfind(|s| s.contains(|c: char| c.is_ascii_digit()))?.trim();
+    match value.split_once('=') {
+        Some((beat, bpm)) => {
+            let (a, b) = parse_fraction(beat.split_whitespace().next()?)?;
+            let bpm = bpm.trim().parse::<u32>().ok()?;
+            Some(bpm * 4 * a / b)
+        }
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_fraction(value: &str) -> Option<(u32, u32)> {
+    let (a, b) = value.split_once('/')?;
+    match (a.trim().parse(), b.trim().parse()) {
+        (Ok(a), Ok(b)) if a > 0 && b > 0 => Some((a, b)),
+        _ => None,
+    }
+}
+
+/// 音符后面的时值倍数，例如`2`、`/`、`//`、`3/2`
+fn parse_length(chars: &[char], i: &mut usize) -> (u32, u32) {
+    let number = |i: &mut usize| {
+        let start = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse::<u32>().ok()
+    };
+
+    let num = number(i).unwrap_or(1);
+    let mut den = 1;
+    while *i < chars.len() && chars[*i] == '/' {
+        *i += 1;
+        match number(i) {
+            Some(d) => den *= d.max(1),
+            None => den *= 2,
+        }
+    }
+    (num, den)
+}
+
+fn step(letter: char) -> i32 {
+    match letter {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        _ => 11,
+    }
+}
+
+/// 调号给这个音名加的升降
+fn key_alter(fifths: i8, letter: char) -> i8 {
+    let position = SHARP_ORDER.iter().position(|&l| l == letter).unwrap_or(0) as i8;
+    if fifths > position {
+        1
+    } else if fifths < 0 && 6 - position < -fifths {
+        -1
+    } else {
+        0
+    }
+}
+
+/// 调名，黑键调用降号
+fn key_name(finger_tone: FingerTone) -> &'static str {
+    match finger_tone {
+        FingerTone::C => "C",
+        FingerTone::SC => "Db",
+        FingerTone::D => "D",
+        FingerTone::SD => "Eb",
+        FingerTone::E => "E",
+        FingerTone::F => "F",
+        FingerTone::SF => "F#",
+        FingerTone::G => "G",
+        FingerTone::SG => "Ab",
+        FingerTone::A => "A",
+        FingerTone::SA => "Bb",
+        FingerTone::B => "B",
+    }
+}
+
+/// ABC的音名：C4为`C`，C5为`c`，再高加`'`，再低加`,`
+fn pitch_name(letter: char, octave: u8) -> String {
+    if octave >= 5 {
+        format!("{}{}", letter.to_ascii_lowercase(), "'".repeat(octave as usize - 5))
+    } else {
+        format!("{}{}", letter, ",".repeat(4 - octave as usize))
+    }
+}
+
+/// 以`L:1/8`为单位的时值写法
+fn length(duration: u32) -> String {
+    let gcd = gcd(duration, UNIT);
+    match (duration / gcd, UNIT / gcd) {
+        (1, 1) => String::new(),
+        (num, 1) => num.to_string(),
+        (1, 2) => "/".to_owned(),
+        (1, den) => format!("/{}", den),
+        (num, den) => format!("{}/{}", num, den),
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a.max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read(abc: &str) -> Score {
+        read_abc(abc, &AbcOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("G"), Ok(1));
+        assert_eq!(parse_key("Bb"), Ok(-2));
+        assert_eq!(parse_key("F#"), Ok(6));
+        assert_eq!(parse_key("Em"), Ok(1));
+        assert_eq!(parse_key("E minor"), Ok(1));
+        assert_eq!(parse_key("Ador"), Ok(1));
+        assert_eq!(parse_key("D Mix"), Ok(1));
+        assert_eq!(parse_key("none"), Ok(0));
+        assert_eq!(parse_key("G clef=treble"), Ok(1));
+        assert_eq!(parse_key("Am bass"), Ok(0));
+        assert_eq!(parse_key("D exp ^f ^c"), Ok(2));
+        assert_eq!(parse_key("C transpose=-12 middle=c"), Ok(0));
+        assert_eq!(parse_key("E dor octave=-1"), Ok(2));
+        assert_eq!(parse_key("clef=bass"), Ok(0));
+        assert!(parse_key("H").is_err());
+        assert!(parse_key("Cxyz").is_err());
+    }
+
+    #[test]
+    fn test_key_alter() {
+        assert_eq!(key_alter(1, 'F'), 1);
+        assert_eq!(key_alter(1, 'C'), 0);
+        assert_eq!(key_alter(-1, 'B'), -1);
+        assert_eq!(key_alter(-1, 'E'), 0);
+        assert_eq!(key_alter(-3, 'A'), -1);
+        assert_eq!(key_alter(7, 'B'), 1);
+    }
+
+    #[test]
+    fn test_read_abc() {
+        let score = read("X:1\nT:Test\nM:3/4\nL:1/4\nQ:1/8=200\nK:F\n|: F/G/ A>B c2 | z ^c =B B | B3 :|\nw: la~la li lo lu le\n");
+        assert_eq!(score.finger_tone, FingerTone::F);
+        assert_eq!(score.meter, Some(Meter { beats: 3, beat_type: 4 }));
+        assert_eq!(score.tempo, Some(100));
+        let jianpu = score.to_jianpu(MEASURES_PER_LINE).unwrap();
+        assert_eq!(jianpu, "1=F 3/4 ♩=100\n1_ 2_ 3. 4_ 5 - | 0 #5 #4 #4 | 4 - - |\nw: la la li lo lu le\n");
+    }
+
+    #[test]
+    fn test_read_abc_details() {
+        // 默认单位时值、连音、和弦、倚音、整小节休止、注释
+        let score = read("X:1\nM:2/4\nK:D\n\"D\"(3ABA {g}[DFA]2 | Z | d,, % 注释\n\nX:2\nK:C\nC");
+        assert_eq!(score.events.iter().map(Event::duration).collect::<Vec<_>>(), vec![8, 8, 8, 24, 0, 96, 0, 12]);
+        assert_eq!(score.events[3], Event::Note { tone: Tone::HA, duration: 24, lyrics: vec![] });
+
+        assert!(read_abc("X:1\nK:C\nC & E", &AbcOptions::default()).is_err());
+        assert!(read_abc("X:1\nT:no key\n", &AbcOptions::default()).is_err());
+        // 连音线不跨小节合并
+        let score = read("X:1\nM:2/4\nL:1/4\nK:C\nc-c | d2- | d2 |");
+        assert_eq!(score.events.iter().map(Event::duration).collect::<Vec<_>>(), vec![96, 0, 96, 0, 96, 0]);
+        // 第二个声部不读
+        let score = read("X:1\nL:1/4\nK:C\nV:1\nc\nV:2\nC\nV:1\nd\n");
+        assert_eq!(score.events.len(), 2);
+    }
+
+    #[test]
+    fn test_write_abc() {
+        let score = Score::parse("1=F 3/4 ♩=90\n1_ #1_ 1 4. 4_ | 0 [1] - | [1] #4 4 |\nw: 一 * 三\n", None).unwrap();
+        let abc = write_abc(&score, "测试");
+        assert_eq!(abc, "X:1\nT:测试\nM:3/4\nL:1/8\nQ:1/4=90\nK:F\nf _g f2 b3 b | z2 f'4 | f'2 =b2 _b2 |]\nw: 一 * 三\n");
+        assert_eq!(read(&abc), score);
+    }
+}
@@ -0,0 +1,7 @@
+//! 识别竖笛录音的音高，转成简谱或给演奏评分，`recorder analyze`的别名
+
+use recorder::cli::{analyze, exit_on_error, from_args_as, AnalyzeOpt};
+
+fn main() {
+    exit_on_error(analyze(&from_args_as::<AnalyzeOpt>("recorder-analyze")));
+}
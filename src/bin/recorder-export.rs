@@ -0,0 +1,7 @@
+//! 简谱导出为其他乐谱格式，`recorder export`的别名
+
+use recorder::cli::{exit_on_error, export, from_args_as, ExportOpt};
+
+fn main() {
+    exit_on_error(export(&from_args_as::<ExportOpt>("recorder-export")));
+}
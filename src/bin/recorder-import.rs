@@ -0,0 +1,82 @@
+//! 其他乐谱格式导入为简谱
+
+use structopt::StructOpt;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use recorder::tone::FingerTone;
+use recorder::musicxml::{read_musicxml, read_mxl, ImportOptions};
+use recorder::abc::{read_abc, AbcOptions};
+use recorder::midi::{read_midi, MidiImportOptions};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "recorder-import", about = "把MusicXML、ABC、MIDI等乐谱格式转换成数字简谱。")]
+struct Opt {
+    /// 简谱的调，例如`C`，`#c`，`Db`；默认使用乐谱的调号
+    #[structopt(long = "tone", short = "t")]
+    tone: Option<FingerTone>,
+
+    /// 读第几个声部（从1开始），乐谱有多个声部时必须指定
+    #[structopt(long = "part")]
+    part: Option<usize>,
+
+    /// 读哪个声音，声部里有和弦或多个声音时必须指定
+    #[structopt(long = "voice")]
+    voice: Option<String>,
+
+    /// MIDI文件只读第几个音轨（从1开始），默认合并所有音轨并取最高音
+    #[structopt(long = "track")]
+    track: Option<usize>,
+
+    /// MIDI文件的量化网格，例如`16`为十六分音符
+    #[structopt(long = "quantize", default_value = "16", possible_values = &["4", "8", "16", "32"])]
+    quantize: u32,
+
+    /// 整体移高几个八度，可以为负数；默认自动移到竖笛能吹的音域
+    #[structopt(long = "octave", allow_hyphen_values = true)]
+    octave: Option<i8>,
+
+    /// 简谱每行的小节数
+    #[structopt(long = "measures-per-line", default_value = "4")]
+    measures_per_line: usize,
+
+    /// 输出文件，默认输出到标准输出
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// 乐谱文件，`.mxl`为压缩的MusicXML，`.abc`为ABC记谱法，`.mid`为MIDI文件
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let opt = Opt::from_args();
+    let finger_tone = opt.tone;
+    let options = ImportOptions {
+        finger_tone,
+        part: opt.part,
+        voice: opt.voice,
+        octave: opt.octave,
+    };
+
+    let data = fs::read(&opt.file)?;
+    let score = match opt.file.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("mxl") => read_musicxml(&read_mxl(&data)?, &options)?,
+        Some(ext) if ext.eq_ignore_ascii_case("abc") => {
+            read_abc(&String::from_utf8(data)?, &AbcOptions { finger_tone, octave: opt.octave })?
+        }
+        Some(ext) if ext.eq_ignore_ascii_case("mid") || ext.eq_ignore_ascii_case("midi") => {
+            let options = MidiImportOptions { finger_tone, track: opt.track, quantize: opt.quantize, octave: opt.octave };
+            read_midi(&data, &options)?
+        }
+        _ => read_musicxml(&String::from_utf8(data)?, &options)?,
+    };
+    let content = score.to_jianpu(opt.measures_per_line)?;
+
+    match opt.output {
+        Some(path) => fs::write(path, content)?,
+        None => print!("{}", content),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,7 @@
+//! 竖笛歌本，`recorder songbook`的别名
+
+use recorder::cli::{exit_on_error, from_args_as, songbook, SongbookOpt};
+
+fn main() {
+    exit_on_error(songbook(&from_args_as::<SongbookOpt>("recorder-songbook")));
+}
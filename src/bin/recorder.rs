@@ -0,0 +1,8 @@
+//! 竖笛相关工具
+
+use structopt::StructOpt;
+use recorder::cli::{exit_on_error, run, Command};
+
+fn main() {
+    exit_on_error(run(&Command::from_args()));
+}
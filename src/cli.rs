@@ -0,0 +1,1255 @@
+//! 命令行工具
+//!
+//! `recorder`命令的各个子命令和它们共用的选项组：输入、输出、乐器、指法和调。
+//! `tone-converter`、`recorder-visualizer`等单独的命令只是对应子命令的别名。
+
+use crate::abc::write_abc;
+use crate::config::{Config, Style, CONFIG_FILE, EXPORT_FORMATS, FINGERINGS, VISUALIZE_FORMATS};
+use crate::finger::Fingering;
+use crate::html::visualize_html;
+use crate::json::visualize_json;
+use crate::key::{read_header, Numbering, NUMBERINGS};
+use crate::lilypond::{write_lilypond, Diagrams};
+use crate::midi::{write_midi, MidiOptions, DEFAULT_TEMPO};
+use crate::musicxml::write_musicxml;
+use crate::notation::parser::{Parser, Token};
+use crate::optimizer::FingeringMode;
+use crate::pdf::{songbook_pdf, Song};
+use crate::pitch::{detect_notes, grade, grade_report, read_wav, to_score, PitchOptions};
+use crate::raster::{visualize_png, PngOptions};
+use crate::score::{Event, Score, DIVISIONS, MEASURES_PER_LINE};
+use crate::svg::{visualize_svg, SvgOptions};
+use crate::synth::{synthesize, write_wav, SynthOptions};
+use crate::terminal::{visualize_terminal, Layout, TerminalOptions};
+use crate::tone::{convert_tone, renumber, visualize_tone_with_mode, FingerTone, Tone};
+use std::env;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::{stdin, stdout, IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+// 选项组的文档注释会覆盖命令的说明，所以用普通注释
+
+// 输入：文件或标准输入
+#[derive(Debug, StructOpt)]
+pub struct InputOpt {
+    /// 输入文件，可以用`*`、`?`、`[abc]`和`**`（任意层目录）通配；不指定时读标准输入
+    pub files: Vec<String>,
+}
+
+impl InputOpt {
+    /// 展开通配符后的所有输入，没有指定文件时为标准输入
+    pub fn inputs(&self) -> Vec<Input> {
+        if self.files.is_empty() {
+            return vec![Input { path: None }];
+        }
+        self.files.iter().flat_map(|pattern| expand_glob(pattern)).map(|path| Input { path: Some(path) }).collect()
+    }
+}
+
+/// 一个输入，`path`为`None`时是标准输入
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    pub path: Option<PathBuf>,
+}
+
+impl Input {
+    pub fn read_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        match &self.path {
+            Some(path) => Ok(fs::read(path)?),
+            None => {
+                let mut input = Vec::new();
+                stdin().read_to_end(&mut input)?;
+                Ok(input)
+            }
+        }
+    }
+
+    pub fn read_string(&self) -> Result<String, Box<dyn Error>> {
+        Ok(String::from_utf8(self.read_bytes()?)?)
+    }
+
+    /// 报错时显示的名字
+    pub fn name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "标准输入".to_owned(),
+        }
+    }
+}
+
+/// 展开通配符，没有通配符或没有匹配的文件时原样返回，留到读文件时报错
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![PathBuf::from(pattern)];
+    }
+
+    let mut paths = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let part = component.as_os_str().to_string_lossy();
+        paths = if let (Component::Normal(_), "**") = (component, &*part) {
+            paths.iter().flat_map(|path| sub_dirs(path)).collect()
+        } else if let (Component::Normal(_), true) = (component, part.contains(['*', '?', '['])) {
+            let pattern = part.chars().collect::<Vec<_>>();
+            paths.iter().flat_map(|path| {
+                let dir = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+                let mut names = fs::read_dir(dir).into_iter().flatten().flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    // 隐藏文件只有模式以`.`开头时才匹配
+                    .filter(|name| (!name.starts_with('.') || pattern[0] == '.') && wildcard_match(&pattern, &name.chars().collect::<Vec<_>>()))
+                    .collect::<Vec<_>>();
+                names.sort();
+                names.into_iter().map(move |name| path.join(name)).collect::<Vec<_>>()
+            }).collect()
+        } else {
+            paths.iter().map(|path| path.join(component)).collect()
+        };
+    }
+
+    paths.retain(|path| path.exists());
+    if paths.is_empty() {
+        return vec![PathBuf::from(pattern)];
+    }
+    paths
+}
+
+/// 目录本身和它下面的所有子目录，跳过隐藏目录
+fn sub_dirs(path: &Path) -> Vec<PathBuf> {
+    let dir = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+    let mut children = fs::read_dir(dir).into_iter().flatten().flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()) && !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| path.join(entry.file_name()))
+        .collect::<Vec<_>>();
+    children.sort();
+    std::iter::once(path.to_owned()).chain(children.iter().flat_map(|child| sub_dirs(child))).collect()
+}
+
+/// 文件名是否匹配通配符：`*`任意个字符，`?`一个字符，`[abc]`、`[a-z]`、`[!abc]`字符集合
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|index| wildcard_match(&pattern[1..], &name[index..])),
+        Some('?') => !name.is_empty() && wildcard_match(&pattern[1..], &name[1..]),
+        Some('[') if pattern.iter().skip(2).any(|&c| c == ']') => {
+            let end = 2 + pattern[2..].iter().position(|&c| c == ']').unwrap_or(0);
+            let (negate, class) = match pattern[1] {
+                '!' | '^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let c = match name.first() {
+                Some(&c) => c,
+                None => return false,
+            };
+            let mut found = false;
+            let mut index = 0;
+            while index < class.len() {
+                if index + 2 < class.len() && class[index + 1] == '-' {
+                    found |= class[index] <= c && c <= class[index + 2];
+                    index += 3;
+                } else {
+                    found |= class[index] == c;
+                    index += 1;
+                }
+            }
+            found != negate && wildcard_match(&pattern[end + 1..], &name[1..])
+        }
+        Some(&c) => name.first() == Some(&c) && wildcard_match(&pattern[1..], &name[1..]),
+    }
+}
+
+// 输出：文件、标准输出或输出目录
+#[derive(Debug, Clone, StructOpt)]
+pub struct OutputOpt {
+    /// 输出文件，默认输出到标准输出
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// 输出目录，每个输入文件各输出一个文件
+    #[structopt(long = "out-dir", parse(from_os_str), conflicts_with = "output")]
+    pub out_dir: Option<PathBuf>,
+
+    /// 输出目录里的文件名：`{stem}`为输入文件名去掉扩展名，`{name}`为输入文件名，`{parent}`为输入文件所在目录名，`{ext}`为输出格式的扩展名
+    #[structopt(long = "name", default_value = "{stem}.{ext}")]
+    pub name: String,
+}
+
+impl OutputOpt {
+    /// 是否输出到终端
+    pub fn is_terminal(&self) -> bool {
+        self.output.is_none() && self.out_dir.is_none() && stdout().is_terminal()
+    }
+
+    /// 一个输入对应的输出文件，`None`为标准输出
+    pub fn path(&self, input: &Input, ext: &str) -> Option<PathBuf> {
+        let dir = match &self.out_dir {
+            Some(dir) => dir,
+            None => return self.output.clone(),
+        };
+        let path = input.path.as_deref().unwrap_or_else(|| Path::new("stdin"));
+        let part = |s: Option<&std::ffi::OsStr>| s.map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let name = self.name
+            .replace("{stem}", &part(path.file_stem()))
+            .replace("{name}", &part(path.file_name()))
+            .replace("{parent}", &part(path.parent().and_then(Path::file_name)))
+            .replace("{ext}", ext);
+        Some(dir.join(name))
+    }
+}
+
+/// 写出一个输入的结果，多页时文件依次命名为`name-1.ext`、`name-2.ext`……
+fn write_pages(path: Option<&Path>, pages: &[Vec<u8>]) -> CliResult {
+    match path {
+        Some(path) => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            for (index, page) in pages.iter().enumerate() {
+                fs::write(page_path(path, index + 1, pages.len()), page)?;
+            }
+        }
+        None => {
+            let mut out = stdout();
+            for page in pages {
+                out.write_all(page)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 第`index`页（从1开始）的文件名
+fn page_path(path: &Path, index: usize, total: usize) -> PathBuf {
+    if total == 1 {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// 二进制的输出格式，不能把多个结果接在一起输出
+const BINARY_EXTENSIONS: [&str; 3] = ["mid", "wav", "png"];
+
+/// 依次处理每个输入。`process`返回输出内容，多页的输出每页一项。
+/// 一个文件出错时在标准错误输出报告，继续处理其他文件，最后返回错误
+pub fn batch<F>(input: &InputOpt, output: &OutputOpt, ext: &str, mut process: F) -> CliResult
+where
+    F: FnMut(&Input) -> Result<Vec<Vec<u8>>, Box<dyn Error>>,
+{
+    let inputs = input.inputs();
+    if inputs.len() == 1 {
+        let pages = process(&inputs[0]).map_err(|e| prefix_error(&inputs[0], &*e))?;
+        return write_pages(output.path(&inputs[0], ext).as_deref(), &pages);
+    }
+    if output.out_dir.is_none() && BINARY_EXTENSIONS.contains(&ext) {
+        return Err("有多个输入文件，请用--out-dir指定输出目录".into());
+    }
+
+    // 没有输出目录时，所有结果依次写到同一个输出
+    let mut joined = Vec::new();
+    let mut failed = 0;
+    for input in &inputs {
+        let result = process(input).and_then(|pages| match output.out_dir {
+            Some(_) => write_pages(output.path(input, ext).as_deref(), &pages),
+            None => {
+                joined.extend(pages.into_iter().flatten());
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("{}", prefix_error(input, &*e));
+            failed += 1;
+        }
+    }
+    if output.out_dir.is_none() {
+        write_pages(output.output.as_deref(), &[joined])?;
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{}个文件中有{}个出错", inputs.len(), failed).into()),
+    }
+}
+
+/// 在错误信息的每一行前面加上文件名
+fn prefix_error(input: &Input, error: &dyn Error) -> String {
+    match input.path {
+        Some(_) => error.to_string().lines().map(|line| format!("{}：{}", input.name(), line)).collect::<Vec<_>>().join("\n"),
+        None => error.to_string(),
+    }
+}
+
+/// 打印错误并以非零状态退出，供各个命令的`main`使用
+pub fn exit_on_error(result: CliResult) {
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+// 调
+#[derive(Debug, StructOpt)]
+pub struct KeyOpt {
+    /// 简谱的调，例如`C`，`#c`，`Db`；不指定时使用配置文件的`key`或简谱里的`1=`调号行
+    #[structopt(long = "tone", short = "t", alias = "from")]
+    pub tone: Option<FingerTone>,
+}
+
+/// 命令行或配置文件指定的调，都没有时取简谱的第一个调号行
+pub fn resolve_key(key: Option<FingerTone>, content: &str) -> Result<FingerTone, Box<dyn Error>> {
+    if let Some(key) = key {
+        return Ok(key);
+    }
+    let header = Parser::from_str(content)?.lines().into_iter().flatten().find_map(|token| match token {
+        Token::Header(header) => Some(header),
+        _ => None,
+    });
+    match header {
+        Some(header) => Ok(read_header(&header).map_err(|_| format!("调号有误：{}", header))?.0),
+        None => Err("请用-t指定调".into()),
+    }
+}
+
+/// 竖笛的种类
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instrument {
+    /// 高音竖笛（C调）
+    Soprano,
+    /// 中音竖笛（F调）
+    Alto,
+    /// 次中音竖笛（C调）
+    Tenor,
+    /// 低音竖笛（F调）
+    Bass,
+}
+
+impl Instrument {
+    /// 命令行和配置文件里的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            Instrument::Soprano => "soprano",
+            Instrument::Alto => "alto",
+            Instrument::Tenor => "tenor",
+            Instrument::Bass => "bass",
+        }
+    }
+
+    /// 吹某个调时，按高音竖笛算的指法音调：F调竖笛的指法比实际的调高五度
+    pub fn finger_tone(self, key: FingerTone) -> FingerTone {
+        match self {
+            Instrument::Soprano | Instrument::Tenor => key,
+            Instrument::Alto | Instrument::Bass => FingerTone::from_fifths(key.fifths() + 1),
+        }
+    }
+
+    /// 吹某个调时，指法音比实际音高几个半音，和`finger_tone`换算出来的简谱音一致
+    pub fn finger_shift(self, key: FingerTone) -> i32 {
+        self.finger_tone(key).do_offset() - key.do_offset()
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "soprano" => Ok(Instrument::Soprano),
+            "alto" => Ok(Instrument::Alto),
+            "tenor" => Ok(Instrument::Tenor),
+            "bass" => Ok(Instrument::Bass),
+            _ => Err(format!("未知的竖笛：{}", s)),
+        }
+    }
+}
+
+impl Display for Instrument {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Instrument::Soprano => "高音竖笛",
+            Instrument::Alto => "中音竖笛",
+            Instrument::Tenor => "次中音竖笛",
+            Instrument::Bass => "低音竖笛",
+        }.fmt(f)
+    }
+}
+
+// 乐器
+#[derive(Debug, StructOpt)]
+pub struct InstrumentOpt {
+    /// 竖笛种类，默认为soprano；F调竖笛（alto、bass）按简谱的调换算指法，简谱的1就是实际的1
+    #[structopt(long = "instrument", possible_values = &["soprano", "alto", "tenor", "bass"])]
+    pub instrument: Option<Instrument>,
+}
+
+// 指法选择方式
+#[derive(Debug, StructOpt)]
+pub struct FingeringOpt {
+    /// 指法：`standard`（默认）总是用英式（巴洛克式）标准指法，`german`用德式竖笛的指法，`auto`自动选择换指最少的指法（含替代指法）
+    #[structopt(long = "fingering", possible_values = &FINGERINGS)]
+    pub fingering: Option<String>,
+
+    /// 同`--fingering auto`
+    #[structopt(long = "auto", hidden = true)]
+    pub auto: bool,
+}
+
+impl FingeringOpt {
+    /// 命令行指定的指法选择方式
+    pub fn fingering(&self) -> Option<String> {
+        if self.auto {
+            return Some("auto".to_owned());
+        }
+        self.fingering.clone()
+    }
+}
+
+/// 配置里的竖笛种类
+fn instrument(config: &Config) -> Instrument {
+    config.instrument.unwrap_or(Instrument::Soprano)
+}
+
+/// 配置里的指法选择方式
+fn fingering_mode(config: &Config) -> FingeringMode {
+    match config.fingering.as_deref() {
+        Some("auto") => FingeringMode::Auto(Default::default()),
+        Some("german") => FingeringMode::German,
+        _ => FingeringMode::Standard,
+    }
+}
+
+// 配置文件
+#[derive(Debug, StructOpt)]
+pub struct ConfigOpt {
+    /// 配置文件，默认从当前目录开始逐级向上查找`recorder.toml`
+    #[structopt(long = "config", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// 显示合并了配置文件和命令行参数之后的配置，然后退出
+    #[structopt(long = "print-config")]
+    pub print_config: bool,
+}
+
+impl ConfigOpt {
+    /// 依次合并默认值、配置文件和命令行参数`cli`。`--print-config`时打印合并结果并返回`None`
+    pub fn merge(&self, cli: &Config) -> Result<Option<Config>, Box<dyn Error>> {
+        let path = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => Config::discover(&env::current_dir()?),
+        };
+        let file = match &path {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        let config = Config::defaults().merge(&file).merge(cli);
+
+        if self.print_config {
+            match &path {
+                Some(path) => println!("# 配置文件：{}", path.display()),
+                None => println!("# 没有找到配置文件{}", CONFIG_FILE),
+            }
+            print!("{}", config);
+            return Ok(None);
+        }
+        Ok(Some(config))
+    }
+}
+
+/// 转换简谱的调
+#[derive(Debug, StructOpt)]
+#[structopt(about = "音调转换器，把数字简谱从一个调转换到另一个调。")]
+pub struct ConvertOpt {
+    #[structopt(flatten)]
+    pub input: InputOpt,
+
+    #[structopt(flatten)]
+    pub output: OutputOpt,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    /// 输出的调，例如`C`，`#c`，`Db`
+    #[structopt(long = "to", required_unless = "numbering")]
+    pub to: Option<FingerTone>,
+
+    /// 按另一种记法重新记谱，音高不变：`do`为`1`是主音，例如`1=Am`；`la`为`1`是大调主音，小调主音记成`6`，例如`1=C Am`
+    #[structopt(long = "numbering", possible_values = &NUMBERINGS)]
+    pub numbering: Option<Numbering>,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+pub fn convert(opt: &ConvertOpt) -> CliResult {
+    let config = match opt.config.merge(&Config { key: opt.key.tone, ..Default::default() })? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    batch(&opt.input, &opt.output, "txt", |input| {
+        let mut content = input.read_string()?;
+        if let Some(to) = opt.to {
+            let from = resolve_key(config.key, &content)?;
+            content = convert_tone(&content, from, to)?;
+        }
+        if let Some(numbering) = opt.numbering {
+            content = renumber(&content, numbering)?;
+        }
+        Ok(vec![(content + "\n").into_bytes()])
+    })
+}
+
+/// 生成指法图
+#[derive(Debug, StructOpt)]
+#[structopt(about = "简单的竖笛指法图生成器。")]
+pub struct VisualizeOpt {
+    #[structopt(flatten)]
+    pub input: InputOpt,
+
+    #[structopt(flatten)]
+    pub output: OutputOpt,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    #[structopt(flatten)]
+    pub instrument: InstrumentOpt,
+
+    #[structopt(flatten)]
+    pub fingering: FingeringOpt,
+
+    /// 输出格式：`text`（默认）为文字指法图，`svg`多页时依次命名为`name-1.svg`、`name-2.svg`……，`html`悬停或点按音符显示指法图，可切换调，`json`为解析后的简谱和每个音符的音调、指法
+    #[structopt(long = "format", short = "f", possible_values = &VISUALIZE_FORMATS)]
+    pub format: Option<String>,
+
+    /// 紧凑的终端输出：按终端宽度折行，彩色显示孔位
+    #[structopt(long = "compact")]
+    pub compact: bool,
+
+    /// 紧凑输出时每个音符占一行，孔位横向排开
+    #[structopt(long = "horizontal")]
+    pub horizontal: bool,
+
+    /// 紧凑输出的宽度，默认为终端宽度
+    #[structopt(long = "width")]
+    pub width: Option<usize>,
+
+    /// 紧凑输出时不使用颜色
+    #[structopt(long = "no-color")]
+    pub no_color: bool,
+
+    /// 紧凑输出时只用ASCII字符：`o`开、`h`半按、`x`按住
+    #[structopt(long = "ascii")]
+    pub ascii: bool,
+
+    /// 紧凑输出时用上下加点的简谱写法代替括号
+    #[structopt(long = "dots")]
+    pub dots: bool,
+
+    /// 监视输入文件，改变后重新生成输出，出错时显示错误并继续监视，按Ctrl-C退出
+    #[structopt(long = "watch")]
+    pub watch: bool,
+
+    /// 同`-f svg -o <svg>`
+    #[structopt(long = "svg", parse(from_os_str), hidden = true)]
+    pub svg: Option<PathBuf>,
+
+    /// 同`-f html -o <html>`
+    #[structopt(long = "html", parse(from_os_str), hidden = true)]
+    pub html: Option<PathBuf>,
+
+    /// 同`-f png -o <png>`
+    #[structopt(long = "png", parse(from_os_str), hidden = true)]
+    pub png: Option<PathBuf>,
+
+    /// PNG分辨率，默认为96
+    #[structopt(long = "dpi")]
+    pub dpi: Option<u32>,
+
+    /// PNG使用透明背景
+    #[structopt(long = "transparent")]
+    pub transparent: bool,
+
+    /// SVG页面或PNG图片宽度（像素，按96dpi计），默认为794
+    #[structopt(long = "page-width")]
+    pub page_width: Option<u32>,
+
+    /// SVG页面高度（像素），默认为1123
+    #[structopt(long = "page-height")]
+    pub page_height: Option<u32>,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+pub fn visualize(opt: &VisualizeOpt) -> CliResult {
+    let cli = Config {
+        instrument: opt.instrument.instrument,
+        fingering: opt.fingering.fingering(),
+        key: opt.key.tone,
+        visualize_format: opt.format.clone(),
+        style: Style {
+            page_width: opt.page_width,
+            page_height: opt.page_height,
+            dpi: opt.dpi,
+            transparent: if opt.transparent { Some(true) } else { None },
+            color: if opt.no_color { Some(false) } else { None },
+            ascii: if opt.ascii { Some(true) } else { None },
+            dots: if opt.dots { Some(true) } else { None },
+        },
+        ..Default::default()
+    };
+    let config = match opt.config.merge(&cli)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let style = &config.style;
+    let mode = fingering_mode(&config);
+    // 旧的`--svg`等选项直接指定了输出文件
+    let legacy = [("png", &opt.png), ("html", &opt.html), ("svg", &opt.svg)].iter()
+        .find_map(|(format, path)| path.as_ref().map(|path| (*format, path.clone())));
+    let (format, output) = match legacy {
+        Some((format, path)) => (format, OutputOpt { output: Some(path), out_dir: None, ..opt.output.clone() }),
+        None => (config.visualize_format.as_deref().unwrap_or("text"), opt.output.clone()),
+    };
+    let ext = if format == "text" { "txt" } else { format };
+
+    let render = || batch(&opt.input, &output, ext, |input| {
+        let content = input.read_string()?;
+        let tone = instrument(&config).finger_tone(resolve_key(config.key, &content)?);
+        Ok(match format {
+            "svg" => {
+                let options = SvgOptions {
+                    page_width: style.page_width.unwrap_or_default(),
+                    page_height: style.page_height.unwrap_or_default(),
+                };
+                visualize_svg(&content, tone, mode, &options)?.into_iter().map(String::into_bytes).collect()
+            }
+            "html" => vec![visualize_html(&content, tone, mode)?.into_bytes()],
+            "json" => vec![format!("{}\n", visualize_json(&content, tone, mode)?).into_bytes()],
+            "png" => {
+                let options = PngOptions {
+                    width: style.page_width.unwrap_or_default(),
+                    dpi: style.dpi.unwrap_or_default(),
+                    transparent: style.transparent.unwrap_or_default(),
+                };
+                vec![visualize_png(&content, tone, mode, &options)?]
+            }
+            _ if opt.compact || opt.horizontal => {
+                let width = opt.width
+                    .or_else(|| terminal_size::terminal_size().map(|(width, _)| width.0 as usize))
+                    .or_else(|| env::var("COLUMNS").ok().and_then(|s| s.parse().ok()))
+                    .unwrap_or(80);
+                let options = TerminalOptions {
+                    width,
+                    color: style.color.unwrap_or_default() && env::var_os("NO_COLOR").is_none() && output.is_terminal(),
+                    ascii: style.ascii.unwrap_or_default(),
+                    dots: style.dots.unwrap_or_default(),
+                    layout: if opt.horizontal { Layout::Horizontal } else { Layout::Vertical },
+                };
+                vec![format!("{}\n", visualize_terminal(&content, tone, mode, &options)?).into_bytes()]
+            }
+            _ => vec![visualize_tone_with_mode(&content, tone, mode)?.into_bytes()],
+        })
+    });
+
+    if opt.watch {
+        // 输出到终端时每次先清屏
+        watch(&opt.input, format == "text" && output.is_terminal(), render)
+    } else {
+        render()
+    }
+}
+
+/// 监视时检查文件的间隔
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 文件改变后这么久没有新的改变才重新生成，连续保存只生成一次
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 输入文件的修改时间和大小
+type Stamp = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+fn stamp(input: &InputOpt) -> Stamp {
+    input.inputs().into_iter().filter_map(|input| input.path).map(|path| {
+        let metadata = fs::metadata(&path).and_then(|metadata| Ok((metadata.modified()?, metadata.len())));
+        (path, metadata.ok())
+    }).collect()
+}
+
+/// 文件改变的防抖
+#[derive(Debug)]
+struct Debounce<S> {
+    /// 上次生成时的状态
+    last: S,
+    /// 还没有稳定下来的新状态和它出现的时间
+    pending: Option<(S, Instant)>,
+}
+
+impl<S: PartialEq> Debounce<S> {
+    fn new(stamp: S) -> Self {
+        Debounce { last: stamp, pending: None }
+    }
+
+    /// 记录`now`时的状态，返回是否应该重新生成
+    fn update(&mut self, stamp: S, now: Instant) -> bool {
+        match self.pending.take() {
+            Some((pending, since)) if pending == stamp => {
+                if now.duration_since(since) >= DEBOUNCE {
+                    self.last = pending;
+                    return true;
+                }
+                self.pending = Some((pending, since));
+            }
+            _ if stamp != self.last => self.pending = Some((stamp, now)),
+            _ => {}
+        }
+        false
+    }
+}
+
+/// 先生成一次，之后每当输入文件改变时重新生成，不会返回
+fn watch(input: &InputOpt, clear: bool, render: impl Fn() -> CliResult) -> CliResult {
+    if input.files.is_empty() {
+        return Err("--watch需要指定输入文件".into());
+    }
+
+    let run = || {
+        if clear {
+            print!("\x1b[2J\x1b[H");
+        }
+        match render() {
+            Ok(()) => eprintln!("已生成，正在监视输入文件……"),
+            Err(e) => eprintln!("{}\n出错了，改好后保存会重新生成……", e),
+        }
+    };
+
+    let mut debounce = Debounce::new(stamp(input));
+    run();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        if debounce.update(stamp(input), Instant::now()) {
+            run();
+        }
+    }
+}
+
+/// 检查简谱
+#[derive(Debug, StructOpt)]
+#[structopt(about = "检查数字简谱：语法错误、竖笛吹不了的音、时值和拍号不符的小节。")]
+pub struct CheckOpt {
+    #[structopt(flatten)]
+    pub input: InputOpt,
+
+    #[structopt(flatten)]
+    pub output: OutputOpt,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    #[structopt(flatten)]
+    pub instrument: InstrumentOpt,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+/// 检查简谱，返回发现的问题，每个问题一行
+pub fn check_jianpu(content: &str, key: FingerTone, instrument: Instrument) -> Vec<String> {
+    let finger_tone = instrument.finger_tone(key);
+    let mut problems = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let tokens = match Parser::from_str(line) {
+            Ok(parser) => parser.lines().into_iter().flatten(),
+            Err(e) => {
+                problems.push(format!("第{}行：{}", index + 1, e));
+                continue;
+            }
+        };
+        for token in tokens {
+            if let Token::Notation(n) = token {
+                let s = n.pitch().to_string();
+                if Tone::from_notation(&n, finger_tone).and_then(Tone::to_finger).is_none() {
+                    problems.push(format!("第{}行：{}超出{}的音域", index + 1, s, instrument));
+                }
+            }
+        }
+    }
+
+    // 只有小节线和音符都没问题时才检查小节，弱起小节和最后一个小节可以不完整
+    if problems.is_empty() {
+        if let Ok(score) = Score::parse(content, Some(key)) {
+            let measures = score.measures();
+            let expected = score.measure_duration();
+            let count = measures.len();
+            for (index, measure) in measures.iter().enumerate() {
+                let duration = measure.iter().map(|event| event.duration()).sum::<u32>();
+                let partial = (index == 0 || index + 1 == count) && duration < expected;
+                if score.events.contains(&Event::Bar) && duration != expected && !partial {
+                    problems.push(format!(
+                        "第{}小节：长{}拍，应为{}拍（按四分音符计）",
+                        index + 1,
+                        duration as f64 / DIVISIONS as f64,
+                        expected as f64 / DIVISIONS as f64,
+                    ));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// 发现问题时报错，错误信息每行一个问题
+pub fn check(opt: &CheckOpt) -> CliResult {
+    let cli = Config { instrument: opt.instrument.instrument, key: opt.key.tone, ..Default::default() };
+    let config = match opt.config.merge(&cli)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    batch(&opt.input, &opt.output, "txt", |input| {
+        let content = input.read_string()?;
+        let key = resolve_key(config.key, &content)?;
+        let problems = check_jianpu(&content, key, instrument(&config));
+        if !problems.is_empty() {
+            return Err(problems.join("\n").into());
+        }
+        let prefix = input.path.as_ref().map(|_| format!("{}：", input.name())).unwrap_or_default();
+        Ok(vec![format!("{}没有发现问题\n", prefix).into_bytes()])
+    })
+}
+
+/// 识别录音
+#[derive(Debug, StructOpt)]
+#[structopt(about = "识别竖笛录音（WAV）的音高，转成数字简谱或和参考简谱对比评分。")]
+pub struct AnalyzeOpt {
+    #[structopt(flatten)]
+    pub input: InputOpt,
+
+    #[structopt(flatten)]
+    pub output: OutputOpt,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    /// 速度（每分钟四分音符数），用来把时间换算成简谱时值
+    #[structopt(long = "tempo")]
+    pub tempo: Option<u32>,
+
+    /// A4的频率，默认为440
+    #[structopt(long = "a4")]
+    pub a4: Option<f64>,
+
+    /// 参考简谱文件，指定时输出评分报告；这时调默认使用参考简谱的`1=`调号行
+    #[structopt(long = "grade", parse(from_os_str))]
+    pub grade: Option<PathBuf>,
+
+    /// 评分时音准偏差超过多少音分算偏高或偏低
+    #[structopt(long = "tolerance", default_value = "25")]
+    pub tolerance: f64,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+pub fn analyze(opt: &AnalyzeOpt) -> CliResult {
+    let config = match opt.config.merge(&Config { key: opt.key.tone, a4: opt.a4, ..Default::default() })? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let options = PitchOptions { a4: config.a4.unwrap_or(440.0), ..Default::default() };
+    let reference = match &opt.grade {
+        Some(path) => Some(Score::parse(&fs::read_to_string(path)?, config.key)?),
+        None => None,
+    };
+    let tone = config.key;
+
+    batch(&opt.input, &opt.output, "txt", |input| {
+        let (samples, sample_rate) = read_wav(&input.read_bytes()?)?;
+        let notes = detect_notes(&samples, sample_rate, &options);
+        let content = match &reference {
+            Some(reference) => grade_report(&grade(&notes, reference), reference.finger_tone, opt.tolerance),
+            None => {
+                let tone = tone.ok_or("请用-t指定简谱的调")?;
+                to_score(&notes, tone, opt.tempo.unwrap_or(DEFAULT_TEMPO)).to_jianpu(MEASURES_PER_LINE)?
+            }
+        };
+        Ok(vec![content.into_bytes()])
+    })
+}
+
+/// 导出乐谱
+#[derive(Debug, StructOpt)]
+#[structopt(about = "把数字简谱导出为MusicXML、ABC、LilyPond、MIDI等乐谱格式，或合成为WAV音频。")]
+pub struct ExportOpt {
+    #[structopt(flatten)]
+    pub input: InputOpt,
+
+    #[structopt(flatten)]
+    pub output: OutputOpt,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    #[structopt(flatten)]
+    pub instrument: InstrumentOpt,
+
+    #[structopt(flatten)]
+    pub fingering: FingeringOpt,
+
+    /// 输出格式，默认为musicxml
+    #[structopt(long = "format", short = "f", possible_values = &EXPORT_FORMATS)]
+    pub format: Option<String>,
+
+    /// LilyPond输出时在音符上方加竖笛指法图
+    #[structopt(long = "diagrams")]
+    pub diagrams: bool,
+
+    /// 速度（每分钟四分音符数），覆盖调号行的速度；都没有时MIDI和WAV按120
+    #[structopt(long = "tempo")]
+    pub tempo: Option<u32>,
+
+    /// WAV输出时A4的频率，默认为440
+    #[structopt(long = "a4")]
+    pub a4: Option<f64>,
+
+    /// WAV输出的采样率
+    #[structopt(long = "sample-rate", default_value = "44100")]
+    pub sample_rate: u32,
+
+    /// MIDI输出时，简谱没有写节奏时每个音符的时值，例如`4`为四分音符、`8`为八分音符
+    #[structopt(long = "note-length", default_value = "4", possible_values = &["1", "2", "4", "8", "16"])]
+    pub note_length: u32,
+
+    /// 标题，默认为输入文件名
+    #[structopt(long = "title", default_value = "")]
+    pub title: String,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+pub fn export(opt: &ExportOpt) -> CliResult {
+    let cli = Config {
+        instrument: opt.instrument.instrument,
+        fingering: opt.fingering.fingering(),
+        key: opt.key.tone,
+        a4: opt.a4,
+        export_format: opt.format.clone(),
+        ..Default::default()
+    };
+    let config = match opt.config.merge(&cli)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let format = config.export_format.as_deref().unwrap_or("musicxml");
+    let ext = match format {
+        "lilypond" => "ly",
+        "midi" => "mid",
+        format => format,
+    };
+    batch(&opt.input, &opt.output, ext, |input| {
+        let mut score = Score::parse(&input.read_string()?, config.key)?;
+        if opt.tempo.is_some() {
+            score.tempo = opt.tempo;
+        }
+        // 没有指定标题时用文件名
+        let title = match (&input.path, opt.title.is_empty()) {
+            (Some(path), true) => path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+            _ => opt.title.clone(),
+        };
+        let content = match format {
+            "musicxml" => write_musicxml(&score, &title).into_bytes(),
+            "abc" => write_abc(&score, &title).into_bytes(),
+            "lilypond" => {
+                let diagrams = if opt.diagrams {
+                    Some(Diagrams { mode: fingering_mode(&config), shift: instrument(&config).finger_shift(score.finger_tone) })
+                } else {
+                    None
+                };
+                write_lilypond(&score, &title, diagrams)?.into_bytes()
+            }
+            "midi" => {
+                let options = MidiOptions { tempo: DEFAULT_TEMPO, note_length: DIVISIONS * 4 / opt.note_length };
+                write_midi(&[score], &options)
+            }
+            "wav" => {
+                let options = SynthOptions { sample_rate: opt.sample_rate, a4: config.a4.unwrap_or(440.0), ..Default::default() };
+                write_wav(&synthesize(&score, &options), opt.sample_rate)
+            }
+            _ => unreachable!(),
+        };
+        Ok(vec![content])
+    })
+}
+
+/// 排歌本
+#[derive(Debug, StructOpt)]
+#[structopt(about = "把多首数字简谱排成带指法图、目录和页码的PDF歌本。")]
+pub struct SongbookOpt {
+    /// 简谱文件，可以用通配符，标题取文件名；可用`文件@调`单独指定调，例如`孟姜女.txt@#a`
+    #[structopt(required = true)]
+    pub files: Vec<String>,
+
+    /// 输出的PDF文件
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    pub output: PathBuf,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    #[structopt(flatten)]
+    pub instrument: InstrumentOpt,
+
+    #[structopt(flatten)]
+    pub fingering: FingeringOpt,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+pub fn songbook(opt: &SongbookOpt) -> CliResult {
+    let cli = Config {
+        instrument: opt.instrument.instrument,
+        fingering: opt.fingering.fingering(),
+        key: opt.key.tone,
+        ..Default::default()
+    };
+    let config = match opt.config.merge(&cli)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+
+    let mut songs = Vec::new();
+    for file in &opt.files {
+        // `@`后面的调优先于-t和配置文件，都没有时取简谱的调号行
+        let (pattern, key) = match file.rfind('@') {
+            Some(index) => (&file[..index], Some(file[index + 1..].parse::<FingerTone>().map_err(|e| format!("{}：{}", file, e))?)),
+            None => (file.as_str(), config.key),
+        };
+        for path in expand_glob(pattern) {
+            let input = Input { path: Some(path) };
+            let content = input.read_string().map_err(|e| prefix_error(&input, &*e))?;
+            let key = resolve_key(key, &content).map_err(|e| prefix_error(&input, &*e))?;
+            let finger_tone = instrument(&config).finger_tone(key);
+            let title = input.path.as_deref().and_then(Path::file_stem).map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            songs.push(Song { title, key, finger_tone, content });
+        }
+    }
+
+    let pdf = songbook_pdf(&songs, fingering_mode(&config))?;
+    write_pages(Some(&opt.output), &[pdf])
+}
+
+/// 查指法
+#[derive(Debug, StructOpt)]
+#[structopt(about = "查询音符的指法，包括替代指法。")]
+pub struct FingeringTableOpt {
+    /// 输出文件，默认输出到标准输出
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    pub key: KeyOpt,
+
+    #[structopt(flatten)]
+    pub instrument: InstrumentOpt,
+
+    #[structopt(flatten)]
+    pub fingering: FingeringOpt,
+
+    /// 简谱音符，例如`1`、`#4`、`(5)`、`[2]`
+    #[structopt(required = true)]
+    pub notes: Vec<String>,
+
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+}
+
+/// 把几个指法图横向排开，每个指法图上方是标题
+fn side_by_side(items: &[(String, Fingering)]) -> String {
+    let columns = items.iter()
+        .map(|(title, fingering)| std::iter::once(title.clone()).chain(fingering.to_string().lines().map(str::to_owned)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let width = items.iter().map(|(title, _)| title.chars().count()).max().unwrap_or(0).max(1) + 2;
+    (0..columns[0].len()).map(|row| {
+        let line = columns.iter()
+            .map(|column| format!("{:width$}", column[row], width = width))
+            .collect::<String>();
+        format!("{}\n", line.trim_end())
+    }).collect()
+}
+
+pub fn fingering_table(opt: &FingeringTableOpt) -> CliResult {
+    let cli = Config {
+        instrument: opt.instrument.instrument,
+        fingering: opt.fingering.fingering(),
+        key: opt.key.tone,
+        ..Default::default()
+    };
+    let config = match opt.config.merge(&cli)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let instrument = instrument(&config);
+    let finger_tone = instrument.finger_tone(config.key.ok_or("请用-t指定调")?);
+    let mut items = Vec::new();
+    for note in &opt.notes {
+        let tone = Tone::notation_to_tone(note, finger_tone).ok_or_else(|| format!("无法识别的音符：{}", note))?;
+        let mut fingers = tone.to_fingers();
+        // 德式竖笛先列德式指法
+        if let (FingeringMode::German, Some(german)) = (fingering_mode(&config), tone.to_german_finger()) {
+            fingers.retain(|&fingering| fingering != german);
+            fingers.insert(0, german);
+        }
+        if fingers.is_empty() {
+            return Err(format!("{}超出{}的音域", note, instrument).into());
+        }
+        for (index, fingering) in fingers.into_iter().enumerate() {
+            let title = if index == 0 { note.clone() } else { format!("{}'", note) };
+            items.push((title, fingering));
+        }
+    }
+    write_pages(opt.output.as_deref(), &[side_by_side(&items).into_bytes()])
+}
+
+/// `recorder`的子命令
+#[derive(Debug, StructOpt)]
+#[structopt(name = "recorder", about = "竖笛相关工具。")]
+pub enum Command {
+    #[structopt(name = "convert")]
+    Convert(ConvertOpt),
+    #[structopt(name = "visualize")]
+    Visualize(VisualizeOpt),
+    #[structopt(name = "check")]
+    Check(CheckOpt),
+    #[structopt(name = "analyze")]
+    Analyze(AnalyzeOpt),
+    #[structopt(name = "export")]
+    Export(ExportOpt),
+    #[structopt(name = "fingering")]
+    Fingering(FingeringTableOpt),
+    #[structopt(name = "songbook")]
+    Songbook(SongbookOpt),
+}
+
+pub fn run(command: &Command) -> CliResult {
+    match command {
+        Command::Convert(opt) => convert(opt),
+        Command::Visualize(opt) => visualize(opt),
+        Command::Check(opt) => check(opt),
+        Command::Analyze(opt) => analyze(opt),
+        Command::Export(opt) => export(opt),
+        Command::Fingering(opt) => fingering_table(opt),
+        Command::Songbook(opt) => songbook(opt),
+    }
+}
+
+/// 按别名解析命令行参数，供`tone-converter`等旧命令使用
+pub fn from_args_as<T: StructOpt>(name: &str) -> T {
+    T::from_clap(&T::clap().name(name).get_matches())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_instrument() {
+        assert_eq!(Instrument::Soprano.finger_tone(FingerTone::F), FingerTone::F);
+        assert_eq!(Instrument::Alto.finger_tone(FingerTone::F), FingerTone::C);
+        assert_eq!(Instrument::Bass.finger_tone(FingerTone::C), FingerTone::G);
+        assert_eq!(Instrument::Alto.finger_shift(FingerTone::F), -5);
+        assert_eq!(Instrument::Alto.finger_shift(FingerTone::B), 7);
+        assert_eq!(Instrument::Tenor.finger_shift(FingerTone::F), 0);
+        assert_eq!("alto".parse(), Ok(Instrument::Alto));
+        assert!("piccolo".parse::<Instrument>().is_err());
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        let matches = |pattern: &str, name: &str| wildcard_match(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>());
+        assert!(matches("*.txt", "孟姜女.txt"));
+        assert!(!matches("*.txt", "孟姜女.mid"));
+        assert!(matches("告白之夜?.txt", "告白之夜2.txt"));
+        assert!(!matches("告白之夜?.txt", "告白之夜.txt"));
+        assert!(matches("[a-c]*", "b.txt"));
+        assert!(!matches("[!a-c]*", "b.txt"));
+        assert!(matches("[[]x", "[x"));
+        assert!(matches("a[", "a["));
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = env::temp_dir().join(format!("recorder-glob-{}", process::id()));
+        fs::create_dir_all(dir.join("sub/deep")).unwrap();
+        for name in ["a.txt", "b.txt", "c.abc", ".hidden.txt", "sub/d.txt", "sub/deep/e.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let glob = |pattern: &str| expand_glob(&dir.join(pattern).to_string_lossy());
+        assert_eq!(glob("*.txt"), vec![dir.join("a.txt"), dir.join("b.txt")]);
+        assert_eq!(glob("**/*.txt"), vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("sub/d.txt"), dir.join("sub/deep/e.txt")]);
+        assert_eq!(glob("*/d.txt"), vec![dir.join("sub/d.txt")]);
+        // 没有匹配时原样返回
+        assert_eq!(glob("*.mid"), vec![dir.join("*.mid")]);
+        assert_eq!(expand_glob("不存在.txt"), vec![PathBuf::from("不存在.txt")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_path() {
+        let input = Input { path: Some(PathBuf::from("songs/民歌/孟姜女.txt")) };
+        let output = OutputOpt { output: Some(PathBuf::from("a.mid")), out_dir: None, name: "{stem}.{ext}".to_owned() };
+        assert_eq!(output.path(&input, "mid"), Some(PathBuf::from("a.mid")));
+        let output = OutputOpt { output: None, out_dir: Some(PathBuf::from("out")), name: "{parent}-{name}.{ext}".to_owned() };
+        assert_eq!(output.path(&input, "mid"), Some(PathBuf::from("out/民歌-孟姜女.txt.mid")));
+        assert_eq!(output.path(&Input { path: None }, "txt"), Some(PathBuf::from("out/-stdin.txt")));
+        assert_eq!(page_path(Path::new("out/a.svg"), 2, 3), PathBuf::from("out/a-2.svg"));
+    }
+
+    #[test]
+    fn test_debounce() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut debounce = Debounce::new(1);
+        assert!(!debounce.update(1, at(100)));
+        // 连续保存，最后一次保存之后稳定了才重新生成
+        assert!(!debounce.update(2, at(200)));
+        assert!(!debounce.update(3, at(400)));
+        assert!(!debounce.update(3, at(600)));
+        assert!(debounce.update(3, at(700)));
+        assert!(!debounce.update(3, at(2000)));
+        // 改了又改回去不用重新生成
+        assert!(!debounce.update(4, at(2100)));
+        assert!(!debounce.update(3, at(2200)));
+        assert!(!debounce.update(3, at(3000)));
+    }
+
+    #[test]
+    fn test_check_jianpu() {
+        assert!(check_jianpu("1=C 4/4\n1 2 3 4 | 5 - - - |", FingerTone::C, Instrument::Soprano).is_empty());
+        // 弱起小节不算错
+        assert!(check_jianpu("5 | 1 2 3 4 | 5 - - - |", FingerTone::C, Instrument::Soprano).is_empty());
+        assert_eq!(check_jianpu("1 2 3 4 | 5 - | 1 2 3 4 |", FingerTone::C, Instrument::Soprano), vec![
+            "第2小节：长2拍，应为4拍（按四分音符计）".to_owned(),
+        ]);
+        assert_eq!(check_jianpu("1 2\n((1)) x", FingerTone::C, Instrument::Soprano), vec![
+            "第2行：未知字符: 'x'".to_owned(),
+        ]);
+        assert_eq!(check_jianpu("(1) 1", FingerTone::C, Instrument::Soprano), vec![
+            "第1行：(1)超出高音竖笛的音域".to_owned(),
+        ]);
+        // 中音竖笛吹F调的1用全按的指法
+        assert!(check_jianpu("1 [[2]]", FingerTone::F, Instrument::Alto).is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+//! 项目配置文件`recorder.toml`
+//!
+//! 从当前目录开始逐级向上查找`recorder.toml`，命令行参数覆盖配置文件。
+//! 只支持TOML的一个子集：`[表]`、`键 = 值`和`#`注释，值可以是字符串、整数、小数和布尔值。
+
+use crate::cli::Instrument;
+use crate::tone::FingerTone;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 配置文件名
+pub const CONFIG_FILE: &str = "recorder.toml";
+
+/// 配置文件错误
+#[derive(Debug, PartialEq)]
+pub struct ConfigError(String);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// 配置文件里的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+/// 解析TOML，返回`(行号, 表名.键, 值)`，顶层的键没有表名
+pub fn parse_toml(text: &str) -> Result<Vec<(usize, String, Value)>, ConfigError> {
+    let mut table = String::new();
+    let mut items = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: &str| ConfigError(format!("第{}行：{}", number, message));
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name.strip_suffix(']').ok_or_else(|| error("表头缺少`]`"))?.trim();
+            if name.is_empty() || !name.chars().all(is_bare_key_char) {
+                return Err(error("表名有误"));
+            }
+            table = name.to_owned();
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| error("应为`键 = 值`"))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(is_bare_key_char) {
+            return Err(error("键名有误"));
+        }
+        let value = parse_value(value.trim()).ok_or_else(|| error(&format!("无法识别的值：{}", value.trim())))?;
+        let key = if table.is_empty() { key.to_owned() } else { format!("{}.{}", table, key) };
+        items.push((number, key, value));
+    }
+
+    Ok(items)
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// 去掉字符串外面的`#`注释
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if let Some(s) = s.strip_prefix('"') {
+        let s = s.strip_suffix('"')?;
+        let mut value = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                value.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                });
+            } else if c == '"' {
+                return None;
+            } else {
+                value.push(c);
+            }
+        }
+        return Some(Value::String(value));
+    }
+    if let Some(s) = s.strip_prefix('\'') {
+        return Some(Value::String(s.strip_suffix('\'')?.to_owned()));
+    }
+    match s {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        _ => {}
+    }
+    let number = s.replace('_', "");
+    if let Ok(integer) = number.parse() {
+        return Some(Value::Integer(integer));
+    }
+    match number.parse() {
+        Ok(float) if s.chars().next().is_some_and(|c| c.is_ascii_digit() || c == '-' || c == '+') => Some(Value::Float(float)),
+        _ => None,
+    }
+}
+
+/// 配置，每一项都可以不写
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// 竖笛种类
+    pub instrument: Option<Instrument>,
+    /// 指法：`standard`、`german`或`auto`
+    pub fingering: Option<String>,
+    /// 简谱的调
+    pub key: Option<FingerTone>,
+    /// A4的频率
+    pub a4: Option<f64>,
+    /// `recorder export`的输出格式
+    pub export_format: Option<String>,
+    /// `recorder visualize`的输出格式
+    pub visualize_format: Option<String>,
+    pub style: Style,
+}
+
+/// 指法图的样式，对应`[style]`表
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    /// SVG页面或PNG图片宽度（像素）
+    pub page_width: Option<u32>,
+    /// SVG页面高度（像素）
+    pub page_height: Option<u32>,
+    /// PNG分辨率
+    pub dpi: Option<u32>,
+    /// PNG透明背景
+    pub transparent: Option<bool>,
+    /// 紧凑输出使用颜色
+    pub color: Option<bool>,
+    /// 紧凑输出只用ASCII字符
+    pub ascii: Option<bool>,
+    /// 紧凑输出用加点的简谱写法
+    pub dots: Option<bool>,
+}
+
+/// 输出格式的可选值
+pub const EXPORT_FORMATS: [&str; 5] = ["musicxml", "abc", "lilypond", "midi", "wav"];
+pub const VISUALIZE_FORMATS: [&str; 5] = ["text", "svg", "html", "png", "json"];
+pub const FINGERINGS: [&str; 3] = ["standard", "german", "auto"];
+
+impl Config {
+    /// 解析配置文件的内容
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+
+        for (number, key, value) in parse_toml(text)? {
+            let error = |message: String| ConfigError(format!("第{}行：{}", number, message));
+            let string = |choices: &[&str]| match &value {
+                Value::String(s) if choices.is_empty() || choices.contains(&s.as_str()) => Ok(s.clone()),
+                Value::String(s) => Err(error(format!("{}只能是{}，不能是{}", key, choices.join("、"), s))),
+                _ => Err(error(format!("{}应为字符串", key))),
+            };
+            let integer = || match value {
+                Value::Integer(n) if n > 0 && n <= u32::MAX as i64 => Ok(n as u32),
+                _ => Err(error(format!("{}应为正整数", key))),
+            };
+            let boolean = || match value {
+                Value::Boolean(b) => Ok(b),
+                _ => Err(error(format!("{}应为true或false", key))),
+            };
+
+            match key.as_str() {
+                "instrument" => config.instrument = Some(string(&[])?.parse().map_err(error)?),
+                "fingering" => config.fingering = Some(string(&FINGERINGS)?),
+                "key" => {
+                    let s = string(&[])?;
+                    config.key = Some(s.parse().map_err(|_| error(format!("调有误：{}", s)))?);
+                }
+                "a4" => config.a4 = Some(match value {
+                    Value::Integer(n) if n > 0 => n as f64,
+                    Value::Float(f) if f > 0.0 => f,
+                    _ => return Err(error("a4应为正数".to_owned())),
+                }),
+                "export.format" => config.export_format = Some(string(&EXPORT_FORMATS)?),
+                "visualize.format" => config.visualize_format = Some(string(&VISUALIZE_FORMATS)?),
+                "style.page-width" => config.style.page_width = Some(integer()?),
+                "style.page-height" => config.style.page_height = Some(integer()?),
+                "style.dpi" => config.style.dpi = Some(integer()?),
+                "style.transparent" => config.style.transparent = Some(boolean()?),
+                "style.color" => config.style.color = Some(boolean()?),
+                "style.ascii" => config.style.ascii = Some(boolean()?),
+                "style.dots" => config.style.dots = Some(boolean()?),
+                _ => return Err(error(format!("未知的配置项：{}", key))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// 读取配置文件，错误信息带上文件名
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError(format!("{}：{}", path.display(), e)))?;
+        Config::parse(&text).map_err(|e| ConfigError(format!("{}：{}", path.display(), e)))
+    }
+
+    /// 从`dir`开始逐级向上查找配置文件
+    pub fn discover(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|path| path.is_file())
+    }
+
+    /// 各项的默认值
+    pub fn defaults() -> Config {
+        Config {
+            instrument: Some(Instrument::Soprano),
+            fingering: Some("standard".to_owned()),
+            key: None,
+            a4: Some(440.0),
+            export_format: Some("musicxml".to_owned()),
+            visualize_format: Some("text".to_owned()),
+            style: Style {
+                page_width: Some(794),
+                page_height: Some(1123),
+                dpi: Some(96),
+                transparent: Some(false),
+                color: Some(true),
+                ascii: Some(false),
+                dots: Some(false),
+            },
+        }
+    }
+
+    /// 用`other`里写了的项覆盖这个配置
+    pub fn merge(&self, other: &Config) -> Config {
+        Config {
+            instrument: other.instrument.or(self.instrument),
+            fingering: other.fingering.clone().or_else(|| self.fingering.clone()),
+            key: other.key.or(self.key),
+            a4: other.a4.or(self.a4),
+            export_format: other.export_format.clone().or_else(|| self.export_format.clone()),
+            visualize_format: other.visualize_format.clone().or_else(|| self.visualize_format.clone()),
+            style: Style {
+                page_width: other.style.page_width.or(self.style.page_width),
+                page_height: other.style.page_height.or(self.style.page_height),
+                dpi: other.style.dpi.or(self.style.dpi),
+                transparent: other.style.transparent.or(self.style.transparent),
+                color: other.style.color.or(self.style.color),
+                ascii: other.style.ascii.or(self.style.ascii),
+                dots: other.style.dots.or(self.style.dots),
+            },
+        }
+    }
+}
+
+/// 写成配置文件的格式，没有写的项省略
+impl Display for Config {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        fn item<T: Display>(f: &mut Formatter, key: &str, value: &Option<T>, quoted: bool) -> FmtResult {
+            match value {
+                Some(value) if quoted => writeln!(f, "{} = \"{}\"", key, value),
+                Some(value) => writeln!(f, "{} = {}", key, value),
+                None => Ok(()),
+            }
+        }
+
+        let instrument = self.instrument.map(|instrument| instrument.name());
+        item(f, "instrument", &instrument, true)?;
+        item(f, "fingering", &self.fingering, true)?;
+        item(f, "key", &self.key.map(|key| key.to_string().to_lowercase()), true)?;
+        item(f, "a4", &self.a4, false)?;
+        writeln!(f, "\n[export]")?;
+        item(f, "format", &self.export_format, true)?;
+        writeln!(f, "\n[visualize]")?;
+        item(f, "format", &self.visualize_format, true)?;
+        writeln!(f, "\n[style]")?;
+        item(f, "page-width", &self.style.page_width, false)?;
+        item(f, "page-height", &self.style.page_height, false)?;
+        item(f, "dpi", &self.style.dpi, false)?;
+        item(f, "transparent", &self.style.transparent, false)?;
+        item(f, "color", &self.style.color, false)?;
+        item(f, "ascii", &self.style.ascii, false)?;
+        item(f, "dots", &self.style.dots, false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml() {
+        let items = parse_toml("# 注释\na = \"x # y\" # 注释\n\n[style]\nb-c = 1_000\nd = -1.5\ne = true\nf = 'raw\\n'").unwrap();
+        assert_eq!(items, vec![
+            (2, "a".to_owned(), Value::String("x # y".to_owned())),
+            (5, "style.b-c".to_owned(), Value::Integer(1000)),
+            (6, "style.d".to_owned(), Value::Float(-1.5)),
+            (7, "style.e".to_owned(), Value::Boolean(true)),
+            (8, "style.f".to_owned(), Value::String("raw\\n".to_owned())),
+        ]);
+        assert_eq!(parse_toml("a = \"x\\\"y\"").unwrap()[0].2, Value::String("x\"y".to_owned()));
+        assert_eq!(parse_toml("a\n").unwrap_err().to_string(), "第1行：应为`键 = 值`");
+        assert_eq!(parse_toml("[style\n").unwrap_err().to_string(), "第1行：表头缺少`]`");
+        assert_eq!(parse_toml("a = b").unwrap_err().to_string(), "第1行：无法识别的值：b");
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse("instrument = \"alto\"\nkey = \"#A\"\na4 = 442\n[export]\nformat = \"midi\"\n[style]\ndpi = 192\ncolor = false").unwrap();
+        assert_eq!(config.instrument, Some(Instrument::Alto));
+        assert_eq!(config.key, Some(FingerTone::SA));
+        assert_eq!(config.a4, Some(442.0));
+        assert_eq!(config.export_format.as_deref(), Some("midi"));
+        assert_eq!(config.style.dpi, Some(192));
+        assert_eq!(config.style.color, Some(false));
+        assert_eq!(config.fingering, None);
+
+        assert_eq!(Config::parse("dpi = 96").unwrap_err().to_string(), "第1行：未知的配置项：dpi");
+        assert_eq!(Config::parse("[style]\ndpi = \"96\"").unwrap_err().to_string(), "第2行：style.dpi应为正整数");
+        assert_eq!(Config::parse("[export]\nformat = \"pdf\"").unwrap_err().to_string(), "第2行：export.format只能是musicxml、abc、lilypond、midi、wav，不能是pdf");
+        assert!(Config::parse("instrument = \"piccolo\"").is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let file = Config::parse("fingering = \"auto\"\na4 = 442\n[style]\ndpi = 192").unwrap();
+        let cli = Config { a4: Some(415.0), ..Default::default() };
+        let merged = Config::defaults().merge(&file).merge(&cli);
+        assert_eq!(merged.fingering.as_deref(), Some("auto"));
+        assert_eq!(merged.a4, Some(415.0));
+        assert_eq!(merged.style.dpi, Some(192));
+        assert_eq!(merged.style.page_width, Some(794));
+        // 输出的格式能再读回来
+        assert_eq!(Config::parse(&merged.to_string()).unwrap(), merged);
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = std::env::temp_dir().join(format!("recorder-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("songs/民歌")).unwrap();
+        fs::write(dir.join(CONFIG_FILE), "key = \"d\"").unwrap();
+        assert_eq!(Config::discover(&dir.join("songs/民歌")), Some(dir.join(CONFIG_FILE)));
+        assert_eq!(Config::load(&dir.join(CONFIG_FILE)).unwrap().key, Some(FingerTone::D));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+//! 指法图的排版和几何形状，供SVG、PDF等图形输出共用
+//!
+//! 坐标单位为像素（96dpi），原点在左上角，y轴向下。
+
+use crate::finger::{Fingering, Hole};
+use crate::notation::parser::{Rest, Token};
+use crate::notation::Notation;
+use crate::tone::FingeringLine;
+
+/// 音符列宽
+pub const NOTE_WIDTH: u32 = 32;
+/// 空格宽度
+pub const SPACE_WIDTH: u32 = 16;
+/// 文字字宽
+pub const CHAR_WIDTH: u32 = 16;
+/// 指法图下方音符文字的基线位置
+pub const NOTE_BASELINE: u32 = 152;
+/// 带指法图的一行高度
+pub const ROW_HEIGHT: u32 = 172;
+/// 只有文字的一行高度
+pub const TEXT_ROW_HEIGHT: u32 = 32;
+
+/// 音符数字的字号
+pub const NOTE_SIZE: f32 = 16.0;
+
+/// 一行带指法的简谱的一部分
+pub type Row<'a> = &'a [(Token, Option<Fingering>)];
+
+/// 指法图的基本形状
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// 管身，两端为半圆的竖直圆角矩形
+    Body { x: f32, y: f32, width: f32, height: f32 },
+    /// 分隔线
+    Separator { x1: f32, x2: f32, y: f32 },
+    /// 孔，半按时涂黑左半边
+    Hole { cx: f32, cy: f32, r: f32, hole: Hole },
+}
+
+/// 指法图的形状，`cx`为指法图中线的横坐标：
+/// 拇指孔在背面单独画在最上面，第六、七孔为双孔
+pub fn fingering_shapes(fingering: &Fingering, cx: f32) -> Vec<Shape> {
+    let holes = fingering.holes();
+    let mut shapes = vec![
+        Shape::Body { x: cx - 8.0, y: 19.0, width: 16.0, height: 106.0 },
+        Shape::Hole { cx, cy: 9.0, r: 5.0, hole: holes[0] },
+        Shape::Separator { x1: cx - 10.0, x2: cx + 10.0, y: 19.0 },
+    ];
+
+    for (index, &cy) in [28.0, 42.0, 56.0].iter().enumerate() {
+        shapes.push(Shape::Hole { cx, cy, r: 5.0, hole: holes[index + 1] });
+    }
+    shapes.push(Shape::Separator { x1: cx - 10.0, x2: cx + 10.0, y: 65.0 });
+    for (index, &cy) in [74.0, 88.0].iter().enumerate() {
+        shapes.push(Shape::Hole { cx, cy, r: 5.0, hole: holes[index + 4] });
+    }
+
+    // 双孔：半按表示只按住大孔
+    for (index, &cy) in [102.0, 116.0].iter().enumerate() {
+        let (big, small) = match holes[index + 6] {
+            Hole::Open => (Hole::Open, Hole::Open),
+            Hole::Half => (Hole::Close, Hole::Open),
+            Hole::Close => (Hole::Close, Hole::Close),
+        };
+        shapes.push(Shape::Hole { cx: cx - 3.5, cy, r: 3.5, hole: big });
+        shapes.push(Shape::Hole { cx: cx + 4.0, cy, r: 2.5, hole: small });
+    }
+
+    shapes
+}
+
+/// 简谱音符的记号
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mark {
+    /// 居中的文字，`y`为基线
+    Text { cx: f32, y: f32, size: f32, text: String },
+    /// 八度点、附点
+    Dot { cx: f32, cy: f32, r: f32 },
+    /// 减时线、增时线
+    Line { x1: f32, x2: f32, y: f32 },
+    /// 小节线
+    Bar { x: f32, y1: f32, y2: f32 },
+}
+
+/// 简谱音符的排版：数字居中，升号在左上，高音点在上，减时线和低音点在下，附点在右。
+/// `cx`为数字中线的横坐标，`baseline`为数字基线
+pub fn notation_marks(notation: &Notation, cx: f32, baseline: f32) -> Vec<Mark> {
+    let mut marks = vec![Mark::Text { cx, y: baseline, size: NOTE_SIZE, text: notation.number().to_string() }];
+
+    if notation.is_sharp() {
+        marks.push(Mark::Text { cx: cx - 8.0, y: baseline - 6.0, size: 10.0, text: "#".to_owned() });
+    }
+
+    let dots = notation.octave().unsigned_abs();
+    let high = notation.octave() > 0;
+
+    // 低音点排在减时线下面
+    let low_start = baseline + 5.0 + 3.0 * notation.underline() as f32;
+    for i in 0..dots {
+        let cy = if high { baseline - 15.0 - 5.0 * i as f32 } else { low_start + 5.0 * i as f32 };
+        marks.push(Mark::Dot { cx, cy, r: 1.5 });
+    }
+
+    push_duration_marks(&mut marks, notation.underline(), notation.dotted(), cx, baseline);
+    marks
+}
+
+/// 休止符的排版，和音符一样带减时线和附点
+pub fn rest_marks(rest: &Rest, cx: f32, baseline: f32) -> Vec<Mark> {
+    let mut marks = vec![Mark::Text { cx, y: baseline, size: NOTE_SIZE, text: "0".to_owned() }];
+    push_duration_marks(&mut marks, rest.underline, rest.dotted, cx, baseline);
+    marks
+}
+
+fn push_duration_marks(marks: &mut Vec<Mark>, underline: u8, dotted: bool, cx: f32, baseline: f32) {
+    for i in 0..underline {
+        marks.push(Mark::Line { x1: cx - 6.0, x2: cx + 6.0, y: baseline + 3.0 + 3.0 * i as f32 });
+    }
+    if dotted {
+        marks.push(Mark::Dot { cx: cx + 7.0, cy: baseline - 4.0, r: 1.5 });
+    }
+}
+
+/// 小节线，与数字同高
+pub fn bar_mark(cx: f32, baseline: f32) -> Mark {
+    Mark::Bar { x: cx, y1: baseline - 16.0, y2: baseline + 4.0 }
+}
+
+/// 增时线，与数字同高
+pub fn dash_mark(cx: f32, baseline: f32) -> Mark {
+    Mark::Line { x1: cx - 5.0, x2: cx + 5.0, y: baseline - 5.0 }
+}
+
+pub fn token_width(token: &Token) -> u32 {
+    match token {
+        Token::Notation(_) | Token::Rest(_) | Token::Dash => NOTE_WIDTH,
+        Token::Whitespace | Token::Bar => SPACE_WIDTH,
+        token => token.to_string().chars().count() as u32 * CHAR_WIDTH,
+    }
+}
+
+pub fn row_height(row: Row) -> u32 {
+    if row.iter().any(|(_, fingering)| fingering.is_some()) {
+        ROW_HEIGHT
+    } else {
+        TEXT_ROW_HEIGHT
+    }
+}
+
+/// 按宽度把一行简谱折成多行，折行处的空格去掉
+pub fn wrap_line(line: &FingeringLine, max_width: u32) -> Vec<Row<'_>> {
+    wrap_line_with(line, max_width, token_width)
+}
+
+/// 按宽度把一行简谱折成多行，每个记号的宽度由`token_width`给出
+pub fn wrap_line_with(line: &FingeringLine, max_width: u32, token_width: impl Fn(&Token) -> u32) -> Vec<Row<'_>> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut width = 0;
+
+    for (index, (token, _)) in line.iter().enumerate() {
+        if index == start && *token == Token::Whitespace {
+            start += 1;
+            continue;
+        }
+        let w = token_width(token);
+        if width + w > max_width && index > start {
+            rows.push(&line[start..index]);
+            start = index;
+            width = 0;
+            if *token == Token::Whitespace {
+                start += 1;
+                continue;
+            }
+        }
+        width += w;
+    }
+
+    if start < line.len() {
+        rows.push(&line[start..]);
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::optimizer::FingeringMode;
+    use crate::tone::{fingering_lines, FingerTone};
+
+    #[test]
+    fn test_wrap_line() {
+        let lines = fingering_lines("1 2 3  4 5", FingerTone::C, FingeringMode::Standard).unwrap();
+        let rows = wrap_line(&lines[0], 3 * NOTE_WIDTH + 2 * SPACE_WIDTH);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), 5);
+        assert_eq!(rows[1].len(), 3);
+        assert_eq!(row_height(rows[1]), ROW_HEIGHT);
+        assert_eq!(wrap_line(&lines[0], 1000).len(), 1);
+    }
+
+    #[test]
+    fn test_fingering_shapes() {
+        let shapes = fingering_shapes(&crate::tone::Tone::SC.to_finger().unwrap(), 16.0);
+        assert_eq!(shapes.iter().filter(|s| matches!(s, Shape::Hole { .. })).count(), 10);
+        assert!(shapes.contains(&Shape::Hole { cx: 12.5, cy: 116.0, r: 3.5, hole: Hole::Close }));
+        assert!(shapes.contains(&Shape::Hole { cx: 20.0, cy: 116.0, r: 2.5, hole: Hole::Open }));
+    }
+
+    fn marks(s: &str) -> Vec<Mark> {
+        match s.parse::<crate::notation::parser::Parser>().unwrap().lines().remove(0).remove(0) {
+            Token::Notation(n) => notation_marks(&n, 16.0, 100.0),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_notation_marks() {
+        assert_eq!(marks("5"), vec![Mark::Text { cx: 16.0, y: 100.0, size: NOTE_SIZE, text: "5".to_owned() }]);
+        // 不再出现括号
+        assert!(marks("((#5))").iter().all(|m| !matches!(m, Mark::Text { text, .. } if text.contains('('))));
+        assert_eq!(marks("[[1]]").iter().filter(|m| matches!(m, Mark::Dot { cy, .. } if *cy < 100.0)).count(), 2);
+        // 低音点在减时线下面
+        let low = marks("(1)__.");
+        assert!(low.contains(&Mark::Line { x1: 10.0, x2: 22.0, y: 106.0 }));
+        assert!(low.contains(&Mark::Dot { cx: 16.0, cy: 111.0, r: 1.5 }));
+        assert!(low.contains(&Mark::Dot { cx: 23.0, cy: 96.0, r: 1.5 }));
+    }
+}
@@ -1,7 +1,9 @@
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use serde::Serialize;
 
 /// 洞
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Hole {
     /// 全开
     Open,
@@ -22,7 +24,7 @@ impl Display for Hole {
 }
 
 /// 指法
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub struct Fingering {
     /// 左手拇指
     left_0: Hole,
@@ -44,6 +46,7 @@ pub struct Fingering {
 
 impl Fingering {
     /// 创建指法
+    #[allow(clippy::too_many_arguments)]
     pub fn new( left_0: Hole, left_1: Hole, left_2: Hole, left_3: Hole, right_4: Hole, right_5: Hole, right_6: Hole, right_7: Hole) -> Self {
         Self {
             left_0,
@@ -58,6 +61,18 @@ impl Fingering {
     }
 }
 
+impl Fingering {
+    /// 从左手拇指到右手小指依次排列的孔位
+    pub fn holes(&self) -> [Hole; 8] {
+        [self.left_0, self.left_1, self.left_2, self.left_3, self.right_4, self.right_5, self.right_6, self.right_7]
+    }
+
+    /// 换到另一个指法需要变化的孔数
+    pub fn distance(&self, other: &Fingering) -> u32 {
+        self.holes().iter().zip(other.holes().iter()).filter(|(a, b)| a != b).count() as u32
+    }
+}
+
 impl Display for Fingering {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         format!(r#"{}
@@ -109,4 +124,30 @@ mod test {
         assert_eq!(Tone::HHSC.to_finger().map(|f| f.to_string()), Some("◐\n-\n●\n◐\n●\n-\n●\n○\n●\n●\n".to_owned()));
         assert_eq!(Tone::HHD.to_finger().map(|f| f.to_string()), Some("◐\n-\n●\n○\n●\n-\n●\n○\n●\n◐\n".to_owned()));
     }
+
+    #[test]
+    fn test_distance() {
+        let c = Tone::C.to_finger().unwrap();
+        let d = Tone::D.to_finger().unwrap();
+        let hd = Tone::HD.to_finger().unwrap();
+        assert_eq!(c.holes()[0], Hole::Close);
+        assert_eq!(c.distance(&c), 0);
+        assert_eq!(c.distance(&d), 1);
+        assert_eq!(d.distance(&hd), 6);
+        assert_eq!(hd.distance(&d), 6);
+    }
+
+    #[test]
+    fn test_fingers() {
+        assert_eq!(Tone::C.to_fingers(), vec![Tone::C.to_finger().unwrap()]);
+        assert_eq!(Tone::HD.to_fingers().len(), 2);
+        assert_eq!(Tone::HD.to_fingers()[0], Tone::HD.to_finger().unwrap());
+        assert!(Tone::LC.to_fingers().is_empty());
+
+        assert_eq!(Tone::F.to_german_finger().map(|f| f.to_string()), Some("●\n-\n●\n●\n●\n-\n●\n○\n○\n○\n".to_owned()));
+        assert_eq!(Tone::SF.to_german_finger().map(|f| f.to_string()), Some("●\n-\n●\n●\n●\n-\n○\n●\n●\n●\n".to_owned()));
+        assert_eq!(Tone::F.to_fingers()[1], Tone::F.to_german_finger().unwrap());
+        assert_eq!(Tone::G.to_german_finger(), Tone::G.to_finger());
+        assert_eq!(Tone::LC.to_german_finger(), None);
+    }
 }
@@ -0,0 +1,128 @@
+//! 带交互指法图的HTML
+//!
+//! 输出单个HTML文件，CSS和JS都内嵌在文件里。鼠标悬停或点按音符时显示指法图，
+//! 下拉框可以把同一份简谱换成其他指法音调来吹，各个调的指法事先都已算好。
+
+use crate::notation::parser::Token;
+use crate::optimizer::FingeringMode;
+use crate::svg::{escape, fingering_svg, STYLE};
+use crate::tone::{fingering_lines, ConvertError, FingerTone, FingeringLine};
+use std::fmt::Write;
+
+const PAGE_STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+.score{font-family:monospace;font-size:20px;line-height:2;margin-top:6em}\
+.line{min-height:1em;white-space:pre-wrap}\
+.raw{font-family:sans-serif;color:#666}\
+.note{position:relative;cursor:pointer;padding:0 2px;border-radius:3px}\
+.note:hover,.note:focus{background:#eef;outline:none}\
+.popup{display:none;position:absolute;left:50%;bottom:100%;transform:translateX(-50%);\
+background:#fff;border:1px solid #ccc;border-radius:4px;padding:4px;z-index:1;line-height:0}\
+.note:hover .popup,.note:focus .popup{display:block}";
+
+const SCRIPT: &str = "var select = document.getElementById('key');
+select.addEventListener('change', function () {
+    document.querySelectorAll('.score').forEach(function (score) {
+        score.hidden = score.dataset.key !== select.value;
+    });
+});";
+
+/// 竖笛数字简谱可视化为HTML，可切换到其他能吹奏这份简谱的指法音调
+pub fn visualize_html(content: &str, finger_tone: FingerTone, mode: FingeringMode) -> Result<String, ConvertError> {
+    let original = fingering_lines(content, finger_tone, mode)?;
+
+    let mut scores = Vec::new();
+    for &to in FingerTone::ALL.iter() {
+        if to == finger_tone {
+            scores.push((to, render_score(&original)));
+            continue;
+        }
+        // 超出竖笛音域的调不提供
+        if let Ok(lines) = fingering_lines(content, to, mode) {
+            scores.push((to, render_score(&lines)));
+        }
+    }
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>竖笛指法</title>
+<style>{}{}</style>
+</head>
+<body>
+<label>调：<select id="key">
+"#,
+        PAGE_STYLE, STYLE
+    );
+
+    for (key, _) in &scores {
+        let selected = if *key == finger_tone { " selected" } else { "" };
+        let _ = writeln!(out, r#"<option value="{}"{}>1={}</option>"#, escape(&key.to_string()), selected, key);
+    }
+    out.push_str("</select></label>\n");
+
+    for (key, score) in &scores {
+        let hidden = if *key == finger_tone { "" } else { " hidden" };
+        let _ = writeln!(out, r#"<div class="score" data-key="{}"{}>{}</div>"#, escape(&key.to_string()), hidden, score);
+    }
+
+    let _ = write!(out, "<script>\n{}\n</script>\n</body>\n</html>\n", SCRIPT);
+    Ok(out)
+}
+
+fn render_score(lines: &[FingeringLine]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        let raw = line.iter().all(|(token, _)| token.is_text());
+        out.push_str(if raw && !line.is_empty() { r#"<div class="line raw">"# } else { r#"<div class="line">"# });
+        for (token, fingering) in line {
+            match (token, fingering) {
+                (Token::Notation(n), Some(fingering)) => {
+                    let _ = write!(
+                        out,
+                        r#"<span class="note" tabindex="0">{}<span class="popup">{}</span></span>"#,
+                        n.to_unicode(),
+                        fingering_svg(fingering)
+                    );
+                }
+                (token, _) => out.push_str(&escape(&token.to_string())),
+            }
+        }
+        out.push_str("</div>\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_visualize_html() {
+        let html = visualize_html("1 2 #4\n\n// <注释>", FingerTone::C, FingeringMode::Standard).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(r#"<option value="C" selected>1=C</option>"#));
+        assert!(html.contains(r#"<div class="score" data-key="C">"#));
+        assert!(html.contains(r##"<div class="score" data-key="#A" hidden>"##));
+        assert_eq!(html.matches("<option").count(), html.matches(r#"<div class="score""#).count());
+        assert!(html.contains(r#"<div class="line raw">// &lt;注释&gt;</div>"#));
+        assert!(html.contains(r#"<span class="note" tabindex="0">♯4<span"#));
+        assert_eq!(html.matches(r#"<span class="note""#).count(), 3 * html.matches("<option").count());
+        // 不依赖外部资源
+        assert!(!html.contains("<link"));
+        assert!(!html.contains("src="));
+    }
+
+    #[test]
+    fn test_unplayable_keys_skipped() {
+        // [[2]]在C调已是最高音，升高的调都吹不了
+        let html = visualize_html("1 [[2]]", FingerTone::C, FingeringMode::Standard).unwrap();
+        assert!(html.contains(r#"data-key="C""#));
+        assert!(!html.contains(r#"data-key="D""#));
+        assert!(visualize_html("8", FingerTone::C, FingeringMode::Standard).is_err());
+    }
+}
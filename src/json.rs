@@ -0,0 +1,122 @@
+//! 机器可读的JSON输出
+//!
+//! 输出整份解析后的简谱，给网页等其他程序使用。结构如下，`version`在结构不兼容地改变时加一：
+//!
+//! ```text
+//! {
+//!   "version": 2,
+//!   "key": "C",                   // 指法音调
+//!   "lines": [                    // 每行简谱
+//!     [                           // 按顺序排列的记号
+//!       {
+//!         "token": {"type": "notation", "value": {"octave": 0, "number": 5, "is_sharp": false, "underline": 1, "dotted": false}},
+//!         "text": "5_",           // 记号原来的写法
+//!         "tone": "G",            // 以下三项只有音符才有：音调
+//!         "midi": 79,             // MIDI音符编号
+//!         "fingering": {"left_0": "close", "left_1": "close", ..., "right_7": "open"}
+//!       }
+//!     ]
+//!   ]
+//! }
+//! ```
+//!
+//! `token.type`为`notation`、`rest`、`dash`、`bar`、`header`、`lyrics`、`raw`或`whitespace`，
+//! 孔的状态为`open`、`half`或`close`。
+
+use crate::finger::Fingering;
+use crate::notation::parser::Token;
+use crate::optimizer::FingeringMode;
+use crate::tone::{fingering_lines, ConvertError, FingerTone, Tone};
+use serde::Serialize;
+
+/// JSON结构的版本
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// 整份简谱
+#[derive(Debug, Serialize)]
+pub struct Document {
+    pub version: u32,
+    pub key: String,
+    pub lines: Vec<Vec<Item>>,
+}
+
+/// 一个记号，音符带有音调和指法
+#[derive(Debug, Serialize)]
+pub struct Item {
+    pub token: Token,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tone: Option<Tone>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub midi: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingering: Option<Fingering>,
+}
+
+impl Document {
+    /// 解析简谱并给每个音符配上音调和指法
+    pub fn parse(content: &str, finger_tone: FingerTone, mode: FingeringMode) -> Result<Self, ConvertError> {
+        let lines = fingering_lines(content, finger_tone, mode)?.into_iter().map(|line| {
+            line.into_iter().map(|(token, fingering)| {
+                let tone = match &token {
+                    Token::Notation(n) => Tone::from_notation(n, finger_tone),
+                    _ => None,
+                };
+                Item {
+                    text: token.to_string(),
+                    token,
+                    tone,
+                    midi: tone.map(Tone::midi),
+                    fingering,
+                }
+            }).collect()
+        }).collect();
+
+        Ok(Document {
+            version: SCHEMA_VERSION,
+            key: finger_tone.to_string(),
+            lines,
+        })
+    }
+}
+
+/// 竖笛数字简谱输出为JSON
+pub fn visualize_json(content: &str, finger_tone: FingerTone, mode: FingeringMode) -> Result<String, ConvertError> {
+    let document = Document::parse(content, finger_tone, mode)?;
+    // 只有字符串键和普通的值，不会出错
+    Ok(serde_json::to_string_pretty(&document).expect("简谱总能转成JSON"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_visualize_json() {
+        let output = visualize_json("1=C 2/4\n5_ 0 - |", FingerTone::C, FingeringMode::Standard).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(value["version"], SCHEMA_VERSION);
+        assert_eq!(value["key"], "C");
+        assert_eq!(value["lines"][0][0]["token"]["type"], "header");
+        assert_eq!(value["lines"][0][0]["token"]["value"]["meter"], json!({"beats": 2, "beat_type": 4}));
+        assert_eq!(value["lines"][0][0].get("tone"), None);
+
+        let note = &value["lines"][1][0];
+        assert_eq!(note["text"], "5_");
+        assert_eq!(note["token"], json!({
+            "type": "notation",
+            "value": {"octave": 0, "number": 5, "is_sharp": false, "underline": 1, "dotted": false},
+        }));
+        assert_eq!(note["tone"], "G");
+        assert_eq!(note["midi"], 79);
+        assert_eq!(note["fingering"]["left_0"], "close");
+        assert_eq!(note["fingering"]["left_3"], "close");
+        assert_eq!(note["fingering"]["right_4"], "open");
+
+        assert_eq!(value["lines"][1][2]["token"], json!({"type": "rest", "value": {"underline": 0, "dotted": false}}));
+        assert_eq!(value["lines"][1][4]["token"], json!({"type": "dash"}));
+        assert_eq!(value["lines"][1][6]["token"], json!({"type": "bar"}));
+    }
+}
@@ -0,0 +1,373 @@
+//! 调：主音、升降写法和调式
+//!
+//! `FingerTone`只表示简谱的`1`是哪个音，而且只有升号的写法。`Key`还记下主音写成升号还是降号
+//! （`F#`和`Gb`），以及大调、小调和五声调式（宫商角徵羽），可以和`FingerTone`互相转换。
+//!
+//! 小调和五声调式的简谱有两种记法：do记法（`Numbering::Do`）把主音记成`1`，写成`1=Am`；
+//! la记法（`Numbering::La`）的`1`为关系大调的主音，A小调的主音记成`6`，写成`1=C Am`。
+
+use crate::notation::parser::Header;
+use crate::tone::{parse_pitch_name, FingerTone, ToneError};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// 调式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// 大调
+    Major,
+    /// 自然小调
+    Minor,
+    /// 宫调式
+    Gong,
+    /// 商调式
+    Shang,
+    /// 角调式
+    Jue,
+    /// 徵调式
+    Zhi,
+    /// 羽调式
+    Yu,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 7] = [Mode::Major, Mode::Minor, Mode::Gong, Mode::Shang, Mode::Jue, Mode::Zhi, Mode::Yu];
+
+    /// 主音在简谱里是几，例如小调和羽调式为`6`
+    pub fn degree(self) -> u8 {
+        match self {
+            Mode::Major | Mode::Gong => 1,
+            Mode::Shang => 2,
+            Mode::Jue => 3,
+            Mode::Zhi => 5,
+            Mode::Minor | Mode::Yu => 6,
+        }
+    }
+
+    /// 主音比简谱的`1`高几个半音
+    pub fn offset(self) -> u8 {
+        match self {
+            Mode::Major | Mode::Gong => 0,
+            Mode::Shang => 2,
+            Mode::Jue => 4,
+            Mode::Zhi => 7,
+            Mode::Minor | Mode::Yu => 9,
+        }
+    }
+
+    /// 五声调式
+    pub fn is_pentatonic(self) -> bool {
+        !matches!(self, Mode::Major | Mode::Minor)
+    }
+
+    /// 调名里跟在主音后面的写法，大调为空
+    fn suffix(self) -> &'static str {
+        match self {
+            Mode::Major => "",
+            Mode::Minor => "m",
+            Mode::Gong => "宫",
+            Mode::Shang => "商",
+            Mode::Jue => "角",
+            Mode::Zhi => "徵",
+            Mode::Yu => "羽",
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Mode::Major => "大调",
+            Mode::Minor => "小调",
+            Mode::Gong => "宫调式",
+            Mode::Shang => "商调式",
+            Mode::Jue => "角调式",
+            Mode::Zhi => "徵调式",
+            Mode::Yu => "羽调式",
+        }.fmt(f)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ToneError;
+
+    /// 例如`m`、`minor`、`小调`、`徵`、`徵调式`，不分大小写；空字符串为大调
+    fn from_str(s: &str) -> Result<Self, ToneError> {
+        let name = s.trim().to_lowercase();
+        let name = name.strip_suffix("调式").or_else(|| name.strip_suffix('调')).unwrap_or(&name);
+        Ok(match name {
+            "" | "大" | "major" | "maj" => Mode::Major,
+            "m" | "小" | "minor" | "min" => Mode::Minor,
+            "宫" | "gong" => Mode::Gong,
+            "商" | "shang" => Mode::Shang,
+            "角" | "jue" => Mode::Jue,
+            "徵" | "zhi" => Mode::Zhi,
+            "羽" | "yu" => Mode::Yu,
+            _ => return Err(ToneError(format!("无法识别的调式：{}", s))),
+        })
+    }
+}
+
+/// 黑键写成升号还是降号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Spelling {
+    Sharp,
+    Flat,
+}
+
+/// 调
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    /// 主音比C高几个半音，0到11
+    tonic: u8,
+    spelling: Spelling,
+    mode: Mode,
+}
+
+impl Key {
+    /// 主音为白键时调名里写不出升降，`spelling`改为按调号来定，和`parse`读回来的一样
+    pub fn new(tonic: u8, spelling: Spelling, mode: Mode) -> Key {
+        let key = Key { tonic: tonic % 12, spelling, mode };
+        match key.tonic {
+            1 | 3 | 6 | 8 | 10 => key,
+            _ => Key { spelling: Key::signature_spelling(key.finger_tone()), ..key },
+        }
+    }
+
+    fn signature_spelling(finger_tone: FingerTone) -> Spelling {
+        if finger_tone.fifths() < 0 { Spelling::Flat } else { Spelling::Sharp }
+    }
+
+    /// 简谱的`1`为`finger_tone`，主音按调式来定，例如`1=C`的小调为A小调
+    pub fn from_finger_tone(finger_tone: FingerTone, mode: Mode) -> Key {
+        Key::new(finger_tone.semitones() + mode.offset(), Key::signature_spelling(finger_tone), mode)
+    }
+
+    pub fn tonic(self) -> u8 {
+        self.tonic
+    }
+
+    pub fn spelling(self) -> Spelling {
+        self.spelling
+    }
+
+    pub fn mode(self) -> Mode {
+        self.mode
+    }
+
+    /// 简谱的`1`是哪个调的指法
+    pub fn finger_tone(self) -> FingerTone {
+        FingerTone::ALL[((self.tonic + 12 - self.mode.offset()) % 12) as usize]
+    }
+
+    /// 同一个`1`的其他调式，例如C大调的关系小调为A小调
+    pub fn relative(self, mode: Mode) -> Key {
+        Key::new(self.finger_tone().semitones() + mode.offset(), self.spelling, mode)
+    }
+
+    /// 调号在五度圈上的位置：升号调为正，降号调为负，黑键按写法来定，例如`F#`为6，`Gb`为-6
+    pub fn fifths(self) -> i8 {
+        let fifths = (self.finger_tone().semitones() as i8 * 7).rem_euclid(12);
+        match fifths {
+            11 => -1,
+            6..=10 if self.spelling == Spelling::Flat => fifths - 12,
+            _ => fifths,
+        }
+    }
+
+    /// 主音的名字，例如`D`、`F#`、`Gb`
+    pub fn tonic_name(self) -> &'static str {
+        const SHARP_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+        const FLAT_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
+        match self.spelling {
+            Spelling::Sharp => SHARP_NAMES[self.tonic as usize],
+            Spelling::Flat => FLAT_NAMES[self.tonic as usize],
+        }
+    }
+}
+
+/// 简谱数字的记法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Numbering {
+    /// `1`为主音，小调和五声调式的主音也记成`1`
+    Do,
+    /// `1`为大调主音，小调的主音记成`6`，五声调式的主音按宫商角徵羽记成`1 2 3 5 6`
+    La,
+}
+
+pub const NUMBERINGS: [&str; 2] = ["do", "la"];
+
+impl FromStr for Numbering {
+    type Err = ToneError;
+
+    fn from_str(s: &str) -> Result<Self, ToneError> {
+        match s.to_lowercase().as_str() {
+            "do" => Ok(Numbering::Do),
+            "la" => Ok(Numbering::La),
+            _ => Err(ToneError(format!("记法只能是do或la，不能是{}", s))),
+        }
+    }
+}
+
+impl Key {
+    /// 按`numbering`记谱时简谱的`1`是哪个调的指法
+    pub fn one(self, numbering: Numbering) -> FingerTone {
+        match numbering {
+            Numbering::Do => FingerTone::ALL[self.tonic as usize],
+            Numbering::La => self.finger_tone(),
+        }
+    }
+}
+
+/// 调号行的`1`和调性：`1=Am`的`1`为A，`1=C Am`的`1`为C，调性都是A小调；
+/// 只写大调`1=C`时没有调性
+pub fn read_header(header: &Header) -> Result<(FingerTone, Option<Key>), ToneError> {
+    if let Some(tonality) = &header.tonality {
+        return Ok((header.key.parse()?, Some(tonality.parse()?)));
+    }
+    let key: Key = header.key.parse()?;
+    let tonality = if key.mode() == Mode::Major { None } else { Some(key) };
+    Ok((key.one(Numbering::Do), tonality))
+}
+
+/// 按`1`和调性改写调号行，`read_header`的逆运算，写明的大调`1=C C`也保留
+pub fn write_header(header: Header, one: FingerTone, tonality: Option<Key>) -> Header {
+    let (key, tonality) = match tonality {
+        Some(key) if key.mode() != Mode::Major && key.one(Numbering::Do) == one => (key.to_string(), None),
+        Some(key) => (one.to_string(), Some(key.to_string())),
+        None => (one.to_string(), None),
+    };
+    Header { key, tonality, ..header }
+}
+
+impl From<FingerTone> for Key {
+    /// 大调
+    fn from(finger_tone: FingerTone) -> Key {
+        Key::from_finger_tone(finger_tone, Mode::Major)
+    }
+}
+
+impl From<Key> for FingerTone {
+    fn from(key: Key) -> FingerTone {
+        key.finger_tone()
+    }
+}
+
+impl Display for Key {
+    /// 例如`D`、`F#m`、`Bb`、`D徵`，可以用`parse`读回来
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        format!("{}{}", self.tonic_name(), self.mode.suffix()).fmt(f)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ToneError;
+
+    /// 主音加调式，例如`D`、`F#m`、`Gb`、`a minor`、`D徵`、`升F小调`、`1=Eb`，不分大小写
+    fn from_str(s: &str) -> Result<Self, ToneError> {
+        let name = s.trim();
+        let name = name.strip_prefix("1=").unwrap_or(name).trim_start();
+        let name = match name.strip_prefix('升') {
+            Some(rest) => format!("#{}", rest),
+            None => match name.strip_prefix('降') {
+                Some(rest) => format!("♭{}", rest),
+                None => name.to_owned(),
+            },
+        };
+        let (step, alter, rest) = parse_pitch_name(&name).ok_or_else(|| ToneError(format!("无法识别的调：{}", s)))?;
+        let mode = rest.parse()?;
+        let tonic = (step + alter).rem_euclid(12) as u8;
+        Ok(Key::new(tonic, if alter < 0 { Spelling::Flat } else { Spelling::Sharp }, mode))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::notation::parser::{Parser, Token};
+
+    #[test]
+    fn test_finger_tone() {
+        for &finger_tone in FingerTone::ALL.iter() {
+            let key = Key::from(finger_tone);
+            assert_eq!(FingerTone::from(key), finger_tone);
+            assert_eq!(key.fifths(), finger_tone.fifths());
+            for &mode in Mode::ALL.iter() {
+                assert_eq!(key.relative(mode).finger_tone(), finger_tone);
+                assert_eq!(Key::from_finger_tone(finger_tone, mode).relative(Mode::Major), key);
+            }
+        }
+        assert_eq!(Key::from(FingerTone::C).relative(Mode::Minor).to_string(), "Am");
+        assert_eq!(Key::from(FingerTone::SA).to_string(), "Bb");
+        assert_eq!(Key::from(FingerTone::SF).to_string(), "F#");
+    }
+
+    #[test]
+    fn test_header() {
+        let header = |s: &str| match s.parse::<Parser>().unwrap().lines().remove(0).remove(0) {
+            Token::Header(header) => header,
+            token => panic!("{:?}", token),
+        };
+        let am: Key = "Am".parse().unwrap();
+
+        for &(s, one, tonality) in [
+            ("1=C", FingerTone::C, None),
+            ("1=#A", FingerTone::SA, None),
+            ("1=C Am", FingerTone::C, Some(am)),
+            ("1=Am", FingerTone::A, Some(am)),
+            ("1=#A C", FingerTone::SA, Some(Key::from(FingerTone::C))),
+            ("1=C C", FingerTone::C, Some(Key::from(FingerTone::C))),
+        ].iter() {
+            assert_eq!(read_header(&header(s)), Ok((one, tonality)), "{}", s);
+            assert_eq!(write_header(header(s), one, tonality).to_string(), s);
+        }
+        assert_eq!(write_header(header("1=C Am 3/4"), FingerTone::A, Some(am)).to_string(), "1=Am 3/4");
+        assert_eq!(write_header(header("1=Am"), FingerTone::C, Some(am)).to_string(), "1=C Am");
+        assert!(read_header(&header("1=Am Am")).is_err());
+        assert!(read_header(&header("1=H")).is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        for tonic in 0..12 {
+            for &spelling in [Spelling::Sharp, Spelling::Flat].iter() {
+                for &mode in Mode::ALL.iter() {
+                    let key = Key::new(tonic, spelling, mode);
+                    let parsed: Key = key.to_string().parse().unwrap();
+                    assert_eq!(parsed.tonic(), tonic);
+                    assert_eq!(parsed.mode(), mode);
+                    assert_eq!(parsed.finger_tone(), key.finger_tone());
+                    assert_eq!(parsed, key);
+                }
+            }
+        }
+
+        let key: Key = "Am".parse().unwrap();
+        assert_eq!((key.finger_tone(), key.mode()), (FingerTone::C, Mode::Minor));
+        assert_eq!("a minor".parse(), Ok(key));
+        assert_eq!("A小调".parse(), Ok(key));
+
+        let key: Key = "1=Eb".parse().unwrap();
+        assert_eq!((key.finger_tone(), key.fifths(), key.to_string().as_str()), (FingerTone::SD, -3, "Eb"));
+        assert_eq!("C#".parse::<Key>().unwrap().fifths(), 7);
+        assert_eq!("F#".parse::<Key>().unwrap().fifths(), 6);
+        assert_eq!("Gb".parse::<Key>().unwrap().fifths(), -6);
+        assert_eq!("Cm".parse::<Key>().unwrap().to_string(), "Cm");
+        assert_eq!("Cm".parse::<Key>().unwrap().spelling(), Spelling::Flat);
+
+        let key: Key = "D徵".parse().unwrap();
+        assert_eq!((key.finger_tone(), key.mode(), key.mode().degree()), (FingerTone::G, Mode::Zhi, 5));
+        assert_eq!("d 徵调式".parse(), Ok(key));
+        assert_eq!("E羽".parse::<Key>().unwrap().finger_tone(), FingerTone::G);
+        assert_eq!("升F小调".parse::<Key>().unwrap().to_string(), "F#m");
+        assert_eq!("降B大调".parse::<Key>().unwrap().to_string(), "Bb");
+
+        assert_eq!("Am".parse::<Key>().unwrap().one(Numbering::La), FingerTone::C);
+        assert_eq!("Am".parse::<Key>().unwrap().one(Numbering::Do), FingerTone::A);
+        assert_eq!("La".parse(), Ok(Numbering::La));
+
+        for s in &["", "H", "Cx", "C dorian", "1="] {
+            assert!(s.parse::<Key>().is_err(), "{}", s);
+        }
+    }
+}
@@ -1,3 +1,21 @@
 pub mod tone;
+pub mod key;
 pub mod notation;
-pub mod finger;
\ No newline at end of file
+pub mod finger;
+pub mod optimizer;
+pub mod diagram;
+pub mod svg;
+pub mod pdf;
+pub mod html;
+pub mod json;
+pub mod terminal;
+pub mod raster;
+pub mod score;
+pub mod musicxml;
+pub mod abc;
+pub mod lilypond;
+pub mod midi;
+pub mod synth;
+pub mod pitch;
+pub mod cli;
+pub mod config;
@@ -0,0 +1,261 @@
+//! LilyPond输出
+//!
+//! 五线谱按高音竖笛的实际音高写，用高八度的高音谱号；五线谱下面用`\lyricmode`写一行简谱，
+//! 再写歌词。可以在每个音符上方加竖笛的`\woodwind-diagram`指法图。
+
+use crate::finger::{Fingering, Hole};
+use crate::musicxml::note_values;
+use crate::optimizer::{optimize_fingerings, FingeringMode};
+use crate::score::{Event, Score, DEFAULT_METER, DIVISIONS};
+use crate::tone::{ConvertError, FingerTone, Tone};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+/// 生成的文件使用的LilyPond版本
+const VERSION: &str = "2.24.0";
+
+/// 竖笛指法图里孔的名字，从左手拇指到右手小指
+const HOLES: [&str; 8] = ["thumb", "one", "two", "three", "four", "five", "six", "seven"];
+
+/// 指法图的选项
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diagrams {
+    pub mode: FingeringMode,
+    /// 指法音比实际音高几个半音，F调竖笛按高音竖笛的指法来查
+    pub shift: i32,
+}
+
+/// 乐谱转换为LilyPond，`diagrams`指定时在音符上方加指法图
+pub fn write_lilypond(score: &Score, title: &str, diagrams: Option<Diagrams>) -> Result<String, ConvertError> {
+    let flats = score.finger_tone.fifths() < 0;
+    let meter = score.meter.unwrap_or(DEFAULT_METER);
+    let mut fingerings = match diagrams {
+        Some(diagrams) => Some(fingerings(score, diagrams)?.into_iter()),
+        None => None,
+    };
+
+    let mut melody = String::new();
+    let mut jianpu = Vec::new();
+    let mut words: Vec<Vec<String>> = Vec::new();
+
+    let measures = score.measures();
+    for measure in &measures {
+        melody.push_str("  ");
+        if measure.is_empty() {
+            let _ = write!(melody, "R1*{}/{} ", meter.beats, meter.beat_type);
+        }
+        for event in measure {
+            let values = note_values(event.duration());
+            for (index, &(duration, _, dot)) in values.iter().enumerate() {
+                match event {
+                    Event::Note { tone, .. } => {
+                        let (step, alter, octave) = tone.spell(flats);
+                        melody.push_str(&pitch_name(step, alter, octave));
+                    }
+                    _ => melody.push('r'),
+                }
+                melody.push_str(&length(duration, dot));
+                if let (Some(fingerings), Event::Note { .. }, 0) = (&mut fingerings, event, index) {
+                    if let Some(fingering) = fingerings.next() {
+                        melody.push_str(&diagram(&fingering));
+                    }
+                }
+                if matches!(event, Event::Note { .. }) && index + 1 < values.len() {
+                    melody.push('~');
+                }
+                melody.push(' ');
+            }
+
+            if let Event::Note { tone, lyrics, .. } = event {
+                let notation = tone.to_notation(score.finger_tone).to_string();
+                for (verse, word) in lyrics.iter().enumerate() {
+                    if words.len() <= verse {
+                        words.resize(verse + 1, Vec::new());
+                    }
+                    words[verse].resize(jianpu.len(), "\"\"".to_owned());
+                    words[verse].push(word.as_deref().map_or_else(|| "\"\"".to_owned(), quote));
+                }
+                jianpu.push(syllable(&notation));
+            }
+        }
+        melody.push_str("|\n");
+    }
+    melody.push_str("  \\bar \"|.\"\n");
+
+    let mut out = String::new();
+    let _ = writeln!(out, "\\version \"{}\"\n", VERSION);
+    let _ = writeln!(out, "\\header {{\n  title = {}\n  tagline = ##f\n}}\n", quote(title));
+
+    let _ = writeln!(out, "melody = {{");
+    let _ = writeln!(out, "  \\clef \"treble^8\"");
+    let _ = writeln!(out, "  \\key {} \\major", key_name(score.finger_tone));
+    let _ = writeln!(out, "  \\time {}/{}", meter.beats, meter.beat_type);
+    if let Some(tempo) = score.tempo {
+        let _ = writeln!(out, "  \\tempo 4 = {}", tempo);
+    }
+    let _ = writeln!(out, "{}}}\n", melody);
+
+    let _ = writeln!(out, "jianpu = \\lyricmode {{\n  {}\n}}\n", jianpu.join(" "));
+    for (verse, words) in words.iter().enumerate() {
+        let _ = writeln!(out, "verse{} = \\lyricmode {{\n  {}\n}}\n", verse_name(verse), words.join(" "));
+    }
+
+    out.push_str("\\score {\n  <<\n");
+    out.push_str("    \\new Staff \\with { midiInstrument = \"recorder\" } { \\new Voice = \"melody\" \\melody }\n");
+    out.push_str("    \\new Lyrics \\lyricsto \"melody\" \\jianpu\n");
+    for verse in 0..words.len() {
+        let _ = writeln!(out, "    \\new Lyrics \\lyricsto \"melody\" \\verse{}", verse_name(verse));
+    }
+    out.push_str("  >>\n  \\layout { }\n  \\midi { }\n}\n");
+
+    Ok(out)
+}
+
+/// 每个音符的指法
+fn fingerings(score: &Score, diagrams: Diagrams) -> Result<Vec<Fingering>, ConvertError> {
+    let tones = score.events.iter().filter_map(|event| match event {
+        Event::Note { tone, .. } => Some(*tone),
+        _ => None,
+    }).map(|tone| {
+        u8::try_from(tone.midi() as i32 + diagrams.shift).ok().and_then(Tone::from_midi)
+            .ok_or_else(|| ConvertError::NotFound(format!("竖笛吹不了的音符：{:?}", tone)))
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    match diagrams.mode {
+        FingeringMode::Standard => tones.iter().map(|tone| {
+            tone.to_finger().ok_or_else(|| ConvertError::NotFound(format!("竖笛吹不了的音符：{:?}", tone)))
+        }).collect(),
+        FingeringMode::German => tones.iter().map(|tone| {
+            tone.to_german_finger().ok_or_else(|| ConvertError::NotFound(format!("竖笛吹不了的音符：{:?}", tone)))
+        }).collect(),
+        FingeringMode::Auto(model) => optimize_fingerings(&tones, &model)
+            .ok_or_else(|| ConvertError::NotFound("找不到可用的指法".to_owned())),
+    }
+}
+
+/// 指法图，半按的孔用`1h`
+fn diagram(fingering: &Fingering) -> String {
+    let holes = fingering.holes();
+    let name = |index: usize| match holes[index] {
+        Hole::Open => None,
+        Hole::Half => Some(format!("{}1h", HOLES[index])),
+        Hole::Close => Some(HOLES[index].to_owned()),
+    };
+    let thumb = name(0).unwrap_or_default();
+    let cc = (1..HOLES.len()).filter_map(name).collect::<Vec<_>>().join(" ");
+    format!("^\\markup \\override #'(size . 0.4) \\woodwind-diagram #'recorder #'((cc . ({})) (lh . ({})) (rh . ()))", cc, thumb)
+}
+
+/// LilyPond的绝对音高：`c'`为C4
+fn pitch_name(step: &str, alter: i8, octave: u8) -> String {
+    let accidental = match alter {
+        1 => "is",
+        -1 => "es",
+        _ => "",
+    };
+    let marks = if octave >= 3 {
+        "'".repeat(octave as usize - 3)
+    } else {
+        ",".repeat(3 - octave as usize)
+    };
+    format!("{}{}{}", step.to_lowercase(), accidental, marks)
+}
+
+/// 时值写法，例如`4`、`8.`
+fn length(duration: u32, dot: bool) -> String {
+    let base = if dot { duration * 2 / 3 } else { duration };
+    format!("{}{}", DIVISIONS * 4 / base.max(1), if dot { "." } else { "" })
+}
+
+/// 调名，黑键调用降号
+fn key_name(finger_tone: FingerTone) -> &'static str {
+    match finger_tone {
+        FingerTone::C => "c",
+        FingerTone::SC => "des",
+        FingerTone::D => "d",
+        FingerTone::SD => "ees",
+        FingerTone::E => "e",
+        FingerTone::F => "f",
+        FingerTone::SF => "fis",
+        FingerTone::G => "g",
+        FingerTone::SG => "aes",
+        FingerTone::A => "a",
+        FingerTone::SA => "bes",
+        FingerTone::B => "b",
+    }
+}
+
+/// 简谱音符写成歌词音节，高低音用上下的圆点表示
+fn syllable(notation: &str) -> String {
+    let low = notation.matches('(').count();
+    let high = notation.matches('[').count();
+    let sharp = notation.contains('#');
+    let digit = notation.trim_matches(|c| "()[]#".contains(c));
+
+    if low == 0 && high == 0 && !sharp {
+        return quote(digit);
+    }
+    let digit = if sharp {
+        format!("\\concat {{ \\teeny \\sharp {} }}", quote(digit))
+    } else {
+        quote(digit)
+    };
+    let dots = vec!["\\teeny \"•\""; high.max(low)].join(" ");
+    if high > 0 {
+        format!("\\markup \\center-column {{ {} {} }}", dots, digit)
+    } else if low > 0 {
+        format!("\\markup \\center-column {{ {} {} }}", digit, dots)
+    } else {
+        format!("\\markup {}", digit)
+    }
+}
+
+/// LilyPond的变量名不能有数字，第几段歌词用字母表示
+fn verse_name(verse: usize) -> String {
+    ((b'A' + (verse % 26) as u8) as char).to_string().repeat(verse / 26 + 1)
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_syllable() {
+        assert_eq!(syllable("5"), "\"5\"");
+        assert_eq!(syllable("#4"), "\\markup \\concat { \\teeny \\sharp \"4\" }");
+        assert_eq!(syllable("[1]"), "\\markup \\center-column { \\teeny \"•\" \"1\" }");
+        assert_eq!(syllable("((7))"), "\\markup \\center-column { \"7\" \\teeny \"•\" \\teeny \"•\" }");
+    }
+
+    #[test]
+    fn test_write_lilypond() {
+        let score = Score::parse("1=F 3/4 ♩=90\n1_ #1_ 1 - | 0 [1]. (7)_ |\nw: 一 * 三\n", None).unwrap();
+        let ly = write_lilypond(&score, "测试", None).unwrap();
+        assert!(ly.contains("\\key f \\major\n  \\time 3/4\n  \\tempo 4 = 90\n"));
+        assert!(ly.contains("  f''8 ges''8 f''2 |\n  r4 f'''4. e''8 |\n  \\bar \"|.\"\n"));
+        assert!(ly.contains("jianpu = \\lyricmode {\n  \"1\" \\markup \\concat { \\teeny \\sharp \"1\" } \"1\""));
+        assert!(ly.contains("verseA = \\lyricmode {\n  \"一\" \"\" \"三\"\n}"));
+        assert!(ly.contains("\\new Lyrics \\lyricsto \"melody\" \\verseA\n"));
+    }
+
+    #[test]
+    fn test_diagram() {
+        let score = Score::parse("1=C\n1 [3] - -", None).unwrap();
+        let diagrams = Diagrams { mode: FingeringMode::Standard, shift: 0 };
+        let ly = write_lilypond(&score, "", Some(diagrams)).unwrap();
+        assert!(ly.contains("c''4^\\markup \\override #'(size . 0.4) \\woodwind-diagram #'recorder \
+#'((cc . (one two three four five six seven)) (lh . (thumb)) (rh . ()))"));
+        assert!(ly.contains("e'''2.^\\markup \\override #'(size . 0.4) \\woodwind-diagram #'recorder \
+#'((cc . (one two three four five)) (lh . (thumb1h)) (rh . ()))"));
+
+        // 中音竖笛吹F调的1，用高音竖笛1=C的指法
+        let score = Score::parse("1=F\n1", None).unwrap();
+        let ly = write_lilypond(&score, "", Some(Diagrams { shift: -5, ..diagrams })).unwrap();
+        assert!(ly.contains("f''4^\\markup \\override #'(size . 0.4) \\woodwind-diagram #'recorder \
+#'((cc . (one two three four five six seven)) (lh . (thumb)) (rh . ()))"));
+    }
+}
@@ -0,0 +1,605 @@
+//! 标准MIDI文件（SMF）导入导出
+//!
+//! 单声部写成Type 0，多声部写成Type 1（第一轨为速度、拍号轨，每个声部一轨）。
+//! 音色为General MIDI的竖笛（75号），音高按`Tone::midi`。
+//!
+//! 导入时合并所有音轨（或只读指定的音轨），同时发声的音只取最高音，音符的起止按网格量化，
+//! 跨小节线的音符在小节线处拆开。第10通道的打击乐忽略。
+
+use crate::notation::parser::Meter;
+use crate::score::{fit_octave, Event, Score, DEFAULT_METER, DIVISIONS};
+use crate::tone::{FingerTone, Tone};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// 每个四分音符的tick数
+pub const TICKS_PER_QUARTER: u16 = 480;
+
+/// General MIDI的竖笛音色（从0开始编号）
+const RECORDER_PROGRAM: u8 = 74;
+
+/// 没有速度时默认每分钟四分音符数
+pub const DEFAULT_TEMPO: u32 = 120;
+
+const VELOCITY: u8 = 80;
+
+/// 导出设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiOptions {
+    /// 简谱没有调号行速度时使用的速度
+    pub tempo: u32,
+    /// 简谱没有写节奏（没有减时线、附点、增时线和休止符）时每个音符的时值，单位同`DIVISIONS`
+    pub note_length: u32,
+}
+
+impl Default for MidiOptions {
+    fn default() -> Self {
+        Self {
+            tempo: DEFAULT_TEMPO,
+            note_length: DIVISIONS,
+        }
+    }
+}
+
+/// 乐谱转换为MIDI文件，`parts`只有一个声部时为Type 0
+pub fn write_midi(parts: &[Score], options: &MidiOptions) -> Vec<u8> {
+    let first = parts.first();
+    let tempo = first.and_then(|score| score.tempo).unwrap_or(options.tempo).max(1);
+    let mut conductor = Vec::new();
+    if let Some(score) = first {
+        let meter = score.meter.unwrap_or(DEFAULT_METER);
+        // 拍号：分母写成2的幂，每拍24个MIDI时钟，每四分音符8个三十二分音符
+        conductor.extend_from_slice(&[0, 0xff, 0x58, 4, meter.beats, meter.beat_type.trailing_zeros() as u8, 24, 8]);
+        conductor.extend_from_slice(&[0, 0xff, 0x59, 2, score.finger_tone.fifths() as u8, 0]);
+    }
+    // 速度只有3个字节，每拍最长0xFFFFFF微秒，约每分钟3.6拍
+    let micros = (60_000_000 / tempo).min(0xff_ffff);
+    conductor.extend_from_slice(&[0, 0xff, 0x51, 3, (micros >> 16) as u8, (micros >> 8) as u8, micros as u8]);
+
+    let mut out = Vec::new();
+    if parts.len() == 1 {
+        let mut track = conductor;
+        track.extend(notes(&parts[0], 0, options));
+        header(&mut out, 0, 1);
+        chunk(&mut out, track);
+    } else {
+        header(&mut out, 1, parts.len() as u16 + 1);
+        conductor.extend_from_slice(&[0, 0xff, 0x2f, 0]);
+        chunk(&mut out, conductor);
+        for (index, score) in parts.iter().enumerate() {
+            // 跳过打击乐的第10通道
+            let channel = match index % 15 {
+                c if c >= 9 => c + 1,
+                c => c,
+            } as u8;
+            chunk(&mut out, notes(score, channel, options));
+        }
+    }
+    out
+}
+
+/// 一个声部的音符事件，以结束事件结尾
+fn notes(score: &Score, channel: u8, options: &MidiOptions) -> Vec<u8> {
+    let rhythm = score.events.iter().any(|event| match event {
+        Event::Note { duration, .. } => *duration != DIVISIONS,
+        Event::Rest { .. } => true,
+        Event::Bar => false,
+    });
+    let length = |duration: u32| {
+        let duration = if rhythm { duration } else { options.note_length };
+        duration * TICKS_PER_QUARTER as u32 / DIVISIONS
+    };
+
+    let mut track = vec![0, 0xc0 | channel, RECORDER_PROGRAM];
+    let mut delta = 0;
+    for event in &score.events {
+        match event {
+            Event::Note { tone, duration, .. } => {
+                let midi = tone.midi();
+                write_vlq(&mut track, delta);
+                track.extend_from_slice(&[0x90 | channel, midi, VELOCITY]);
+                write_vlq(&mut track, length(*duration));
+                track.extend_from_slice(&[0x80 | channel, midi, 0]);
+                delta = 0;
+            }
+            Event::Rest { duration } => delta += length(*duration),
+            Event::Bar => {}
+        }
+    }
+    write_vlq(&mut track, delta);
+    track.extend_from_slice(&[0xff, 0x2f, 0]);
+    track
+}
+
+fn header(out: &mut Vec<u8>, format: u16, tracks: u16) {
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&format.to_be_bytes());
+    out.extend_from_slice(&tracks.to_be_bytes());
+    out.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+}
+
+fn chunk(out: &mut Vec<u8>, track: Vec<u8>) {
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    out.extend(track);
+}
+
+/// 变长数值，每字节7位，最高位表示后面还有
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// 读取错误
+#[derive(Debug, PartialEq)]
+pub struct MidiError(String);
+
+impl Display for MidiError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for MidiError {}
+
+/// 导入设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiImportOptions {
+    /// 简谱的调，默认使用文件里的调号，没有调号时按音高分布推测
+    pub finger_tone: Option<FingerTone>,
+    /// 只读第几个音轨（从1开始），默认合并所有音轨
+    pub track: Option<usize>,
+    /// 量化网格，例如`16`为十六分音符
+    pub quantize: u32,
+    /// 整体移高几个八度，默认自动移到竖笛能吹的音域
+    pub octave: Option<i8>,
+}
+
+impl Default for MidiImportOptions {
+    fn default() -> Self {
+        Self {
+            finger_tone: None,
+            track: None,
+            quantize: 16,
+            octave: None,
+        }
+    }
+}
+
+/// 一个音符，时间单位为tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Note {
+    start: u32,
+    end: u32,
+    key: u8,
+}
+
+/// 音轨
+#[derive(Debug, Default)]
+struct Track {
+    name: Option<String>,
+    notes: Vec<Note>,
+}
+
+/// 整个文件里第一次出现的速度、拍号和调号
+#[derive(Debug, Default)]
+struct Meta {
+    /// 每个四分音符的微秒数
+    tempo: Option<u32>,
+    meter: Option<Meter>,
+    fifths: Option<i8>,
+}
+
+/// 读MIDI文件并转换为单声部乐谱
+pub fn read_midi(data: &[u8], options: &MidiImportOptions) -> Result<Score, MidiError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err(MidiError("不是MIDI文件".to_owned()));
+    }
+    let length = reader.u32()? as usize;
+    let header = reader.take(length)?;
+    if header.len() < 6 {
+        return Err(MidiError("文件头有误".to_owned()));
+    }
+    let count = u16::from_be_bytes([header[2], header[3]]) as usize;
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(MidiError("不支持SMPTE时间格式".to_owned()));
+    }
+
+    let mut meta = Meta::default();
+    let mut tracks = Vec::new();
+    while tracks.len() < count && reader.pos < data.len() {
+        let id = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let chunk = reader.take(length)?;
+        // 忽略不认识的块
+        if id == b"MTrk" {
+            tracks.push(read_track(chunk, &mut meta)?);
+        }
+    }
+
+    let notes = match options.track {
+        Some(index) => {
+            let track = index.checked_sub(1).and_then(|index| tracks.get(index)).ok_or_else(|| {
+                let list = tracks.iter().enumerate().map(|(index, track)| {
+                    format!("{}. {}（{}个音符）", index + 1, track.name.as_deref().unwrap_or(""), track.notes.len())
+                }).collect::<Vec<_>>().join("，");
+                MidiError(format!("没有第{}个音轨，可选的音轨：{}", index, list))
+            })?;
+            track.notes.clone()
+        }
+        None => tracks.iter().flat_map(|track| track.notes.iter().copied()).collect(),
+    };
+    if notes.is_empty() {
+        return Err(MidiError("没有音符".to_owned()));
+    }
+
+    // 换算成乐谱的时值单位并量化
+    let grid = (DIVISIONS * 4 / options.quantize.max(1)).max(1);
+    let scale = |tick: u32| {
+        let time = (tick as u64 * DIVISIONS as u64 + division as u64 / 2) / division as u64;
+        ((time as u32 + grid / 2) / grid) * grid
+    };
+    let notes = notes.into_iter().map(|note| {
+        let start = scale(note.start);
+        Note { start, end: scale(note.end).max(start + grid), key: note.key }
+    }).collect();
+    let notes = skyline(notes);
+
+    let meter = meta.meter;
+    let finger_tone = match (options.finger_tone, meta.fifths) {
+        (Some(finger_tone), _) => finger_tone,
+        (None, Some(fifths)) => FingerTone::from_fifths(fifths),
+        (None, None) => detect_key(&notes),
+    };
+    let shift = match options.octave {
+        Some(octave) => 12 * octave as i32,
+        None => fit_octave(notes.iter().map(|note| note.key as i32)),
+    };
+
+    // 开头整小节的休止去掉
+    let measure = meter.unwrap_or(DEFAULT_METER);
+    let measure = measure.beats as u32 * DIVISIONS * 4 / measure.beat_type as u32;
+    let mut time = notes[0].start / measure * measure;
+    let mut events = Events { events: Vec::new(), measure, position: 0 };
+    for note in &notes {
+        if note.start > time {
+            events.push(None, note.start - time);
+        }
+        let tone = u8::try_from(note.key as i32 + shift).ok().and_then(Tone::from_midi)
+            .ok_or_else(|| MidiError(format!("音高超出范围：MIDI {}", note.key as i32 + shift)))?;
+        events.push(Some(tone), note.end - note.start);
+        time = note.end;
+    }
+
+    Ok(Score {
+        finger_tone,
+        meter,
+        tempo: meta.tempo.map(|micros| (60_000_000 + micros / 2) / micros.max(1)),
+        events: events.events,
+    })
+}
+
+/// 按小节线拆开事件
+struct Events {
+    events: Vec<Event>,
+    measure: u32,
+    /// 在当前小节里的位置
+    position: u32,
+}
+
+impl Events {
+    fn push(&mut self, tone: Option<Tone>, duration: u32) {
+        let mut rest = duration;
+        while rest > 0 {
+            let duration = rest.min(self.measure - self.position);
+            self.events.push(match tone {
+                Some(tone) => Event::Note { tone, duration, lyrics: Vec::new() },
+                None => Event::Rest { duration },
+            });
+            rest -= duration;
+            self.position += duration;
+            if self.position == self.measure {
+                self.events.push(Event::Bar);
+                self.position = 0;
+            }
+        }
+    }
+}
+
+fn read_track(data: &[u8], meta: &mut Meta) -> Result<Track, MidiError> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut track = Track::default();
+    let mut tick = 0;
+    let mut status = 0;
+    // 正在发声的音：(通道, 音高, 开始时间)
+    let mut sounding: Vec<(u8, u8, u32)> = Vec::new();
+
+    while reader.pos < data.len() {
+        tick += reader.vlq()?;
+        let byte = reader.byte()?;
+        match byte {
+            0xff => {
+                let r#type = reader.byte()?;
+                let length = reader.vlq()? as usize;
+                let value = reader.take(length)?;
+                match (r#type, value) {
+                    (0x2f, _) => break,
+                    (0x03, _) if track.name.is_none() => track.name = Some(String::from_utf8_lossy(value).into_owned()),
+                    (0x51, &[a, b, c]) if meta.tempo.is_none() => meta.tempo = Some(u32::from_be_bytes([0, a, b, c])),
+                    // 0拍的拍号当作没有拍号
+                    (0x58, &[beats, power, ..]) if meta.meter.is_none() && beats > 0 && power < 8 => {
+                        meta.meter = Some(Meter { beats, beat_type: 1 << power });
+                    }
+                    (0x59, &[fifths, ..]) if meta.fifths.is_none() => meta.fifths = Some(fifths as i8),
+                    _ => {}
+                }
+            }
+            0xf0 | 0xf7 => {
+                let length = reader.vlq()? as usize;
+                reader.take(length)?;
+            }
+            _ => {
+                // 没有状态字节时沿用上一个状态
+                let first = if byte & 0x80 != 0 {
+                    status = byte;
+                    reader.byte()?
+                } else if status != 0 {
+                    byte
+                } else {
+                    return Err(MidiError("音轨数据有误".to_owned()));
+                };
+                let channel = status & 0x0f;
+                let second = match status & 0xf0 {
+                    0xc0 | 0xd0 => 0,
+                    _ => reader.byte()?,
+                };
+                match (status & 0xf0, second) {
+                    // 打击乐通道
+                    _ if channel == 9 => {}
+                    (0x90, velocity) if velocity > 0 => sounding.push((channel, first, tick)),
+                    (0x80, _) | (0x90, _) => {
+                        if let Some(index) = sounding.iter().position(|&(c, k, _)| c == channel && k == first) {
+                            let (_, key, start) = sounding.remove(index);
+                            track.notes.push(Note { start, end: tick, key });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // 没有关闭的音到音轨结束为止
+    for (_, key, start) in sounding {
+        track.notes.push(Note { start, end: tick, key });
+    }
+    Ok(track)
+}
+
+/// 只保留最高的声音：高音开始时截断正在发声的低音，低音在高音发声时开始的话忽略
+fn skyline(mut notes: Vec<Note>) -> Vec<Note> {
+    notes.sort_by_key(|note| (note.start, std::cmp::Reverse(note.key)));
+    let mut result: Vec<Note> = Vec::new();
+    for note in notes {
+        match result.last_mut() {
+            Some(last) if last.end > note.start => {
+                if note.key > last.key {
+                    last.end = note.start;
+                    if last.end <= last.start {
+                        result.pop();
+                    }
+                    result.push(note);
+                }
+            }
+            _ => result.push(note),
+        }
+    }
+    result
+}
+
+/// 按各音级的总时值和Krumhansl-Schmuckler调性轮廓推测调，小调换成同调号的大调
+fn detect_key(notes: &[Note]) -> FingerTone {
+    const MAJOR: [f64; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+    const MINOR: [f64; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+    let mut weights = [0.0; 12];
+    for note in notes {
+        weights[note.key as usize % 12] += (note.end - note.start) as f64;
+    }
+
+    let correlation = |profile: &[f64; 12], tonic: usize| {
+        let mean_x = weights.iter().sum::<f64>() / 12.0;
+        let mean_y = profile.iter().sum::<f64>() / 12.0;
+        let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+        for (pc, weight) in weights.iter().enumerate() {
+            let x = weight - mean_x;
+            let y = profile[(pc + 12 - tonic) % 12] - mean_y;
+            xy += x * y;
+            xx += x * x;
+            yy += y * y;
+        }
+        if xx == 0.0 { 0.0 } else { xy / (xx * yy).sqrt() }
+    };
+
+    let mut best = (f64::MIN, 0);
+    for tonic in 0..12 {
+        for (profile, major) in [(&MAJOR, tonic), (&MINOR, (tonic + 3) % 12)] {
+            let score = correlation(profile, tonic);
+            // 同分时取先出现的大调
+            if score > best.0 {
+                best = (score, major);
+            }
+        }
+    }
+    FingerTone::ALL[best.1]
+}
+
+/// 按字节读取，越界时返回错误
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], MidiError> {
+        let end = self.pos.checked_add(length).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| MidiError("文件不完整".to_owned()))?;
+        let value = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, MidiError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiError("变长数值有误".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::score::MEASURES_PER_LINE;
+
+    #[test]
+    fn test_vlq() {
+        let vlq = |value| {
+            let mut out = Vec::new();
+            write_vlq(&mut out, value);
+            out
+        };
+        assert_eq!(vlq(0), vec![0]);
+        assert_eq!(vlq(0x7f), vec![0x7f]);
+        assert_eq!(vlq(0x80), vec![0x81, 0]);
+        assert_eq!(vlq(480), vec![0x83, 0x60]);
+        assert_eq!(vlq(0x0fff_ffff), vec![0xff, 0xff, 0xff, 0x7f]);
+    }
+
+    #[test]
+    fn test_write_midi() {
+        let score = Score::parse("1=C 2/4 ♩=100\n1_ 0_ 2 |", None).unwrap();
+        assert_eq!(write_midi(&[score], &MidiOptions::default()), vec![
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xe0,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 47,
+            0, 0xff, 0x58, 4, 2, 2, 24, 8,
+            0, 0xff, 0x59, 2, 0, 0,
+            0, 0xff, 0x51, 3, 0x09, 0x27, 0xc0,
+            0, 0xc0, 74,
+            0, 0x90, 72, 80, 0x81, 0x70, 0x80, 72, 0,
+            0x81, 0x70, 0x90, 74, 80, 0x83, 0x60, 0x80, 74, 0,
+            0, 0xff, 0x2f, 0,
+        ]);
+    }
+
+    #[test]
+    fn test_note_length() {
+        // 没有节奏时用默认时值，有节奏时忽略默认时值
+        let options = MidiOptions { tempo: 60, note_length: DIVISIONS / 2 };
+        let plain = write_midi(&[Score::parse("1=C\n1 2", None).unwrap()], &options);
+        assert_eq!(&plain[plain.len() - 12..], &[0x90, 74, 80, 0x81, 0x70, 0x80, 74, 0, 0, 0xff, 0x2f, 0]);
+        let rhythm = write_midi(&[Score::parse("1=C\n1 2 -", None).unwrap()], &options);
+        assert_eq!(&rhythm[rhythm.len() - 12..], &[0x90, 74, 80, 0x87, 0x40, 0x80, 74, 0, 0, 0xff, 0x2f, 0]);
+        assert!(plain.windows(6).any(|w| w == [0xff, 0x51, 3, 0x0f, 0x42, 0x40]));
+    }
+
+    #[test]
+    fn test_slow_tempo() {
+        let slow = write_midi(&[Score::parse("1=C ♩=3\n1", None).unwrap()], &MidiOptions::default());
+        assert!(slow.windows(6).any(|w| w == [0xff, 0x51, 3, 0xff, 0xff, 0xff]));
+        let slow = write_midi(&[Score::parse("1=C ♩=4\n1", None).unwrap()], &MidiOptions::default());
+        assert!(slow.windows(6).any(|w| w == [0xff, 0x51, 3, 0xe4, 0xe1, 0xc0]));
+    }
+
+    #[test]
+    fn test_type_1() {
+        let part = Score::parse("1=G\n1", None).unwrap();
+        let midi = write_midi(&[part.clone(), part], &MidiOptions::default());
+        assert_eq!(&midi[8..12], &[0, 1, 0, 3]);
+        assert_eq!(midi.windows(4).filter(|w| *w == b"MTrk").count(), 3);
+        assert!(midi.windows(3).any(|w| w == [0, 0xc1, 74]));
+    }
+
+    #[test]
+    fn test_read_midi() {
+        let content = "1=D 3/4 ♩=90\n(7)_ 1_ 2 3 | 0 [1]. 7_ | 6 - - |\n";
+        let score = Score::parse(content, None).unwrap();
+        let midi = write_midi(std::slice::from_ref(&score), &MidiOptions::default());
+        assert_eq!(read_midi(&midi, &MidiImportOptions::default()).unwrap(), score);
+        assert_eq!(read_midi(&midi, &MidiImportOptions::default()).unwrap().to_jianpu(MEASURES_PER_LINE).unwrap(), content);
+
+        assert!(read_midi(b"MThd", &MidiImportOptions::default()).is_err());
+        assert!(read_midi(&midi[..midi.len() - 3], &MidiImportOptions::default()).is_err());
+        let error = read_midi(&midi, &MidiImportOptions { track: Some(2), ..Default::default() }).unwrap_err();
+        assert_eq!(error.to_string(), "没有第2个音轨，可选的音轨：1. （7个音符）");
+    }
+
+    #[test]
+    fn test_skyline() {
+        let note = |start, end, key| Note { start, end, key };
+        let notes = vec![note(0, 48, 60), note(0, 48, 64), note(24, 96, 72), note(48, 72, 62), note(96, 120, 60)];
+        assert_eq!(skyline(notes), vec![note(0, 24, 64), note(24, 96, 72), note(96, 120, 60)]);
+    }
+
+    #[test]
+    fn test_quantize_and_bars() {
+        // 手写的轨道：没有调号，音符时值不整齐，第二个音跨过小节线
+        let mut track = vec![0, 0xff, 0x58, 4, 2, 2, 24, 8];
+        for &(delta, key, length) in &[(0u8, 67u8, 0x10u8), (2, 69, 0x40)] {
+            track.extend_from_slice(&[delta, 0x90, key, 90]);
+            track.extend_from_slice(&[0x81, length, key, 0]);
+        }
+        track.extend_from_slice(&[0, 0xff, 0x2f, 0]);
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        data.extend(track);
+
+        let score = read_midi(&data, &MidiImportOptions { finger_tone: Some(FingerTone::G), ..Default::default() }).unwrap();
+        assert_eq!(score.to_jianpu(MEASURES_PER_LINE).unwrap(), "1=G 2/4\n1. 2_ | 2. |\n");
+    }
+
+    #[test]
+    fn test_zero_beats() {
+        let mut track = vec![0, 0xff, 0x58, 4, 0, 2, 24, 8];
+        track.extend_from_slice(&[0, 0x90, 72, 90, 0x60, 72, 0, 0, 0xff, 0x2f, 0]);
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        data.extend(track);
+
+        let score = read_midi(&data, &MidiImportOptions { finger_tone: Some(FingerTone::C), ..Default::default() }).unwrap();
+        assert_eq!(score.meter, None);
+        assert_eq!(score.to_jianpu(MEASURES_PER_LINE).unwrap(), "1=C\n1 |\n");
+    }
+
+    #[test]
+    fn test_detect_key() {
+        let scale = |keys: &[u8]| keys.iter().enumerate().map(|(i, &key)| Note { start: i as u32 * 48, end: i as u32 * 48 + 48, key }).collect::<Vec<_>>();
+        assert_eq!(detect_key(&scale(&[62, 64, 66, 67, 69, 71, 73, 74, 69, 66, 62])), FingerTone::D);
+        assert_eq!(detect_key(&scale(&[65, 67, 69, 70, 72, 74, 76, 77, 72, 69, 65])), FingerTone::F);
+        // E小调按G大调记
+        assert_eq!(detect_key(&scale(&[64, 66, 67, 69, 71, 72, 74, 76, 71, 67, 64, 64])), FingerTone::G);
+    }
+}
@@ -0,0 +1,617 @@
+//! MusicXML导入导出
+//!
+//! 输出MusicXML 3.1的partwise格式，可以用MuseScore等打谱软件打开。
+//! 高音竖笛的实际音高比记谱高一个八度，用带`clef-octave-change`的高音谱号表示，`<pitch>`写实际音高。
+//!
+//! 导入时只读一个声部（part）里的一个声音（voice），和弦和多个声音需要指定读哪个声音。
+
+use crate::notation::parser::Meter;
+use crate::score::{fit_octave, Event, Score, DEFAULT_METER, DIVISIONS};
+use crate::svg::escape;
+use crate::tone::{FingerTone, Tone};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+use std::io::{Cursor, Read};
+
+/// 音符类型和对应的时值
+const NOTE_TYPES: [(u32, &str); 7] = [
+    (DIVISIONS * 4, "whole"),
+    (DIVISIONS * 2, "half"),
+    (DIVISIONS, "quarter"),
+    (DIVISIONS / 2, "eighth"),
+    (DIVISIONS / 4, "16th"),
+    (DIVISIONS / 8, "32nd"),
+    (DIVISIONS / 16, "64th"),
+];
+
+/// 乐谱转换为MusicXML
+pub fn write_musicxml(score: &Score, title: &str) -> String {
+    let mut out = String::new();
+    let flats = score.finger_tone.fifths() < 0;
+    let meter = score.meter.unwrap_or(DEFAULT_METER);
+
+    let _ = write!(
+        out,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 3.1 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="3.1">
+  <work><work-title>{}</work-title></work>
+  <part-list>
+    <score-part id="P1">
+      <part-name>Recorder</part-name>
+      <score-instrument id="P1-I1"><instrument-name>Recorder</instrument-name></score-instrument>
+      <midi-instrument id="P1-I1"><midi-channel>1</midi-channel><midi-program>75</midi-program></midi-instrument>
+    </score-part>
+  </part-list>
+  <part id="P1">
+"#,
+        escape(title)
+    );
+
+    let mut measures = score.measures();
+    if measures.is_empty() {
+        measures.push(Vec::new());
+    }
+    let count = measures.len();
+
+    for (index, measure) in measures.iter().enumerate() {
+        let _ = writeln!(out, r#"    <measure number="{}">"#, index + 1);
+        if index == 0 {
+            let _ = writeln!(
+                out,
+                "      <attributes><divisions>{}</divisions><key><fifths>{}</fifths></key>\
+<time><beats>{}</beats><beat-type>{}</beat-type></time>\
+<clef><sign>G</sign><line>2</line><clef-octave-change>1</clef-octave-change></clef></attributes>",
+                DIVISIONS, score.finger_tone.fifths(), meter.beats, meter.beat_type
+            );
+            if let Some(tempo) = score.tempo {
+                let _ = writeln!(
+                    out,
+                    r#"      <direction placement="above"><direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>{}</per-minute></metronome></direction-type><sound tempo="{}"/></direction>"#,
+                    tempo, tempo
+                );
+            }
+        }
+
+        if measure.is_empty() {
+            let _ = writeln!(out, r#"      <note><rest measure="yes"/><duration>{}</duration><voice>1</voice></note>"#, score.measure_duration());
+        }
+        for event in measure {
+            write_event(&mut out, event, flats);
+        }
+
+        if index + 1 == count {
+            out.push_str(r#"      <barline location="right"><bar-style>light-heavy</bar-style></barline>"#);
+            out.push('\n');
+        }
+        out.push_str("    </measure>\n");
+    }
+
+    out.push_str("  </part>\n</score-partwise>\n");
+    out
+}
+
+fn write_event(out: &mut String, event: &Event, flats: bool) {
+    let values = note_values(event.duration());
+    let count = values.len();
+
+    for (index, (duration, r#type, dot)) in values.into_iter().enumerate() {
+        out.push_str("      <note>");
+        let (tie_start, tie_stop) = (index + 1 < count, index > 0);
+        match event {
+            Event::Note { tone, .. } => {
+                let (step, alter, octave) = tone.spell(flats);
+                out.push_str("<pitch><step>");
+                out.push_str(step);
+                out.push_str("</step>");
+                if alter != 0 {
+                    let _ = write!(out, "<alter>{}</alter>", alter);
+                }
+                let _ = write!(out, "<octave>{}</octave></pitch>", octave);
+            }
+            _ => out.push_str("<rest/>"),
+        }
+        let _ = write!(out, "<duration>{}</duration>", duration);
+        let is_note = matches!(event, Event::Note { .. });
+        if is_note && tie_stop {
+            out.push_str(r#"<tie type="stop"/>"#);
+        }
+        if is_note && tie_start {
+            out.push_str(r#"<tie type="start"/>"#);
+        }
+        let _ = write!(out, "<voice>1</voice><type>{}</type>", r#type);
+        if dot {
+            out.push_str("<dot/>");
+        }
+        if is_note && (tie_start || tie_stop) {
+            out.push_str("<notations>");
+            if tie_stop {
+                out.push_str(r#"<tied type="stop"/>"#);
+            }
+            if tie_start {
+                out.push_str(r#"<tied type="start"/>"#);
+            }
+            out.push_str("</notations>");
+        }
+        if let (Event::Note { lyrics, .. }, 0) = (event, index) {
+            for (verse, lyric) in lyrics.iter().enumerate() {
+                if let Some(text) = lyric {
+                    let _ = write!(out, r#"<lyric number="{}"><syllabic>single</syllabic><text>{}</text></lyric>"#, verse + 1, escape(text));
+                }
+            }
+        }
+        out.push_str("</note>\n");
+    }
+}
+
+/// 把时值拆成能直接记谱的音符（可带附点），拆开的音符用连音线连起来
+pub(crate) fn note_values(duration: u32) -> Vec<(u32, &'static str, bool)> {
+    let mut values = Vec::new();
+    let mut rest = duration;
+
+    while rest > 0 {
+        let value = NOTE_TYPES.iter().find_map(|&(value, name)| {
+            if value % 2 == 0 && value * 3 / 2 <= rest {
+                Some((value * 3 / 2, name, true))
+            } else if value <= rest {
+                Some((value, name, false))
+            } else {
+                None
+            }
+        });
+        match value {
+            Some(value) => {
+                rest -= value.0;
+                values.push(value);
+            }
+            // 比六十四分音符还短的剩余时值并到最后一个音符里
+            None => {
+                match values.last_mut() {
+                    Some(last) => last.0 += rest,
+                    None => values.push((rest, "64th", false)),
+                }
+                break;
+            }
+        }
+    }
+
+    values
+}
+
+/// 导入错误
+#[derive(Debug)]
+pub enum MusicXmlError {
+    Xml(roxmltree::Error),
+    Zip(zip::result::ZipError),
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl Display for MusicXmlError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            MusicXmlError::Xml(e) => write!(f, "XML格式有误：{}", e),
+            MusicXmlError::Zip(e) => write!(f, "MXL文件有误：{}", e),
+            MusicXmlError::Io(e) => e.fmt(f),
+            MusicXmlError::Invalid(s) => s.fmt(f),
+        }
+    }
+}
+
+impl From<roxmltree::Error> for MusicXmlError {
+    fn from(e: roxmltree::Error) -> MusicXmlError {
+        MusicXmlError::Xml(e)
+    }
+}
+
+impl From<zip::result::ZipError> for MusicXmlError {
+    fn from(e: zip::result::ZipError) -> MusicXmlError {
+        MusicXmlError::Zip(e)
+    }
+}
+
+impl From<std::io::Error> for MusicXmlError {
+    fn from(e: std::io::Error) -> MusicXmlError {
+        MusicXmlError::Io(e)
+    }
+}
+
+impl Error for MusicXmlError {}
+
+/// 导入设置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportOptions {
+    /// 简谱的调，默认使用乐谱的调号
+    pub finger_tone: Option<FingerTone>,
+    /// 读第几个声部（从1开始），只有一个声部时可以不指定
+    pub part: Option<usize>,
+    /// 读哪个声音（`<voice>`），和弦只取最高音
+    pub voice: Option<String>,
+    /// 整体移高几个八度，默认自动移到竖笛能吹的音域
+    pub octave: Option<i8>,
+}
+
+/// 读取前的音符，音高为MIDI编号
+enum RawEvent {
+    Note { midi: i32, duration: u32, lyrics: Vec<Option<String>> },
+    Rest { duration: u32 },
+    Bar,
+}
+
+/// 解析MusicXML（partwise格式）
+pub fn read_musicxml(xml: &str, options: &ImportOptions) -> Result<Score, MusicXmlError> {
+    let options_xml = ParsingOptions { allow_dtd: true, ..Default::default() };
+    let doc = Document::parse_with_options(xml, options_xml)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "score-partwise" {
+        return Err(MusicXmlError::Invalid("只支持score-partwise格式的MusicXML".to_owned()));
+    }
+
+    let parts = children(root, "part").collect::<Vec<_>>();
+    let part = match (options.part, parts.len()) {
+        (_, 0) => return Err(MusicXmlError::Invalid("乐谱中没有声部".to_owned())),
+        (None, 1) => parts[0],
+        (None, _) => {
+            let names = children(root, "part-list")
+                .flat_map(|list| children(list, "score-part"))
+                .enumerate()
+                .map(|(index, part)| format!("{}. {}", index + 1, child_text(part, "part-name").unwrap_or_default()))
+                .collect::<Vec<_>>();
+            return Err(MusicXmlError::Invalid(format!("乐谱有多个声部，请指定读哪个：{}", names.join("，"))));
+        }
+        (Some(index), len) => *parts.get(index.wrapping_sub(1))
+            .ok_or_else(|| MusicXmlError::Invalid(format!("声部编号应为1到{}", len)))?,
+    };
+
+    let mut divisions = 1;
+    let mut transpose = 0;
+    let mut fifths = None;
+    let mut meter = None;
+    let mut tempo = None;
+    let mut voices = BTreeSet::new();
+    let mut events = Vec::new();
+
+    for (number, measure) in children(part, "measure").enumerate() {
+        let number = measure.attribute("number").map(str::to_owned).unwrap_or_else(|| (number + 1).to_string());
+        let measure_start = events.len();
+
+        for element in measure.children().filter(Node::is_element) {
+            match element.tag_name().name() {
+                "attributes" => {
+                    if let Some(value) = child_text(element, "divisions") {
+                        divisions = parse_number(&value, "divisions")?;
+                    }
+                    if let Some(key) = children(element, "key").next() {
+                        if fifths.is_none() {
+                            fifths = child_text(key, "fifths").and_then(|s| s.parse::<i8>().ok());
+                        }
+                    }
+                    if let Some(time) = children(element, "time").next() {
+                        if meter.is_none() {
+                            meter = match (child_text(time, "beats").and_then(|s| s.parse().ok()), child_text(time, "beat-type").and_then(|s| s.parse().ok())) {
+                                (Some(beats), Some(beat_type)) => Some(Meter { beats, beat_type }),
+                                _ => None,
+                            };
+                        }
+                    }
+                    if let Some(node) = children(element, "transpose").next() {
+                        let chromatic = child_text(node, "chromatic").and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
+                        let octave = child_text(node, "octave-change").and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
+                        transpose = chromatic + 12 * octave;
+                    }
+                }
+                "direction" | "sound" => {
+                    let sound = if element.tag_name().name() == "sound" { Some(element) } else { children(element, "sound").next() };
+                    if let (None, Some(value)) = (tempo, sound.and_then(|sound| sound.attribute("tempo"))) {
+                        tempo = value.parse::<f32>().ok().map(|tempo| tempo.round() as u32);
+                    }
+                }
+                "forward" => {
+                    let voice = child_text(element, "voice");
+                    if options.voice.is_some() && voice == options.voice {
+                        let duration = scale(parse_number(&child_text(element, "duration").unwrap_or_default(), "duration")?, divisions, &number)?;
+                        events.push(RawEvent::Rest { duration });
+                    }
+                }
+                "note" => {
+                    if has_child(element, "grace") || has_child(element, "cue") {
+                        continue;
+                    }
+                    let voice = child_text(element, "voice").unwrap_or_else(|| "1".to_owned());
+                    match &options.voice {
+                        Some(selected) if *selected != voice => continue,
+                        Some(_) => {}
+                        None => {
+                            voices.insert(voice);
+                        }
+                    }
+
+                    let duration = scale(parse_number(&child_text(element, "duration").unwrap_or_default(), "duration")?, divisions, &number)?;
+                    let midi = children(element, "pitch").next().map(|pitch| -> Result<i32, MusicXmlError> {
+                        let step = match child_text(pitch, "step").as_deref() {
+                            Some("C") => 0,
+                            Some("D") => 2,
+                            Some("E") => 4,
+                            Some("F") => 5,
+                            Some("G") => 7,
+                            Some("A") => 9,
+                            Some("B") => 11,
+                            _ => return Err(MusicXmlError::Invalid(format!("第{}小节：音名有误", number))),
+                        };
+                        let alter = child_text(pitch, "alter").and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0).round() as i32;
+                        let octave = parse_number(&child_text(pitch, "octave").unwrap_or_default(), "octave")? as i32;
+                        Ok((octave + 1) * 12 + step + alter + transpose)
+                    }).transpose()?;
+
+                    if has_child(element, "chord") {
+                        if options.voice.is_none() {
+                            return Err(MusicXmlError::Invalid(format!("第{}小节有和弦，竖笛只能吹单音，请指定读哪个声音", number)));
+                        }
+                        // 和弦只取最高音
+                        if let (Some(RawEvent::Note { midi: last, .. }), Some(midi)) = (events.last_mut(), midi) {
+                            *last = (*last).max(midi);
+                        }
+                        continue;
+                    }
+
+                    let lyrics = lyrics(element);
+                    let tie_stop = children(element, "tie").any(|tie| tie.attribute("type") == Some("stop"));
+                    let in_measure = events.len() > measure_start;
+                    match (midi, events.last_mut()) {
+                        // 小节内的连音线合并成一个音符
+                        (Some(midi), Some(RawEvent::Note { midi: last, duration: last_duration, .. }))
+                            if tie_stop && in_measure && *last == midi => *last_duration += duration,
+                        (Some(midi), _) => events.push(RawEvent::Note { midi, duration, lyrics }),
+                        (None, _) => events.push(RawEvent::Rest { duration }),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        events.push(RawEvent::Bar);
+    }
+
+    if voices.len() > 1 {
+        let voices = voices.into_iter().collect::<Vec<_>>();
+        return Err(MusicXmlError::Invalid(format!("声部有多个声音，竖笛只能吹单音，请指定读哪个声音：{}", voices.join("，"))));
+    }
+
+    let shift = match options.octave {
+        Some(octave) => 12 * octave as i32,
+        None => fit_octave(events.iter().filter_map(|event| match event {
+            RawEvent::Note { midi, .. } => Some(*midi),
+            _ => None,
+        })),
+    };
+
+    let events = events.into_iter().map(|event| match event {
+        RawEvent::Note { midi, duration, lyrics } => {
+            let tone = u8::try_from(midi + shift).ok().and_then(Tone::from_midi)
+                .ok_or_else(|| MusicXmlError::Invalid(format!("音高超出范围：MIDI {}", midi + shift)))?;
+            Ok(Event::Note { tone, duration, lyrics })
+        }
+        RawEvent::Rest { duration } => Ok(Event::Rest { duration }),
+        RawEvent::Bar => Ok(Event::Bar),
+    }).collect::<Result<Vec<_>, MusicXmlError>>()?;
+
+    Ok(Score {
+        finger_tone: options.finger_tone.unwrap_or_else(|| FingerTone::from_fifths(fifths.unwrap_or(0))),
+        meter,
+        tempo,
+        events,
+    })
+}
+
+/// 从MXL压缩包中取出MusicXML
+pub fn read_mxl(data: &[u8]) -> Result<String, MusicXmlError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+
+    let mut path = None;
+    if let Ok(mut file) = archive.by_name("META-INF/container.xml") {
+        let mut container = String::new();
+        file.read_to_string(&mut container)?;
+        let doc = Document::parse(&container)?;
+        path = doc.descendants()
+            .find(|node| node.has_tag_name("rootfile"))
+            .and_then(|node| node.attribute("full-path"))
+            .map(str::to_owned);
+    }
+    let path = path.or_else(|| {
+        archive.file_names()
+            .find(|name| !name.starts_with("META-INF/") && (name.ends_with(".xml") || name.ends_with(".musicxml")))
+            .map(str::to_owned)
+    }).ok_or_else(|| MusicXmlError::Invalid("MXL文件中没有乐谱".to_owned()))?;
+
+    let mut xml = String::new();
+    archive.by_name(&path)?.read_to_string(&mut xml)?;
+    Ok(xml)
+}
+
+fn lyrics(note: Node) -> Vec<Option<String>> {
+    let mut lyrics = Vec::new();
+    for (index, lyric) in children(note, "lyric").enumerate() {
+        let verse = lyric.attribute("number").and_then(|s| s.parse::<usize>().ok()).unwrap_or(index + 1).max(1) - 1;
+        if let Some(text) = child_text(lyric, "text") {
+            if lyrics.len() <= verse {
+                lyrics.resize(verse + 1, None);
+            }
+            lyrics[verse] = Some(text.split_whitespace().collect());
+        }
+    }
+    lyrics
+}
+
+/// 换算成`DIVISIONS`为单位的时值，短得换算成0或者长得溢出时报错
+fn scale(duration: u32, divisions: u32, number: &str) -> Result<u32, MusicXmlError> {
+    let scaled = duration.checked_mul(DIVISIONS)
+        .map(|value| (value + divisions / 2) / divisions.max(1))
+        .ok_or_else(|| MusicXmlError::Invalid(format!("第{}小节：时值太长：{}", number, duration)))?;
+    if scaled == 0 {
+        return Err(MusicXmlError::Invalid(format!("第{}小节：时值太短：{}/{}拍", number, duration, divisions)));
+    }
+    Ok(scaled)
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+fn has_child(node: Node, name: &'static str) -> bool {
+    children(node, name).next().is_some()
+}
+
+fn child_text(node: Node, name: &'static str) -> Option<String> {
+    children(node, name).next().and_then(|child| child.text()).map(|text| text.trim().to_owned())
+}
+
+fn parse_number(s: &str, name: &str) -> Result<u32, MusicXmlError> {
+    s.parse().map_err(|_| MusicXmlError::Invalid(format!("{}有误：{:?}", name, s)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::score::MEASURES_PER_LINE;
+    use crate::tone::FingerTone;
+
+    #[test]
+    fn test_note_values() {
+        assert_eq!(note_values(48), vec![(48, "quarter", false)]);
+        assert_eq!(note_values(72), vec![(72, "quarter", true)]);
+        assert_eq!(note_values(144), vec![(144, "half", true)]);
+        assert_eq!(note_values(240), vec![(192, "whole", false), (48, "quarter", false)]);
+        assert_eq!(note_values(9), vec![(9, "32nd", true)]);
+    }
+
+    #[test]
+    fn test_write_musicxml() {
+        let score = Score::parse("1=F 3/4 ♩=72\n1 2_ 3_ 4 | 5 - - |\nw: 你 & 好", None).unwrap();
+        let xml = write_musicxml(&score, "<测试>");
+        assert!(xml.contains("<work-title>&lt;测试&gt;</work-title>"));
+        assert!(xml.contains("<fifths>-1</fifths>"));
+        assert!(xml.contains("<beats>3</beats><beat-type>4</beat-type>"));
+        assert!(xml.contains("<clef-octave-change>1</clef-octave-change>"));
+        assert!(xml.contains(r#"<sound tempo="72"/>"#));
+        assert_eq!(xml.matches("<measure ").count(), 2);
+        // 1=F的4是降B
+        assert!(xml.contains("<step>B</step><alter>-1</alter><octave>5</octave>"));
+        assert!(xml.contains("<duration>144</duration><voice>1</voice><type>half</type><dot/>"));
+        assert!(xml.contains("<text>&amp;</text>"));
+        assert_eq!(xml.matches("<lyric").count(), 3);
+        assert_eq!(xml.matches("light-heavy").count(), 1);
+    }
+
+    #[test]
+    fn test_tie() {
+        let score = Score::parse("1 - - - -", Some(FingerTone::C)).unwrap();
+        let xml = write_musicxml(&score, "");
+        assert_eq!(xml.matches("<note>").count(), 2);
+        assert!(xml.contains(r#"<tie type="start"/>"#));
+        assert!(xml.contains(r#"<tied type="stop"/>"#));
+    }
+
+    fn import(xml: &str, options: &ImportOptions) -> Result<Score, MusicXmlError> {
+        read_musicxml(xml, options)
+    }
+
+    fn part(notes: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?><score-partwise version="3.1"><part-list><score-part id="P1"><part-name>Flute</part-name></score-part></part-list>
+<part id="P1"><measure number="1"><attributes><divisions>2</divisions><key><fifths>2</fifths></key></attributes>{}</measure></part></score-partwise>"#,
+            notes
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let content = "1=G 3/4 ♩=100\n1_ 2_ 3. 4_ | 0 [1] - - - - | (5)__ (6)__ 0_ 7 - |\nw: 一 二 三 * 五\n";
+        let score = Score::parse(content, None).unwrap();
+        let imported = import(&write_musicxml(&score, ""), &ImportOptions::default()).unwrap();
+        assert_eq!(imported, score);
+        assert_eq!(imported.to_jianpu(MEASURES_PER_LINE).unwrap(), "1=G 3/4 ♩=100\n1_ 2_ 3. 4_ | 0 [1] - - - - | (5)__ (6)__ 0_ 7 - |\nw: 一 二 三 * 五\n");
+    }
+
+    #[test]
+    fn test_import() {
+        // 按实际音高写在小字一组的乐谱，自动移高一个八度
+        let xml = part(r#"<note><pitch><step>D</step><octave>4</octave></pitch><duration>1</duration><voice>1</voice><lyric number="1"><text>la</text></lyric></note>
+<note><pitch><step>F</step><alter>1</alter><octave>4</octave></pitch><duration>3</duration><voice>1</voice></note>
+<note><grace/><pitch><step>A</step><octave>4</octave></pitch><voice>1</voice></note>
+<note><rest/><duration>4</duration><voice>1</voice></note>"#);
+        let score = import(&xml, &ImportOptions::default()).unwrap();
+        assert_eq!(score.finger_tone, FingerTone::D);
+        assert_eq!(score.to_jianpu(MEASURES_PER_LINE).unwrap(), "1=D\n1_ 3. 0 0 |\nw: la\n");
+
+        let options = ImportOptions { finger_tone: Some(FingerTone::C), octave: Some(0), ..Default::default() };
+        assert_eq!(import(&xml, &options).unwrap().to_jianpu(MEASURES_PER_LINE).unwrap(), "1=C\n(2)_ (#4). 0 0 |\nw: la\n");
+        let options = ImportOptions { finger_tone: Some(FingerTone::C), octave: Some(1), ..Default::default() };
+        assert_eq!(import(&xml, &options).unwrap().to_jianpu(MEASURES_PER_LINE).unwrap(), "1=C\n2_ #4. 0 0 |\nw: la\n");
+    }
+
+    #[test]
+    fn test_scale() {
+        // 比1/48拍还短的音符
+        let xml = part(r#"<note><pitch><step>D</step><octave>5</octave></pitch><duration>1</duration><voice>1</voice></note>"#)
+            .replace("<divisions>2</divisions>", "<divisions>480</divisions>");
+        let error = import(&xml, &ImportOptions::default()).unwrap_err().to_string();
+        assert_eq!(error, "第1小节：时值太短：1/480拍");
+
+        let xml = part(r#"<note><pitch><step>D</step><octave>5</octave></pitch><duration>4294967295</duration><voice>1</voice></note>"#);
+        let error = import(&xml, &ImportOptions::default()).unwrap_err().to_string();
+        assert_eq!(error, "第1小节：时值太长：4294967295");
+    }
+
+    #[test]
+    fn test_polyphony() {
+        let xml = part(r#"<note><pitch><step>D</step><octave>5</octave></pitch><duration>2</duration><voice>1</voice></note>
+<note><chord/><pitch><step>A</step><octave>5</octave></pitch><duration>2</duration><voice>1</voice></note>
+<backup><duration>2</duration></backup>
+<note><pitch><step>B</step><octave>5</octave></pitch><duration>2</duration><voice>2</voice></note>"#);
+        let error = import(&xml, &ImportOptions::default()).unwrap_err().to_string();
+        assert!(error.contains("和弦"), "{}", error);
+
+        let voice = |voice: &str| ImportOptions { voice: Some(voice.to_owned()), ..Default::default() };
+        assert_eq!(import(&xml, &voice("1")).unwrap().events[0], Event::Note { tone: Tone::A, duration: 48, lyrics: vec![] });
+        assert_eq!(import(&xml, &voice("2")).unwrap().events[0], Event::Note { tone: Tone::B, duration: 48, lyrics: vec![] });
+
+        let xml = part(r#"<note><pitch><step>D</step><octave>5</octave></pitch><duration>2</duration><voice>1</voice></note>
+<backup><duration>2</duration></backup>
+<note><pitch><step>B</step><octave>5</octave></pitch><duration>2</duration><voice>2</voice></note>"#);
+        let error = import(&xml, &ImportOptions::default()).unwrap_err().to_string();
+        assert!(error.contains("1，2"), "{}", error);
+    }
+
+    #[test]
+    fn test_parts() {
+        let xml = r#"<score-partwise><part-list><score-part id="P1"><part-name>S</part-name></score-part><score-part id="P2"><part-name>A</part-name></score-part></part-list>
+<part id="P1"><measure><note><rest/><duration>1</duration></note></measure></part><part id="P2"><measure><note><pitch><step>C</step><octave>5</octave></pitch><duration>1</duration></note></measure></part></score-partwise>"#;
+        let error = import(xml, &ImportOptions::default()).unwrap_err().to_string();
+        assert!(error.contains("1. S，2. A"), "{}", error);
+        let score = import(xml, &ImportOptions { part: Some(2), ..Default::default() }).unwrap();
+        assert_eq!(score.events[0], Event::Note { tone: Tone::C, duration: 48, lyrics: vec![] });
+        assert!(import(xml, &ImportOptions { part: Some(3), ..Default::default() }).is_err());
+        assert!(import("<score-timewise/>", &ImportOptions::default()).is_err());
+        assert!(import("<score", &ImportOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_read_mxl() {
+        use std::io::Write as _;
+        use zip::write::SimpleFileOptions;
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("META-INF/container.xml", options).unwrap();
+        writer.write_all(br#"<container><rootfiles><rootfile full-path="score/a.xml"/></rootfiles></container>"#).unwrap();
+        writer.start_file("score/a.xml", options).unwrap();
+        writer.write_all(b"<score-partwise/>").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        assert_eq!(read_mxl(&data).unwrap(), "<score-partwise/>");
+        assert!(read_mxl(b"not a zip").is_err());
+    }
+}
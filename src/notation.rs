@@ -1,65 +1,135 @@
-use std::collections::HashMap;
-use lazy_static::lazy_static;
+use serde::Serialize;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
-/// 音符列表
-pub const NOTATIONS: [&'static str; 46] = [
-    "((5))", "((#5))", "((6))", "((#6))", "((7))",
-    "(1)", "(#1)", "(2)", "(#2)", "(3)", "(4)", "(#4)", "(5)", "(#5)", "(6)", "(#6)", "(7)",
-    "1", "#1", "2", "#2", "3", "4", "#4", "5", "#5", "6", "#6", "7",
-    "[1]", "[#1]", "[2]", "[#2]", "[3]", "[4]", "[#4]", "[5]", "[#5]", "[6]", "[#6]", "[7]",
-    "[[1]]", "[[#1]]", "[[2]]", "[[#2]]", "[[3]]",
-];
-
-lazy_static! {
-    pub static ref NOTATIONS_MAP: HashMap<&'static str, usize> = {
-        let mut map = HashMap::new();
-        NOTATIONS.iter().enumerate().for_each(|(index, &item)| {
-            map.insert(item, index);
-        });
-        map
-    };
-}
-
-/// C调一号位坐标
-pub const TONE_C_START: usize = 17;
+/// 简谱数字`1`到`7`比`1`高几个半音
+const DEGREE_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum NotationType {
-    LLow,
-    Low,
-    Normal,
-    High,
-    HHigh,
-}
+/// 一个八度里的十二个半音在简谱里的写法，黑键记成升号
+const SEMITONE_DEGREES: [(u8, bool); 12] = [
+    (1, false), (1, true), (2, false), (2, true), (3, false), (4, false),
+    (4, true), (5, false), (5, true), (6, false), (6, true), (7, false),
+];
 
 /// 音符单元
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub struct Notation {
-    r#type: NotationType,
+    /// 八度：`(1)`为-1，`[[1]]`为2，括号的层数不限
+    octave: i8,
     number: u8,
     is_sharp: bool,
+    /// 减时线条数，1为八分音符，2为十六分音符
+    underline: u8,
+    /// 附点
+    dotted: bool,
+}
+
+impl Notation {
+    /// 不带时值的音符，`number`为1到7
+    pub fn new(octave: i8, number: u8, is_sharp: bool) -> Notation {
+        debug_assert!((1..=7).contains(&number));
+        Notation { octave, number, is_sharp, underline: 0, dotted: false }
+    }
+
+    /// 比中音`1`高几个半音的音符，黑键记成升号，例如`#1`、`(#4)`
+    pub fn from_semitones(semitones: i32) -> Notation {
+        let (number, is_sharp) = SEMITONE_DEGREES[semitones.rem_euclid(12) as usize];
+        Notation::new(semitones.div_euclid(12) as i8, number, is_sharp)
+    }
+
+    pub fn octave(&self) -> i8 {
+        self.octave
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn is_sharp(&self) -> bool {
+        self.is_sharp
+    }
+
+    pub fn underline(&self) -> u8 {
+        self.underline
+    }
+
+    pub fn dotted(&self) -> bool {
+        self.dotted
+    }
+
+    /// 比中音`1`高几个半音，`#3`和`4`一样
+    pub fn semitones(&self) -> i32 {
+        self.octave as i32 * 12 + DEGREE_SEMITONES[self.number as usize - 1] + self.is_sharp as i32
+    }
+
+    /// 升高（`semitones`为正）或降低几个半音，时值不变，可以超出竖笛的音域
+    pub fn transpose(&self, semitones: i32) -> Notation {
+        Notation {
+            underline: self.underline,
+            dotted: self.dotted,
+            ..Notation::from_semitones(self.semitones() + semitones)
+        }
+    }
+
+    /// 去掉时值，只保留音高
+    pub fn pitch(&self) -> Notation {
+        Notation {
+            underline: 0,
+            dotted: false,
+            ..*self
+        }
+    }
+
+    /// 时值后缀，例如`_`，`__.`
+    pub fn duration_suffix(&self) -> String {
+        let dot = if self.dotted { "." } else { "" };
+        format!("{}{}", "_".repeat(self.underline as usize), dot)
+    }
+
+    /// 用组合附加符号表示八度的Unicode写法：低音点在下，高音点在上，例如`5̣`，`♯1̇`
+    pub fn to_unicode(&self) -> String {
+        let sharp = if self.is_sharp { "♯" } else { "" };
+        let octave = match self.octave {
+            -3 => "\u{20E8}".to_owned(),
+            -2 => "\u{0324}".to_owned(),
+            -1 => "\u{0323}".to_owned(),
+            0 => String::new(),
+            1 => "\u{0307}".to_owned(),
+            2 => "\u{0308}".to_owned(),
+            3 => "\u{20DB}".to_owned(),
+            // 再多就一个点一个点地叠上去
+            octave if octave < 0 => "\u{0323}".repeat(octave.unsigned_abs() as usize),
+            octave => "\u{0307}".repeat(octave as usize),
+        };
+        let underline = match self.underline {
+            0 => "",
+            1 => "\u{0332}",
+            2 => "\u{0333}",
+            _ => "\u{0333}\u{0332}",
+        };
+        let dot = if self.dotted { "·" } else { "" };
+        format!("{}{}{}{}{}", sharp, self.number, octave, underline, dot)
+    }
 }
 
 impl Display for Notation {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         let sharp = if self.is_sharp { "#" } else { "" };
-        match self.r#type {
-            NotationType::LLow => format!("(({}{}))", sharp, self.number),
-            NotationType::Low => format!("({}{})", sharp, self.number),
-            NotationType::Normal => format!("{}{}", sharp, self.number),
-            NotationType::High => format!("[{}{}]", sharp, self.number),
-            NotationType::HHigh => format!("[[{}{}]]", sharp, self.number),
-        }.fmt(f)
+        let (open, close) = match self.octave {
+            octave if octave < 0 => ("(".repeat(octave.unsigned_abs() as usize), ")".repeat(octave.unsigned_abs() as usize)),
+            octave => ("[".repeat(octave as usize), "]".repeat(octave as usize)),
+        };
+        format!("{}{}{}{}{}", open, sharp, self.number, close, self.duration_suffix()).fmt(f)
     }
 }
 
 pub mod parser {
     use std::fmt::{Display, Formatter, Result as FmtResult};
-    use crate::tone::Tone;
     use std::error::Error;
-    use super::{Notation, NotationType};
+    use super::Notation;
+    use crate::key::Key;
     use std::collections::LinkedList;
+    use std::str::FromStr;
+    use serde::Serialize;
 
     type ParseResult<T> = Result<T, ParseError>;
 
@@ -75,31 +145,142 @@ pub mod parser {
 
     impl Error for ParseError {}
 
-    #[derive(Debug, PartialEq)]
+    /// 减时线最多的条数
+    const MAX_UNDERLINE: u8 = 3;
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(tag = "type", content = "value", rename_all = "lowercase")]
     pub enum Token {
         Notation(Notation),
+        /// 休止符`0`
+        Rest(Rest),
+        /// 增时线
+        Dash,
+        /// 小节线
+        Bar,
+        /// 调号、拍号、速度行，例如`1=D 3/4 ♩=90`
+        Header(Header),
+        /// 歌词行，例如`w: 孟 姜 女`，按顺序对应上一行简谱的音符
+        Lyrics(Vec<String>),
         Raw(String),
         Whitespace,
     }
 
+    impl Token {
+        /// 整行的文字，不是音符
+        pub fn is_text(&self) -> bool {
+            matches!(self, Token::Raw(_) | Token::Header(_) | Token::Lyrics(_))
+        }
+    }
+
     impl Display for Token {
         fn fmt(&self, f: &mut Formatter) -> FmtResult {
             match self {
                 Token::Notation(n) => n.fmt(f),
+                Token::Rest(r) => r.fmt(f),
+                Token::Dash => "-".fmt(f),
+                Token::Bar => "|".fmt(f),
+                Token::Header(h) => h.fmt(f),
+                Token::Lyrics(words) => format!("w: {}", words.join(" ")).fmt(f),
                 Token::Raw(s) => s.fmt(f),
                 Token::Whitespace => " ".fmt(f),
             }
         }
     }
 
+    /// 休止符
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct Rest {
+        pub underline: u8,
+        pub dotted: bool,
+    }
+
+    impl Display for Rest {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            let dot = if self.dotted { "." } else { "" };
+            format!("0{}{}", "_".repeat(self.underline as usize), dot).fmt(f)
+        }
+    }
+
+    /// 拍号
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct Meter {
+        /// 每小节的拍数
+        pub beats: u8,
+        /// 以几分音符为一拍
+        pub beat_type: u8,
+    }
+
+    impl Display for Meter {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}/{}", self.beats, self.beat_type)
+        }
+    }
+
+    /// 调号行：`1=`后面是调名，调性、拍号和速度（每分钟四分音符数）可选
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct Header {
+        pub key: String,
+        /// 调性，例如`1=C Am`的`Am`：简谱的`1`为C，主音为`6`
+        pub tonality: Option<String>,
+        pub meter: Option<Meter>,
+        pub tempo: Option<u32>,
+    }
+
+    impl Header {
+        fn parse(line: &str) -> ParseResult<Self> {
+            let mut items = line.split_whitespace();
+            let key = items.next().unwrap_or_default().trim_start_matches("1=").to_owned();
+            if key.is_empty() {
+                return Err(ParseError("调号行缺少调名".to_owned()));
+            }
+
+            let mut header = Header { key, tonality: None, meter: None, tempo: None };
+            for item in items {
+                if let Some(tempo) = item.strip_prefix("♩=").or_else(|| item.strip_prefix("q=")) {
+                    header.tempo = Some(tempo.parse().map_err(|_| ParseError(format!("速度有误：{}", item)))?);
+                } else if let Some((beats, beat_type)) = item.split_once('/') {
+                    let meter = match (beats.parse(), beat_type.parse()) {
+                        (Ok(beats), Ok(beat_type)) if beats > 0 && [1, 2, 4, 8, 16].contains(&beat_type) => Meter { beats, beat_type },
+                        _ => return Err(ParseError(format!("拍号有误：{}", item))),
+                    };
+                    header.meter = Some(meter);
+                } else if header.tonality.is_none() && item.parse::<Key>().is_ok() {
+                    header.tonality = Some(item.to_owned());
+                } else {
+                    return Err(ParseError(format!("调号行无法识别：{}", item)));
+                }
+            }
+            Ok(header)
+        }
+    }
+
+    impl Display for Header {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "1={}", self.key)?;
+            if let Some(tonality) = &self.tonality {
+                write!(f, " {}", tonality)?;
+            }
+            if let Some(meter) = self.meter {
+                write!(f, " {}", meter)?;
+            }
+            if let Some(tempo) = self.tempo {
+                write!(f, " ♩={}", tempo)?;
+            }
+            Ok(())
+        }
+    }
+
     /// 简单数字谱解析器
     #[derive(Debug, PartialEq)]
     pub struct Parser {
         inner: Vec<Vec<Token>>,
     }
 
-    impl Parser {
-        pub fn from_str(s: &str) -> ParseResult<Self> {
+    impl FromStr for Parser {
+        type Err = ParseError;
+
+        fn from_str(s: &str) -> ParseResult<Self> {
             let mut lines = Vec::new();
 
             for line in s.lines() {
@@ -109,6 +290,10 @@ pub mod parser {
                     // 注释
                     if line.starts_with("//") {
                         vec.push(Token::Raw(line.to_owned()));
+                    } else if line.starts_with("1=") {
+                        vec.push(Token::Header(Header::parse(line)?));
+                    } else if let Some(words) = line.strip_prefix("w:") {
+                        vec.push(Token::Lyrics(words.split_whitespace().map(str::to_owned).collect()));
                     } else {
                         Self::parse_token_item(line, &mut vec)?;
                     }
@@ -120,116 +305,117 @@ pub mod parser {
                 inner: lines,
             })
         }
+    }
+
+    impl FromStr for Notation {
+        type Err = ParseError;
 
+        /// 单个音符，例如`(#5)`、`[[[1]]]`，可以带时值
+        fn from_str(s: &str) -> ParseResult<Self> {
+            let mut tokens = Parser::from_str(s)?.lines().into_iter().flatten();
+            match (tokens.next(), tokens.next()) {
+                (Some(Token::Notation(notation)), None) => Ok(notation),
+                _ => Err(ParseError(format!("不是音符：{}", s))),
+            }
+        }
+    }
+
+    impl Parser {
         fn parse_token_item(line: &str, vec: &mut Vec<Token>) -> ParseResult<()> {
             let mut line: LinkedList<_> = line.chars().collect();
 
             let mut is_sharp = false;
-            let mut is_low = false;
-            let mut is_double_low = false;
-            let mut is_high = false;
-            let mut is_double_high = false;
-
-            loop {
-                match line.pop_front() {
-                    Some(c) => match c {
-                        ' ' => {
-                            vec.push(Token::Whitespace);
+            let mut octave: i8 = 0;
+
+            while let Some(c) = line.pop_front() {
+                match c {
+                    ' ' => {
+                        vec.push(Token::Whitespace);
+                    }
+                    '-' => {
+                        vec.push(Token::Dash);
+                        Self::expect_separator(&mut line)?;
+                    }
+                    '|' => {
+                        vec.push(Token::Bar);
+                        Self::expect_separator(&mut line)?;
+                    }
+                    '#' => {
+                        is_sharp = true;
+                    }
+                    // 低音和高音的括号可以套任意多层，每层一个八度
+                    '(' | '[' => {
+                        let mut depth: i8 = 1;
+                        while line.front() == Some(&c) {
+                            line.pop_front();
+                            depth = depth.checked_add(1).ok_or_else(|| ParseError("括号太多".to_owned()))?;
                         }
-                        '#' => {
-                            is_sharp = true;
+                        if line.is_empty() {
+                            break;
                         }
-                        '(' => match line.pop_front() {
-                            Some('(') => {
-                                is_double_low = true;
-                            }
-                            Some(c) => {
-                                is_low = true;
-                                line.push_front(c);
-                            }
-                            None => break,
+                        octave = if c == '(' { -depth } else { depth };
+                    }
+                    c => {
+                        let x = match c.to_digit(8) {
+                            Some(x) if x <= 7 => x,
+                            _ => return Err(ParseError(format!("未知字符: {:?}", c))),
+                        };
+                        if x == 0 && (is_sharp || octave != 0) {
+                            return Err(ParseError("休止符不能带升号和八度".to_owned()));
                         }
-                        '[' => match line.pop_front() {
-                            Some('[') => {
-                                is_double_high = true;
-                            }
-                            Some(c) => {
-                                is_high = true;
-                                line.push_front(c);
+
+                        let close = if octave < 0 { ')' } else { ']' };
+                        for _ in 0..octave.unsigned_abs() {
+                            if line.pop_front() != Some(close) {
+                                return Err(ParseError(format!("不以{}结尾", close.to_string().repeat(octave.unsigned_abs() as usize))));
                             }
-                            None => break,
                         }
-                        c => {
-                            let x = match c.to_digit(8) {
-                                Some(x) if x >= 1 && x <= 7 => x,
-                                _ => return Err(ParseError(format!("未知字符: {:?}", c))),
-                            };
-
-                            if is_low {
-                                if line.pop_front() != Some(')') {
-                                    return Err(ParseError(format!("不以)结尾")));
-                                }
-                                vec.push(Token::Notation(Notation {
-                                    r#type: NotationType::Low,
-                                    number: x as u8,
-                                    is_sharp,
-                                }));
-                            } else if is_high  {
-                                if line.pop_front() != Some(']') {
-                                    return Err(ParseError(format!("不以]结尾")));
-                                }
-                                vec.push(Token::Notation(Notation {
-                                    r#type: NotationType::High,
-                                    number: x as u8,
-                                    is_sharp,
-                                }));
-                            } else if is_double_low {
-                                if line.pop_front() != Some(')') || line.pop_front() != Some(')') {
-                                    return Err(ParseError(format!("不以))结尾")));
-                                }
-                                vec.push(Token::Notation(Notation {
-                                    r#type: NotationType::LLow,
-                                    number: x as u8,
-                                    is_sharp,
-                                }));
-                            } else if is_double_high {
-                                if line.pop_front() != Some(']') || line.pop_front() != Some(']') {
-                                    return Err(ParseError(format!("不以]]结尾")));
-                                }
-                                vec.push(Token::Notation(Notation {
-                                    r#type: NotationType::HHigh,
-                                    number: x as u8,
-                                    is_sharp,
-                                }));
-                            } else {
-                                vec.push(Token::Notation(Notation {
-                                    r#type: NotationType::Normal,
-                                    number: x as u8,
-                                    is_sharp,
-                                }));
-                            }
 
-                            if let Some(c) = line.pop_front() {
-                                if c != ' ' {
-                                    return Err(ParseError(format!("要以空格隔开")));
-                                }
-                                line.push_front(c);
-                            }
+                        // 时值：`_`为减时线，`.`为附点
+                        let mut underline = 0;
+                        while line.front() == Some(&'_') {
+                            line.pop_front();
+                            underline += 1;
+                        }
+                        if underline > MAX_UNDERLINE {
+                            return Err(ParseError(format!("减时线最多{}条", MAX_UNDERLINE)));
+                        }
+                        let dotted = line.front() == Some(&'.');
+                        if dotted {
+                            line.pop_front();
+                        }
 
-                            is_sharp = false;
-                            is_low = false;
-                            is_double_low = false;
-                            is_high = false;
-                            is_double_high = false;
+                        if x == 0 {
+                            vec.push(Token::Rest(Rest { underline, dotted }));
+                        } else {
+                            vec.push(Token::Notation(Notation {
+                                octave,
+                                number: x as u8,
+                                is_sharp,
+                                underline,
+                                dotted,
+                            }));
                         }
-                    },
-                    None => break,
+
+                        Self::expect_separator(&mut line)?;
+
+                        is_sharp = false;
+                        octave = 0;
+                    }
                 }
             }
 
             Ok(())
         }
 
+        /// 记号后面要么是空格，要么是行尾
+        fn expect_separator(line: &mut LinkedList<char>) -> ParseResult<()> {
+            match line.front() {
+                Some(' ') | None => Ok(()),
+                Some(_) => Err(ParseError("要以空格隔开".to_owned())),
+            }
+        }
+
         pub fn lines(self) -> Vec<Vec<Token>> {
             self.inner
         }
@@ -242,54 +428,56 @@ pub mod parser {
         #[test]
         fn test_parse_from_str() {
             assert_eq!(Parser::from_str("1"), Ok(Parser { inner: vec![vec![Token::Notation(Notation {
-                r#type: NotationType::Normal,
+                octave: 0,
                 number: 1,
                 is_sharp: false,
+                underline: 0,
+                dotted: false,
             })]]}));
 
             assert_eq!(Parser::from_str("1 #2 3\n4  5 "), Ok(Parser { inner: vec![
                 vec![
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 1, is_sharp: false }),
+                    Token::Notation(Notation { octave: 0, number: 1, is_sharp: false, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 2, is_sharp: true }),
+                    Token::Notation(Notation { octave: 0, number: 2, is_sharp: true, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 3, is_sharp: false }),
+                    Token::Notation(Notation { octave: 0, number: 3, is_sharp: false, underline: 0, dotted: false }),
                 ],
                 vec![
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 4, is_sharp: false }),
+                    Token::Notation(Notation { octave: 0, number: 4, is_sharp: false, underline: 0, dotted: false }),
                     Token::Whitespace,
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 5, is_sharp: false }),
+                    Token::Notation(Notation { octave: 0, number: 5, is_sharp: false, underline: 0, dotted: false }),
                 ]
             ]}));
 
             assert_eq!(Parser::from_str("(1) [#2] ((#7)) \n[[#4]] #5"), Ok(Parser { inner: vec![
                 vec![
-                    Token::Notation(Notation { r#type: NotationType::Low, number: 1, is_sharp: false }),
+                    Token::Notation(Notation { octave: -1, number: 1, is_sharp: false, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::High, number: 2, is_sharp: true }),
+                    Token::Notation(Notation { octave: 1, number: 2, is_sharp: true, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::LLow, number: 7, is_sharp: true }),
+                    Token::Notation(Notation { octave: -2, number: 7, is_sharp: true, underline: 0, dotted: false }),
                 ],
                 vec![
-                    Token::Notation(Notation { r#type: NotationType::HHigh, number: 4, is_sharp: true }),
+                    Token::Notation(Notation { octave: 2, number: 4, is_sharp: true, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 5, is_sharp: true }),
+                    Token::Notation(Notation { octave: 0, number: 5, is_sharp: true, underline: 0, dotted: false }),
                 ]
             ]}));
 
             assert_eq!(Parser::from_str("(1) [2] ((7)) \n[[4]] 5"), Ok(Parser { inner: vec![
                 vec![
-                    Token::Notation(Notation { r#type: NotationType::Low, number: 1, is_sharp: false }),
+                    Token::Notation(Notation { octave: -1, number: 1, is_sharp: false, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::High, number: 2, is_sharp: false }),
+                    Token::Notation(Notation { octave: 1, number: 2, is_sharp: false, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::LLow, number: 7, is_sharp: false }),
+                    Token::Notation(Notation { octave: -2, number: 7, is_sharp: false, underline: 0, dotted: false }),
                 ],
                 vec![
-                    Token::Notation(Notation { r#type: NotationType::HHigh, number: 4, is_sharp: false }),
+                    Token::Notation(Notation { octave: 2, number: 4, is_sharp: false, underline: 0, dotted: false }),
                     Token::Whitespace,
-                    Token::Notation(Notation { r#type: NotationType::Normal, number: 5, is_sharp: false }),
+                    Token::Notation(Notation { octave: 0, number: 5, is_sharp: false, underline: 0, dotted: false }),
                 ]
             ]}));
 
@@ -301,6 +489,76 @@ pub mod parser {
             assert!(Parser::from_str("(1").is_err());
             assert!(Parser::from_str("((1").is_err());
         }
+
+        #[test]
+        fn test_duration() {
+            let parser = Parser::from_str("(1)_ [#2]__. 3 - -").unwrap();
+            let line = parser.lines().remove(0);
+            assert_eq!(line[0], Token::Notation(Notation { octave: -1, number: 1, is_sharp: false, underline: 1, dotted: false }));
+            assert_eq!(line[2], Token::Notation(Notation { octave: 1, number: 2, is_sharp: true, underline: 2, dotted: true }));
+            assert_eq!(line[6], Token::Dash);
+            assert_eq!(line.iter().map(|t| t.to_string()).collect::<String>(), "(1)_ [#2]__. 3 - -");
+
+            assert!(Parser::from_str("1____").is_err());
+            assert!(Parser::from_str("1_2").is_err());
+            assert!(Parser::from_str("1 --").is_err());
+        }
+
+        #[test]
+        fn test_score_tokens() {
+            let parser = Parser::from_str("1=D 3/4 ♩=90\n1 0_. | 2 ||\nw: 孟 姜").unwrap_err();
+            assert_eq!(parser, ParseError("要以空格隔开".to_owned()));
+
+            let lines = Parser::from_str("1=D 3/4 ♩=90\n1 0_. | 2 |\nw: 孟 姜").unwrap().lines();
+            assert_eq!(lines[0], vec![Token::Header(Header { key: "D".to_owned(), tonality: None, meter: Some(Meter { beats: 3, beat_type: 4 }), tempo: Some(90) })]);
+            assert_eq!(lines[1][2], Token::Rest(Rest { underline: 1, dotted: true }));
+            assert_eq!(lines[1][4], Token::Bar);
+            assert_eq!(lines[2], vec![Token::Lyrics(vec!["孟".to_owned(), "姜".to_owned()])]);
+            assert_eq!(lines.iter().flatten().map(|t| t.to_string()).collect::<String>(), "1=D 3/4 ♩=901 0_. | 2 |w: 孟 姜");
+
+            assert!(Parser::from_str("#0").is_err());
+            assert!(Parser::from_str("(0)").is_err());
+            assert!(Parser::from_str("1=").is_err());
+            assert!(Parser::from_str("1=C 3/5").is_err());
+            assert!(Parser::from_str("1=C ♩=快").is_err());
+
+            let lines = Parser::from_str("1=C Am 2/4").unwrap().lines();
+            assert_eq!(lines[0], vec![Token::Header(Header { key: "C".to_owned(), tonality: Some("Am".to_owned()), meter: Some(Meter { beats: 2, beat_type: 4 }), tempo: None })]);
+            assert_eq!(lines[0][0].to_string(), "1=C Am 2/4");
+            assert!(Parser::from_str("1=C Am Am").is_err());
+        }
+
+        #[test]
+        fn test_to_unicode() {
+            let parser = Parser::from_str("((1)) (2)_ #3 [4]. [[5]]__").unwrap();
+            let unicode = parser.lines().remove(0).iter().map(|token| match token {
+                Token::Notation(n) => n.to_unicode(),
+                token => token.to_string(),
+            }).collect::<String>();
+            assert_eq!(unicode, "1\u{0324} 2\u{0323}\u{0332} ♯3 4\u{0307}· 5\u{0308}\u{0333}");
+            assert_eq!(Notation::from_str("6___").unwrap().to_unicode(), "6\u{0333}\u{0332}");
+        }
+
+        #[test]
+        fn test_octaves() {
+            let low = Notation::from_str("(((#1)))").unwrap();
+            assert_eq!((low.octave(), low.number(), low.is_sharp()), (-3, 1, true));
+            assert_eq!(low.to_string(), "(((#1)))");
+            assert_eq!(low.to_unicode(), "♯1\u{20E8}");
+            assert_eq!(Notation::from_str("[[[[7]]]]").unwrap().to_string(), "[[[[7]]]]");
+            assert_eq!(Notation::from_str("[[[2]]]").unwrap().semitones(), 38);
+            assert!(Notation::from_str("([1])").is_err());
+            assert!(Notation::from_str("[[[1]]").is_err());
+            assert!(Notation::from_str("1 2").is_err());
+
+            for semitones in -60..60 {
+                let notation = Notation::from_semitones(semitones);
+                assert_eq!(notation.semitones(), semitones);
+                assert_eq!(Notation::from_str(&notation.to_string()), Ok(notation));
+                assert_eq!(notation.transpose(7).transpose(-7), notation);
+            }
+            assert_eq!(Notation::from_str("[7]_.").unwrap().transpose(1).to_string(), "[[1]]_.");
+        }
     }
 
 }
\ No newline at end of file
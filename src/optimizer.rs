@@ -0,0 +1,142 @@
+//! 最优指法选择
+//!
+//! 把一段乐句看成依次经过每个音符的路径，每个音符的候选指法是路径上的节点，
+//! 用动态规划求出总代价最小的一组指法。
+
+use crate::finger::Fingering;
+use crate::tone::Tone;
+
+/// 指法代价模型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    /// 每变化一个孔位的代价
+    pub movement: u32,
+    /// 每使用一次替代指法的代价
+    pub alternate: u32,
+    /// 两个音来回交替（颤音）时，每变化一个孔位的额外代价
+    pub trill: u32,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            movement: 1,
+            alternate: 2,
+            trill: 2,
+        }
+    }
+}
+
+/// 指法选择方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FingeringMode {
+    /// 总是使用英式（巴洛克式）标准指法
+    #[default]
+    Standard,
+    /// 总是使用德式竖笛的指法
+    German,
+    /// 按代价模型自动选择
+    Auto(CostModel),
+}
+
+/// 从第`index - 1`个音到第`index`个音是否处于两个音来回交替之中
+fn is_trill(tones: &[Tone], index: usize) -> bool {
+    let (prev, current) = (tones[index - 1], tones[index]);
+    if prev == current {
+        return false;
+    }
+    (index >= 2 && tones[index - 2] == current) || tones.get(index + 1) == Some(&prev)
+}
+
+/// 为一段音符选择总代价最小的指法，有吹不了的音符时返回`None`
+pub fn optimize_fingerings(tones: &[Tone], model: &CostModel) -> Option<Vec<Fingering>> {
+    let candidates = tones.iter().map(|tone| tone.to_fingers()).collect::<Vec<_>>();
+    if candidates.iter().any(|fingers| fingers.is_empty()) {
+        return None;
+    }
+    if candidates.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let alternate_cost = |choice: usize| if choice == 0 { 0 } else { model.alternate };
+
+    // costs[i][j]：第i个音使用第j个候选指法时的最小累计代价
+    // from[i][j]：对应的上一个音的候选指法
+    let mut costs = vec![candidates[0].iter().enumerate().map(|(j, _)| alternate_cost(j)).collect::<Vec<_>>()];
+    let mut from = vec![vec![0; candidates[0].len()]];
+
+    for index in 1..candidates.len() {
+        let weight = model.movement + if is_trill(tones, index) { model.trill } else { 0 };
+        let mut row_costs = Vec::with_capacity(candidates[index].len());
+        let mut row_from = Vec::with_capacity(candidates[index].len());
+
+        for (j, finger) in candidates[index].iter().enumerate() {
+            let (best, cost) = candidates[index - 1].iter()
+                .enumerate()
+                .map(|(k, prev)| (k, costs[index - 1][k] + weight * prev.distance(finger)))
+                .fold((0, u32::MAX), |best, item| if item.1 < best.1 { item } else { best });
+            row_costs.push(cost + alternate_cost(j));
+            row_from.push(best);
+        }
+
+        costs.push(row_costs);
+        from.push(row_from);
+    }
+
+    let last = costs.len() - 1;
+    let mut choice = (0..costs[last].len())
+        .fold(0, |best, j| if costs[last][j] < costs[last][best] { j } else { best });
+
+    let mut fingerings = vec![candidates[last][choice]];
+    for index in (1..=last).rev() {
+        choice = from[index][choice];
+        fingerings.push(candidates[index - 1][choice]);
+    }
+    fingerings.reverse();
+
+    Some(fingerings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn primary(tone: Tone) -> Fingering {
+        tone.to_finger().unwrap()
+    }
+
+    fn alternate(tone: Tone) -> Fingering {
+        tone.to_fingers()[1]
+    }
+
+    #[test]
+    fn test_optimize_prefers_fewer_movements() {
+        let model = CostModel::default();
+        assert_eq!(optimize_fingerings(&[Tone::D, Tone::HD], &model), Some(vec![primary(Tone::D), alternate(Tone::HD)]));
+        assert_eq!(optimize_fingerings(&[Tone::B, Tone::HD], &model), Some(vec![primary(Tone::B), primary(Tone::HD)]));
+        assert_eq!(optimize_fingerings(&[Tone::C, Tone::D, Tone::E], &model), Some(vec![primary(Tone::C), primary(Tone::D), primary(Tone::E)]));
+    }
+
+    #[test]
+    fn test_optimize_alternate_cost() {
+        let model = CostModel { alternate: 10, ..Default::default() };
+        assert_eq!(optimize_fingerings(&[Tone::D, Tone::HD], &model), Some(vec![primary(Tone::D), primary(Tone::HD)]));
+        assert_eq!(optimize_fingerings(&[Tone::HD], &CostModel::default()), Some(vec![primary(Tone::HD)]));
+    }
+
+    #[test]
+    fn test_optimize_trill() {
+        let model = CostModel { movement: 0, alternate: 1, trill: 10 };
+        assert_eq!(optimize_fingerings(&[Tone::D, Tone::HD], &model), Some(vec![primary(Tone::D), primary(Tone::HD)]));
+        assert_eq!(
+            optimize_fingerings(&[Tone::D, Tone::HD, Tone::D], &model),
+            Some(vec![primary(Tone::D), alternate(Tone::HD), primary(Tone::D)])
+        );
+    }
+
+    #[test]
+    fn test_optimize_unplayable() {
+        assert_eq!(optimize_fingerings(&[Tone::C, Tone::LC], &CostModel::default()), None);
+        assert_eq!(optimize_fingerings(&[], &CostModel::default()), Some(Vec::new()));
+    }
+}
@@ -0,0 +1,542 @@
+//! PDF歌本
+//!
+//! 不依赖外部工具，直接生成PDF：西文用Helvetica，中文用阅读器自带的STSong-Light，都不嵌入字体；两种字体都没有的`♩`直接画出来。
+//! 排版沿用[`diagram`](crate::diagram)的像素坐标，输出时整体缩放到A4纸。
+
+use crate::diagram::{bar_mark, dash_mark, fingering_shapes, notation_marks, rest_marks, row_height, token_width, wrap_line, Mark, Row, Shape, NOTE_BASELINE, NOTE_WIDTH, SPACE_WIDTH, TEXT_ROW_HEIGHT};
+use crate::finger::Hole;
+use crate::key::{read_header, write_header, Key};
+use crate::notation::parser::Token;
+use crate::optimizer::FingeringMode;
+use crate::tone::{fingering_lines, ConvertError, FingerTone, FingeringLine};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+/// 页面宽度（像素）
+const PAGE_WIDTH: u32 = 794;
+/// 页面高度（像素）
+const PAGE_HEIGHT: u32 = 1123;
+/// 像素到点的缩放比例
+const SCALE: f32 = 0.75;
+/// 左右页边距
+const MARGIN_X: u32 = 56;
+/// 上下页边距
+const MARGIN_Y: u32 = 60;
+/// 目录每项的行高
+const TOC_LINE_HEIGHT: u32 = 28;
+/// 目录每页的项数
+const TOC_PER_PAGE: usize = ((PAGE_HEIGHT - 2 * MARGIN_Y - 60) / TOC_LINE_HEIGHT) as usize;
+/// 贝塞尔曲线近似圆弧的系数
+const KAPPA: f32 = 0.552_284_8;
+
+/// 歌本中的一首歌
+#[derive(Debug, Clone, PartialEq)]
+pub struct Song {
+    pub title: String,
+    /// 简谱的调，标题下的`1=`按它来写
+    pub key: FingerTone,
+    /// 指法音调，F调竖笛和`key`差五度
+    pub finger_tone: FingerTone,
+    pub content: String,
+}
+
+/// 某首歌转换失败
+#[derive(Debug, PartialEq)]
+pub struct SongError {
+    pub title: String,
+    pub error: ConvertError,
+}
+
+impl Display for SongError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "《{}》：{}", self.title, self.error)
+    }
+}
+
+impl Error for SongError {}
+
+/// 一页的绘图指令，坐标为像素，y轴向下
+#[derive(Debug, Default)]
+struct Page {
+    ops: String,
+    /// 目录链接：区域和目标页（从0开始）
+    links: Vec<([f32; 4], usize)>,
+}
+
+/// 同一种字体的一段文字
+#[derive(Debug, PartialEq)]
+enum Run {
+    /// 西文，用Helvetica
+    Latin(String),
+    /// 中文等，用STSong-Light
+    Cjk(String),
+    /// 两种字体都没有的`♩`，画出来
+    Quarter,
+}
+
+/// 按字体把文字分段；UniGB-UCS2-H只能编码基本多文种平面，平面外的字符去掉
+fn runs(s: &str) -> Vec<Run> {
+    let mut runs = Vec::new();
+    for c in s.chars() {
+        match (runs.last_mut(), c) {
+            (_, '♩') => runs.push(Run::Quarter),
+            (_, c) if c as u32 > 0xffff => {}
+            (Some(Run::Latin(run)), c) if c.is_ascii() => run.push(c),
+            (Some(Run::Cjk(run)), c) if !c.is_ascii() => run.push(c),
+            (_, c) if c.is_ascii() => runs.push(Run::Latin(c.to_string())),
+            (_, c) => runs.push(Run::Cjk(c.to_string())),
+        }
+    }
+    runs
+}
+
+impl Page {
+    fn text(&mut self, size: f32, x: f32, y: f32, s: &str) {
+        let mut x = x;
+        for run in runs(s) {
+            let (font, encoded, width) = match &run {
+                Run::Latin(text) => {
+                    let escaped = text.replace('\\', "\\\\").replace('(', "\\(").replace(')', "\\)");
+                    ("F1", format!("({})", escaped), text_width(size, text))
+                }
+                Run::Cjk(text) => {
+                    let hex = text.encode_utf16().map(|u| format!("{:04X}", u)).collect::<String>();
+                    ("F2", format!("<{}>", hex), text_width(size, text))
+                }
+                Run::Quarter => {
+                    // 符头和符干
+                    let (cx, cy, r) = (x + size * 0.2, y - size * 0.15, size * 0.15);
+                    let _ = writeln!(self.ops, "0 g {} f", circle(cx, cy, r));
+                    let _ = writeln!(self.ops, "0 G {} {} m {} {} l S", n(cx + r), n(cy), n(cx + r), n(y - size * 0.8));
+                    x += text_width(size, "♩");
+                    continue;
+                }
+            };
+            let _ = writeln!(self.ops, "BT /{} {} Tf 1 0 0 -1 {} {} Tm {} Tj ET", font, size, n(x), n(y), encoded);
+            x += width;
+        }
+    }
+
+    fn text_centered(&mut self, size: f32, cx: f32, y: f32, s: &str) {
+        self.text(size, cx - text_width(size, s) / 2.0, y, s);
+    }
+
+    fn mark(&mut self, mark: &Mark) {
+        match mark {
+            Mark::Text { cx, y, size, text } => self.text_centered(*size, *cx, *y, text),
+            Mark::Dot { cx, cy, r } => {
+                let _ = writeln!(self.ops, "0 g {} f", circle(*cx, *cy, *r));
+            }
+            Mark::Line { x1, x2, y } => {
+                let _ = writeln!(self.ops, "0 G {} {} m {} {} l S", n(*x1), n(*y), n(*x2), n(*y));
+            }
+            Mark::Bar { x, y1, y2 } => {
+                let _ = writeln!(self.ops, "0 G {} {} m {} {} l S", n(*x), n(*y1), n(*x), n(*y2));
+            }
+        }
+    }
+
+    fn shape(&mut self, shape: &Shape, dx: f32, dy: f32) {
+        let ops = &mut self.ops;
+        match *shape {
+            Shape::Body { x, y, width, height } => {
+                let (x, y, r) = (x + dx, y + dy, width / 2.0);
+                let k = KAPPA * r;
+                let _ = writeln!(
+                    ops,
+                    "0.6 G {x} {y1} m {x} {y2} l {x} {y3} {x4} {yb} {xm} {yb} c {x5} {yb} {xr} {y3} {xr} {y2} c {xr} {y1} l {xr} {y6} {x5} {y} {xm} {y} c {x4} {y} {x} {y6} {x} {y1} c S",
+                    x = n(x),
+                    y = n(y),
+                    y1 = n(y + r),
+                    y2 = n(y + height - r),
+                    y3 = n(y + height - r + k),
+                    yb = n(y + height),
+                    x4 = n(x + r - k),
+                    x5 = n(x + r + k),
+                    xm = n(x + r),
+                    xr = n(x + width),
+                    y6 = n(y + r - k),
+                );
+            }
+            Shape::Separator { x1, x2, y } => {
+                let _ = writeln!(ops, "0.6 G [2 2] 0 d {} {} m {} {} l S [] 0 d", n(x1 + dx), n(y + dy), n(x2 + dx), n(y + dy));
+            }
+            Shape::Hole { cx, cy, r, hole } => {
+                let (cx, cy) = (cx + dx, cy + dy);
+                let fill = if hole == Hole::Close { 0 } else { 1 };
+                let _ = writeln!(ops, "{} g 0 G {} B", fill, circle(cx, cy, r));
+                if hole == Hole::Half {
+                    let k = KAPPA * r;
+                    let _ = writeln!(
+                        ops,
+                        "0 g {} {} m {} {} {} {} {} {} c {} {} {} {} {} {} c h f",
+                        n(cx), n(cy - r),
+                        n(cx - k), n(cy - r), n(cx - r), n(cy - k), n(cx - r), n(cy),
+                        n(cx - r), n(cy + k), n(cx - k), n(cy + r), n(cx), n(cy + r),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// 保留两位小数的数字
+fn n(value: f32) -> String {
+    let s = format!("{:.2}", value);
+    s.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+/// 圆形路径
+fn circle(cx: f32, cy: f32, r: f32) -> String {
+    let k = KAPPA * r;
+    format!(
+        "{} {} m {} {} {} {} {} {} c {} {} {} {} {} {} c {} {} {} {} {} {} c {} {} {} {} {} {} c h",
+        n(cx + r), n(cy),
+        n(cx + r), n(cy + k), n(cx + k), n(cy + r), n(cx), n(cy + r),
+        n(cx - k), n(cy + r), n(cx - r), n(cy + k), n(cx - r), n(cy),
+        n(cx - r), n(cy - k), n(cx - k), n(cy - r), n(cx), n(cy - r),
+        n(cx + k), n(cy - r), n(cx + r), n(cy - k), n(cx + r), n(cy),
+    )
+}
+
+/// 估算文字宽度：西文和`♩`半角，中文全角，去掉的字符不占宽度
+fn text_width(size: f32, s: &str) -> f32 {
+    s.chars().map(|c| match c {
+        c if c.is_ascii() || c == '♩' => 0.55,
+        c if c as u32 > 0xffff => 0.0,
+        _ => 1.0,
+    }).sum::<f32>() * size
+}
+
+/// 标题下的调号：有调号行时保留调性、拍号和速度，`1=`换成实际用的指法音调，例如`1=C Am 3/4`；
+/// 同时返回这个调号行的位置
+fn song_key(song: &Song, lines: &[FingeringLine]) -> Result<(String, Option<usize>), ConvertError> {
+    let header = lines.iter().enumerate().find_map(|(index, line)| match line.as_slice() {
+        [(Token::Header(header), _)] => Some((index, header)),
+        _ => None,
+    });
+    match header {
+        Some((index, header)) => {
+            let (one, tonality) = read_header(header).map_err(|e| ConvertError::NotFound(format!("调号有误：{}", e)))?;
+            // 指定的调和调号行不同时，调性跟着移调
+            let shift = song.key.semitones() + 12 - one.semitones();
+            let tonality = tonality.map(|key| {
+                let finger_tone = FingerTone::ALL[((key.finger_tone().semitones() + shift) % 12) as usize];
+                Key::from_finger_tone(finger_tone, key.mode())
+            });
+            let header = write_header(header.clone(), song.key, tonality);
+            Ok((Token::Header(header).to_string(), Some(index)))
+        }
+        None => Ok((format!("1={}", song.key), None)),
+    }
+}
+
+/// 排版一首歌，每首歌从新的一页开始；同一行简谱和它的指法图尽量放在同一页
+fn layout_song(song: &Song, mode: FingeringMode) -> Result<Vec<Page>, ConvertError> {
+    let lines = fingering_lines(&song.content, song.finger_tone, mode)?;
+    let (key, header) = song_key(song, &lines)?;
+    let max_width = PAGE_WIDTH - 2 * MARGIN_X;
+    let bottom = PAGE_HEIGHT - MARGIN_Y;
+
+    let mut pages = Vec::new();
+    let mut page = Page::default();
+    page.text_centered(24.0, (PAGE_WIDTH / 2) as f32, (MARGIN_Y + 24) as f32, &song.title);
+    page.text(14.0, MARGIN_X as f32, (MARGIN_Y + 52) as f32, &key);
+    let mut y = MARGIN_Y + 72;
+    let mut is_empty = true;
+
+    for (index, line) in lines.iter().enumerate() {
+        // 第一个调号行已经写在标题下面
+        if Some(index) == header {
+            continue;
+        }
+        if line.is_empty() {
+            y += TEXT_ROW_HEIGHT / 2;
+            continue;
+        }
+
+        let rows = wrap_line(line, max_width);
+        let height = rows.iter().map(|row| row_height(row)).sum::<u32>();
+        let fits_on_page = MARGIN_Y + height <= bottom;
+
+        for row in rows {
+            let row_height = row_height(row);
+            let new_page = if fits_on_page { y + height > bottom } else { y + row_height > bottom };
+            if new_page && !is_empty {
+                pages.push(std::mem::take(&mut page));
+                y = MARGIN_Y;
+            }
+            render_row(&mut page, row, MARGIN_X, y);
+            y += row_height;
+            is_empty = false;
+        }
+    }
+
+    pages.push(page);
+    Ok(pages)
+}
+
+fn render_row(page: &mut Page, row: Row, x: u32, y: u32) {
+    let mut x = x;
+    for (token, fingering) in row {
+        let (cx, baseline) = ((x + NOTE_WIDTH / 2) as f32, (y + NOTE_BASELINE) as f32);
+        let marks = match (token, fingering) {
+            (Token::Notation(n), Some(fingering)) => {
+                for shape in fingering_shapes(fingering, (NOTE_WIDTH / 2) as f32) {
+                    page.shape(&shape, x as f32, y as f32);
+                }
+                notation_marks(n, cx, baseline)
+            }
+            (Token::Rest(rest), _) => rest_marks(rest, cx, baseline),
+            (Token::Dash, _) => vec![dash_mark(cx, baseline)],
+            (Token::Bar, _) => vec![bar_mark((x + SPACE_WIDTH / 2) as f32, baseline)],
+            (token, _) if token.is_text() => {
+                page.text(14.0, x as f32, (y + 20) as f32, &token.to_string());
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        for mark in marks {
+            page.mark(&mark);
+        }
+        x += token_width(token);
+    }
+}
+
+/// 目录页，`entries`为标题和所在页（从0开始）
+fn layout_toc(entries: &[(&str, usize)], pages: usize) -> Vec<Page> {
+    let right = (PAGE_WIDTH - MARGIN_X) as f32;
+
+    (0..pages).map(|index| {
+        let mut page = Page::default();
+        page.text_centered(24.0, (PAGE_WIDTH / 2) as f32, (MARGIN_Y + 24) as f32, "目录");
+
+        let mut y = (MARGIN_Y + 60) as f32;
+        for &(title, target) in entries.iter().skip(index * TOC_PER_PAGE).take(TOC_PER_PAGE) {
+            let number = (target + 1).to_string();
+            let title_end = MARGIN_X as f32 + text_width(14.0, title);
+            let number_start = right - text_width(14.0, &number);
+            page.text(14.0, MARGIN_X as f32, y, title);
+            page.text(14.0, number_start, y, &number);
+            let dots = ((number_start - title_end - 8.0) / text_width(14.0, ".")).max(0.0) as usize;
+            page.text(14.0, number_start - 4.0 - text_width(14.0, ".") * dots as f32, y, &".".repeat(dots));
+            page.links.push(([MARGIN_X as f32, y - 16.0, right, y + 6.0], target));
+            y += TOC_LINE_HEIGHT as f32;
+        }
+
+        page
+    }).collect()
+}
+
+/// 把多首歌排成带目录和页码的PDF歌本
+pub fn songbook_pdf(songs: &[Song], mode: FingeringMode) -> Result<Vec<u8>, SongError> {
+    let mut song_pages = Vec::new();
+    for song in songs {
+        let pages = layout_song(song, mode).map_err(|error| SongError {
+            title: song.title.clone(),
+            error,
+        })?;
+        song_pages.push(pages);
+    }
+
+    let toc_pages = songs.len().div_ceil(TOC_PER_PAGE).max(1);
+
+    let mut entries = Vec::new();
+    let mut start = toc_pages;
+    for (song, pages) in songs.iter().zip(song_pages.iter()) {
+        entries.push((song.title.as_str(), start));
+        start += pages.len();
+    }
+
+    let mut pages = layout_toc(&entries, toc_pages);
+    pages.extend(song_pages.into_iter().flatten());
+
+    for (index, page) in pages.iter_mut().enumerate() {
+        page.text_centered(12.0, (PAGE_WIDTH / 2) as f32, (PAGE_HEIGHT - MARGIN_Y / 2) as f32, &(index + 1).to_string());
+    }
+
+    Ok(write_pdf(&pages))
+}
+
+/// PDF对象写入器
+struct PdfWriter {
+    buf: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn object(&mut self, id: usize, body: &[u8]) {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.buf.len();
+        self.buf.extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+        self.buf.extend_from_slice(body);
+        self.buf.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: usize, content: &[u8]) {
+        let mut body = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\nendstream");
+        self.object(id, &body);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.buf.len();
+        let mut tail = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = writeln!(tail, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(tail, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", self.offsets.len() + 1, xref);
+        self.buf.extend_from_slice(tail.as_bytes());
+        self.buf
+    }
+}
+
+fn write_pdf(pages: &[Page]) -> Vec<u8> {
+    const FIRST_PAGE: usize = 7;
+    let page_id = |index: usize| FIRST_PAGE + index * 2;
+    let width = PAGE_WIDTH as f32 * SCALE;
+    let height = PAGE_HEIGHT as f32 * SCALE;
+
+    let mut writer = PdfWriter {
+        buf: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+        offsets: Vec::new(),
+    };
+
+    let kids = (0..pages.len()).map(|index| format!("{} 0 R", page_id(index))).collect::<Vec<_>>().join(" ");
+    writer.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    writer.object(2, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, pages.len()).as_bytes());
+    writer.object(3, b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>");
+    writer.object(4, b"<< /Type /Font /Subtype /Type0 /BaseFont /STSong-Light /Encoding /UniGB-UCS2-H /DescendantFonts [5 0 R] >>");
+    writer.object(5, b"<< /Type /Font /Subtype /CIDFontType0 /BaseFont /STSong-Light \
+/CIDSystemInfo << /Registry (Adobe) /Ordering (GB1) /Supplement 2 >> /FontDescriptor 6 0 R /DW 1000 /W [1 95 500] >>");
+    writer.object(6, b"<< /Type /FontDescriptor /FontName /STSong-Light /Flags 6 /FontBBox [-25 -254 1000 880] \
+/ItalicAngle 0 /Ascent 880 /Descent -120 /CapHeight 880 /StemV 93 >>");
+
+    for (index, page) in pages.iter().enumerate() {
+        let annots = page.links.iter().map(|&([x1, y1, x2, y2], target)| {
+            format!(
+                "<< /Type /Annot /Subtype /Link /Border [0 0 0] /Rect [{} {} {} {}] /Dest [{} 0 R /Fit] >>",
+                n(x1 * SCALE), n(height - y2 * SCALE), n(x2 * SCALE), n(height - y1 * SCALE), page_id(target)
+            )
+        }).collect::<Vec<_>>().join(" ");
+
+        writer.object(page_id(index), format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R /Annots [{}] >>",
+            width, height, page_id(index) + 1, annots
+        ).as_bytes());
+
+        let content = format!("{} 0 0 {} 0 {} cm 1 w\n{}", SCALE, -SCALE, height, page.ops);
+        writer.stream(page_id(index) + 1, content.as_bytes());
+    }
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagram::ROW_HEIGHT;
+
+    fn song(title: &str, content: &str) -> Song {
+        Song {
+            title: title.to_owned(),
+            key: FingerTone::C,
+            finger_tone: FingerTone::C,
+            content: content.to_owned(),
+        }
+    }
+
+    fn page_count(pdf: &[u8]) -> usize {
+        String::from_utf8_lossy(pdf).matches("/Type /Page ").count()
+    }
+
+    #[test]
+    fn test_songbook_pdf() {
+        let pdf = songbook_pdf(&[song("孟姜女", "1 2 3\n\n// 注释\n4 5 6"), song("Two", "[1]")], FingeringMode::Standard).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.ends_with("%%EOF\n"));
+        // 目录一页，每首歌一页
+        assert_eq!(page_count(&pdf), 3);
+        assert_eq!(text.matches("/Subtype /Link").count(), 2);
+        // 标题用中文字体
+        assert!(text.contains("<5B5F59DC5973>"));
+        assert!(text.contains("(1=C)"));
+    }
+
+    #[test]
+    fn test_runs() {
+        let text = |s: &str| Run::Latin(s.to_owned());
+        let cjk = |s: &str| Run::Cjk(s.to_owned());
+        assert_eq!(runs("1=C ♩=90"), vec![text("1=C "), Run::Quarter, text("=90")]);
+        assert_eq!(runs("孟姜女 (1)"), vec![cjk("孟姜女"), text(" (1)")]);
+        assert_eq!(runs("a😀b，c"), vec![text("ab"), cjk("，"), text("c")]);
+
+        let mut page = Page::default();
+        page.text(14.0, 0.0, 20.0, "1=C ♩=90 孟");
+        assert!(page.ops.contains("/F1 14 Tf 1 0 0 -1 0 20 Tm (1=C ) Tj"));
+        assert!(page.ops.contains("(=90 ) Tj"));
+        assert!(page.ops.contains("/F2 14 Tf"));
+        assert!(page.ops.contains("<5B5F> Tj"));
+        assert!(!page.ops.contains("2669"));
+    }
+
+    #[test]
+    fn test_song_key() {
+        let key = |finger_tone: FingerTone, content: &str| {
+            let song = Song { key: finger_tone, finger_tone, ..song("a", content) };
+            song_key(&song, &fingering_lines(content, finger_tone, FingeringMode::Standard).unwrap()).unwrap()
+        };
+        assert_eq!(key(FingerTone::C, "1 2"), ("1=C".to_owned(), None));
+        assert_eq!(key(FingerTone::C, "// 注释\n1=C Am 3/4\n6 7"), ("1=C Am 3/4".to_owned(), Some(1)));
+        assert_eq!(key(FingerTone::A, "1=Am\n1 2"), ("1=Am".to_owned(), Some(0)));
+        // 用-t换了调，调性跟着换
+        assert_eq!(key(FingerTone::D, "1=C Am\n6 7").0, "1=D Bm");
+        assert_eq!(key(FingerTone::D, "1=Am\n1 2").0, "1=Dm");
+        assert_eq!(key(FingerTone::G, "1=C\n1 2").0, "1=G");
+
+        // 中音竖笛按C调的指法吹F调，标题下还是写实际的调
+        let alto = Song { key: FingerTone::F, ..song("a", "1=F\n1 2") };
+        assert_eq!(song_key(&alto, &fingering_lines(&alto.content, alto.finger_tone, FingeringMode::Standard).unwrap()).unwrap().0, "1=F");
+
+        // 调号行不再重复画一遍
+        let pages = layout_song(&song("a", "1=C Am 3/4\n6 7"), FingeringMode::Standard).unwrap();
+        assert_eq!(pages[0].ops.matches("Am").count(), 1);
+    }
+
+    #[test]
+    fn test_xref_offsets() {
+        let pdf = songbook_pdf(&[song("a", "1 2 3")], FingeringMode::Standard).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        let xref = text.rfind("xref\n").unwrap();
+        let entries = text[xref..].lines().skip(3).take_while(|line| line.ends_with(" n "));
+        for (index, entry) in entries.enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", index + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_page_break_keeps_line_together() {
+        let rows_per_page = ((PAGE_HEIGHT - 2 * MARGIN_Y - 72) / ROW_HEIGHT) as usize;
+        let content = vec!["1 2 3"; rows_per_page].join("\n");
+        let pages = layout_song(&song("a", &content), FingeringMode::Standard).unwrap();
+        assert_eq!(pages.len(), 1);
+
+        // 多一行放不下时整行换到下一页
+        let content = vec!["1 2 3"; rows_per_page + 1].join("\n");
+        let pages = layout_song(&song("a", &content), FingeringMode::Standard).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].ops.matches("(1) Tj").count(), 1);
+    }
+
+    #[test]
+    fn test_song_error() {
+        let error = songbook_pdf(&[song("a", "1"), song("坏", "8")], FingeringMode::Standard).unwrap_err();
+        assert_eq!(error.title, "坏");
+        assert!(error.to_string().starts_with("《坏》"));
+    }
+}
@@ -0,0 +1,417 @@
+//! 从WAV录音识别音高
+//!
+//! 用YIN算法逐帧估计基频，中值滤波后把连续相同的半音合并成音符，再按速度换算成简谱时值。
+//! 评分时把识别出的音符和参考简谱按编辑距离对齐，报告错音、漏音、多吹的音和每个音的音准（音分）。
+
+use crate::score::{Event, Score, DIVISIONS};
+use crate::tone::{FingerTone, Tone};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+/// 音量低于附近最大音量的这个比例时当作两个音之间的断开
+const DIP: f64 = 0.4;
+
+/// 读取错误
+#[derive(Debug, PartialEq)]
+pub struct PitchError(String);
+
+impl Display for PitchError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for PitchError {}
+
+/// 音高识别设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchOptions {
+    /// A4的频率
+    pub a4: f64,
+    /// 识别的最低频率
+    pub min_frequency: f64,
+    /// 识别的最高频率
+    pub max_frequency: f64,
+    /// YIN的阈值，越小越严格
+    pub threshold: f64,
+    /// 低于这个均方根音量的帧当作静音
+    pub silence: f64,
+    /// 最短的音符（秒），更短的当作杂音
+    pub min_note: f64,
+}
+
+impl Default for PitchOptions {
+    fn default() -> Self {
+        Self {
+            a4: 440.0,
+            min_frequency: 250.0,
+            max_frequency: 3000.0,
+            threshold: 0.15,
+            silence: 0.01,
+            min_note: 0.06,
+        }
+    }
+}
+
+/// 识别出的音符
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectedNote {
+    /// 开始时间（秒）
+    pub start: f64,
+    /// 结束时间（秒）
+    pub end: f64,
+    pub tone: Tone,
+    /// 音准偏差（音分），偏高为正
+    pub cents: f64,
+}
+
+/// 读WAV文件，多声道混合成单声道，返回采样和采样率
+pub fn read_wav(data: &[u8]) -> Result<(Vec<f32>, u32), PitchError> {
+    let error = |message: &str| PitchError(message.to_owned());
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(error("不是WAV文件"));
+    }
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let length = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let body = data.get(pos + 8..pos + 8 + length).ok_or_else(|| error("文件不完整"))?;
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+                let mut tag = u16_at(0);
+                // WAVE_FORMAT_EXTENSIBLE的真实格式在子格式GUID的前两个字节
+                if tag == 0xfffe && body.len() >= 26 {
+                    tag = u16_at(24);
+                }
+                let rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                format = Some((tag, u16_at(2).max(1) as usize, rate, u16_at(14)));
+            }
+            b"data" => {
+                let (tag, channels, rate, bits) = format.ok_or_else(|| error("缺少fmt块"))?;
+                let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+                    (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+                    (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+                    (1, 24) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0,
+                    (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+                    (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                    _ => return Err(PitchError(format!("不支持的WAV格式：{}，{}位", tag, bits))),
+                };
+                let width = bits as usize / 8;
+                let samples = body.chunks_exact(width * channels).map(|frame| {
+                    frame.chunks_exact(width).map(decode).sum::<f32>() / channels as f32
+                }).collect();
+                return Ok((samples, rate));
+            }
+            _ => {}
+        }
+        // 块的长度为奇数时后面补一个字节
+        pos += 8 + length + length % 2;
+    }
+    Err(error("缺少data块"))
+}
+
+/// 用YIN算法估计一帧的基频，没有明显音高时返回`None`
+fn yin(frame: &[f32], rate: f64, options: &PitchOptions) -> Option<f64> {
+    let tau_min = ((rate / options.max_frequency).floor() as usize).max(2);
+    let tau_max = (rate / options.min_frequency).ceil() as usize;
+    let window = frame.len().checked_sub(tau_max + 1)?;
+    if window == 0 || tau_min >= tau_max {
+        return None;
+    }
+
+    // 差函数和累积平均归一化差函数
+    let mut cmnd = vec![1.0; tau_max + 1];
+    let mut sum = 0.0;
+    for tau in 1..=tau_max {
+        let d = (0..window).map(|j| {
+            let delta = (frame[j] - frame[j + tau]) as f64;
+            delta * delta
+        }).sum::<f64>();
+        sum += d;
+        cmnd[tau] = if sum > 0.0 { d * tau as f64 / sum } else { 1.0 };
+    }
+
+    // 第一个低于阈值的谷
+    let mut tau = (tau_min..tau_max).find(|&tau| cmnd[tau] < options.threshold)?;
+    while tau + 1 < tau_max && cmnd[tau + 1] < cmnd[tau] {
+        tau += 1;
+    }
+
+    // 抛物线插值
+    let (a, b, c) = (cmnd[tau - 1], cmnd[tau], cmnd[tau + 1]);
+    let denominator = a - 2.0 * b + c;
+    let shift = if denominator.abs() > f64::EPSILON { 0.5 * (a - c) / denominator } else { 0.0 };
+    Some(rate / (tau as f64 + shift.clamp(-1.0, 1.0)))
+}
+
+/// 识别录音里的音符
+pub fn detect_notes(samples: &[f32], sample_rate: u32, options: &PitchOptions) -> Vec<DetectedNote> {
+    let rate = sample_rate as f64;
+    let hop = ((rate / 200.0) as usize).max(1);
+    let frame_length = ((rate / options.min_frequency).ceil() as usize) * 3;
+
+    // 每帧的音量和MIDI编号（带小数）
+    let mut volumes = Vec::new();
+    let mut pitches = Vec::new();
+    let mut start = 0;
+    while start + frame_length <= samples.len() {
+        let frame = &samples[start..start + frame_length];
+        let rms = (frame.iter().map(|&s| (s * s) as f64).sum::<f64>() / frame.len() as f64).sqrt();
+        let pitch = if rms < options.silence {
+            None
+        } else {
+            yin(frame, rate, options).map(|frequency| 69.0 + 12.0 * (frequency / options.a4).log2())
+        };
+        volumes.push(rms);
+        pitches.push(pitch);
+        start += hop;
+    }
+
+    // 五帧中值滤波去掉八度跳变等零星错误
+    let semitones = pitches.iter().map(|pitch| pitch.map(|midi| midi.round() as i32)).collect::<Vec<_>>();
+    let mut smoothed = (0..semitones.len()).map(|i| {
+        let mut window = semitones[i.saturating_sub(2)..(i + 3).min(semitones.len())].to_vec();
+        window.sort();
+        window[window.len() / 2]
+    }).collect::<Vec<_>>();
+
+    // 音量明显低于前后100毫秒内最大音量的帧是吐音的断开处，把同音反复分开
+    let range = (0.1 * rate / hop as f64) as usize;
+    for (i, pitch) in smoothed.iter_mut().enumerate() {
+        let around = volumes[i.saturating_sub(range)..(i + range + 1).min(volumes.len())].iter().cloned().fold(0.0, f64::max);
+        if volumes[i] < DIP * around {
+            *pitch = None;
+        }
+    }
+
+    let min_frames = (options.min_note * rate / hop as f64).ceil() as usize;
+    let mut notes = Vec::new();
+    let mut index = 0;
+    while index < smoothed.len() {
+        let end = (index..smoothed.len()).find(|&i| smoothed[i] != smoothed[index]).unwrap_or(smoothed.len());
+        if let (Some(semitone), true) = (smoothed[index], end - index >= min_frames) {
+            // 去掉开头和结尾的过渡部分后取音分偏差的中值
+            let margin = (end - index) / 5;
+            let mut cents = pitches[index + margin..end - margin].iter()
+                .filter_map(|&pitch| pitch)
+                .filter(|midi| midi.round() as i32 == semitone)
+                .map(|midi| (midi - semitone as f64) * 100.0)
+                .collect::<Vec<_>>();
+            cents.sort_by(|a, b| a.total_cmp(b));
+            let tone = u8::try_from(semitone).ok().and_then(Tone::from_midi);
+            if let (Some(tone), false) = (tone, cents.is_empty()) {
+                // 帧的中心作为时间
+                let time = |frame: usize| (frame * hop) as f64 / rate + frame_length as f64 / rate / 2.0;
+                notes.push(DetectedNote { start: time(index), end: time(end), tone, cents: cents[cents.len() / 2] });
+            }
+        }
+        index = end;
+    }
+    notes
+}
+
+/// 识别出的音符按速度写成乐谱，时值按十六分音符量化，开头的静音去掉
+pub fn to_score(notes: &[DetectedNote], finger_tone: FingerTone, tempo: u32) -> Score {
+    let grid = DIVISIONS / 4;
+    let offset = notes.first().map_or(0.0, |note| note.start);
+    let quantize = |seconds: f64| {
+        let divisions = (seconds - offset) * tempo as f64 / 60.0 * DIVISIONS as f64;
+        ((divisions / grid as f64).round().max(0.0) as u32) * grid
+    };
+
+    let mut events = Vec::new();
+    let mut time = 0;
+    for note in notes {
+        let start = quantize(note.start).max(time);
+        let end = quantize(note.end).max(start + grid);
+        if start > time {
+            events.push(Event::Rest { duration: start - time });
+        }
+        events.push(Event::Note { tone: note.tone, duration: end - start, lyrics: Vec::new() });
+        time = end;
+    }
+
+    Score { finger_tone, meter: None, tempo: Some(tempo), events }
+}
+
+/// 一个音的评分
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grade {
+    /// 吹对了
+    Correct { expected: Tone, cents: f64 },
+    /// 吹错了音
+    Wrong { expected: Tone, played: Tone, cents: f64 },
+    /// 漏吹
+    Missed { expected: Tone },
+    /// 参考谱里没有的音
+    Extra { played: Tone, start: f64 },
+}
+
+/// 把识别出的音符和参考乐谱按编辑距离对齐，逐个评分
+pub fn grade(notes: &[DetectedNote], reference: &Score) -> Vec<Grade> {
+    let expected = reference.events.iter().filter_map(|event| match event {
+        Event::Note { tone, .. } => Some(*tone),
+        _ => None,
+    }).collect::<Vec<_>>();
+    let (m, n) = (expected.len(), notes.len());
+
+    // cost[i][j]：参考谱前i个音和录音前j个音对齐的最小代价
+    let mut cost = vec![vec![0; n + 1]; m + 1];
+    for (i, row) in cost.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in cost[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=m {
+        for j in 1..=n {
+            let substitution = cost[i - 1][j - 1] + (expected[i - 1] != notes[j - 1].tone) as usize;
+            cost[i][j] = substitution.min(cost[i - 1][j] + 1).min(cost[i][j - 1] + 1);
+        }
+    }
+
+    let mut grades = Vec::new();
+    let (mut i, mut j) = (m, n);
+    // 代价相同时优先认为是吹对的音，其次是漏吹和多吹，最后才是错音
+    while i > 0 || j > 0 {
+        let matched = i > 0 && j > 0 && expected[i - 1] == notes[j - 1].tone;
+        if matched && cost[i][j] == cost[i - 1][j - 1] {
+            grades.push(Grade::Correct { expected: expected[i - 1], cents: notes[j - 1].cents });
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && cost[i][j] == cost[i - 1][j] + 1 {
+            grades.push(Grade::Missed { expected: expected[i - 1] });
+            i -= 1;
+        } else if j > 0 && cost[i][j] == cost[i][j - 1] + 1 {
+            grades.push(Grade::Extra { played: notes[j - 1].tone, start: notes[j - 1].start });
+            j -= 1;
+        } else {
+            let note = &notes[j - 1];
+            grades.push(Grade::Wrong { expected: expected[i - 1], played: note.tone, cents: note.cents });
+            i -= 1;
+            j -= 1;
+        }
+    }
+    grades.reverse();
+    grades
+}
+
+/// 评分报告，音准偏差超过`tolerance`音分的音标出偏高或偏低
+pub fn grade_report(grades: &[Grade], finger_tone: FingerTone, tolerance: f64) -> String {
+    let name = |tone: Tone| tone.to_notation(finger_tone).to_string();
+    let intonation = |cents: f64| {
+        let mark = if cents > tolerance {
+            "，偏高"
+        } else if cents < -tolerance {
+            "，偏低"
+        } else {
+            ""
+        };
+        format!("{:+.0}音分{}", cents, mark)
+    };
+
+    let mut out = String::new();
+    let mut number = 0;
+    let (mut correct, mut wrong, mut missed, mut extra, mut out_of_tune) = (0, 0, 0, 0, 0);
+    let mut deviations = Vec::new();
+    for grade in grades {
+        match *grade {
+            Grade::Correct { expected, cents } => {
+                number += 1;
+                correct += 1;
+                deviations.push(cents);
+                out_of_tune += (cents.abs() > tolerance) as usize;
+                let _ = writeln!(out, "{}. {} 正确（{}）", number, name(expected), intonation(cents));
+            }
+            Grade::Wrong { expected, played, cents } => {
+                number += 1;
+                wrong += 1;
+                let _ = writeln!(out, "{}. {} 错音：吹成了{}（{}）", number, name(expected), name(played), intonation(cents));
+            }
+            Grade::Missed { expected } => {
+                number += 1;
+                missed += 1;
+                let _ = writeln!(out, "{}. {} 漏音", number, name(expected));
+            }
+            Grade::Extra { played, start } => {
+                extra += 1;
+                let _ = writeln!(out, "   多吹了{}（{:.2}秒）", name(played), start);
+            }
+        }
+    }
+
+    let average = if deviations.is_empty() { 0.0 } else { deviations.iter().sum::<f64>() / deviations.len() as f64 };
+    let _ = writeln!(
+        out,
+        "正确 {}/{}，错音 {}，漏音 {}，多吹 {}；平均音准{:+.0}音分，超过±{:.0}音分的音 {}",
+        correct, number, wrong, missed, extra, average, tolerance, out_of_tune
+    );
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::score::MEASURES_PER_LINE;
+    use crate::synth::{synthesize, write_wav, SynthOptions};
+    use std::f64::consts::PI;
+
+    fn render(content: &str, a4: f64) -> Vec<f32> {
+        let score = Score::parse(content, Some(FingerTone::C)).unwrap();
+        synthesize(&score, &SynthOptions { a4, ..Default::default() })
+    }
+
+    #[test]
+    fn test_yin() {
+        let rate = 44100.0;
+        let sine = (0..600).map(|i| (2.0 * PI * 880.0 * i as f64 / rate).sin() as f32).collect::<Vec<_>>();
+        let frequency = yin(&sine, rate, &PitchOptions::default()).unwrap();
+        assert!((frequency - 880.0).abs() < 1.0, "{}", frequency);
+        assert_eq!(yin(&[0.0; 600], rate, &PitchOptions::default()), None);
+    }
+
+    #[test]
+    fn test_read_wav() {
+        let samples = vec![0.0, 0.5, -0.5];
+        let (read, rate) = read_wav(&write_wav(&samples, 8000)).unwrap();
+        assert_eq!(rate, 8000);
+        assert!(read.iter().zip(&samples).all(|(a, b)| (a - b).abs() < 0.001));
+        assert!(read_wav(b"RIFF....WAVE").is_err());
+        assert!(read_wav(b"not a wav file").is_err());
+    }
+
+    #[test]
+    fn test_transcribe() {
+        let samples = render("1=C ♩=120\n1 2 3_ 4_ 0 | 5 5 [1] - |", 440.0);
+        let notes = detect_notes(&samples, 44100, &PitchOptions::default());
+        assert_eq!(notes.iter().map(|note| note.tone).collect::<Vec<_>>(), vec![Tone::C, Tone::D, Tone::E, Tone::F, Tone::G, Tone::G, Tone::HC]);
+        assert!(notes.iter().all(|note| note.cents.abs() < 5.0));
+        let score = to_score(&notes, FingerTone::C, 120);
+        assert_eq!(score.to_jianpu(MEASURES_PER_LINE).unwrap(), "1=C ♩=120\n1 2 3_ 4_ 0 | 5 5 [1] - |\n");
+    }
+
+    #[test]
+    fn test_grade() {
+        // 按A4=452吹，整体偏高约46音分
+        let samples = render("1=C\n1 2 4 5 5 6", 452.0);
+        let notes = detect_notes(&samples, 44100, &PitchOptions::default());
+        let reference = Score::parse("1=C\n1 2 3 5 6 7", None).unwrap();
+        let grades = grade(&notes, &reference);
+        assert_eq!(grades.len(), 7);
+        assert!(matches!(grades[0], Grade::Correct { expected: Tone::C, cents } if (cents - 46.6).abs() < 5.0));
+        assert!(matches!(grades[2], Grade::Wrong { expected: Tone::E, played: Tone::F, .. }));
+        assert!(matches!(grades[3], Grade::Extra { played: Tone::G, .. }));
+        assert!(matches!(grades[6], Grade::Missed { expected: Tone::B }));
+
+        let report = grade_report(&grades, FingerTone::C, 25.0);
+        assert!(report.starts_with("1. 1 正确（+47音分，偏高）\n"), "{}", report);
+        assert!(report.contains("3. 3 错音：吹成了4（+47音分，偏高）\n"));
+        assert!(report.contains("   多吹了5（"));
+        assert!(report.contains("6. 7 漏音\n"));
+        assert!(report.ends_with("正确 4/6，错音 1，漏音 1，多吹 1；平均音准+47音分，超过±25音分的音 4\n"));
+    }
+}
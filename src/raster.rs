@@ -0,0 +1,381 @@
+//! PNG指法图
+//!
+//! 按[`diagram`](crate::diagram)的排版把整份简谱画成一张图片，适合粘贴到聊天软件和幻灯片。
+//! 数字和升号使用内置的笔画字体，不依赖系统字体，所以画不了调号、歌词和注释等文字行，
+//! 遇到这些行时报错，不会悄悄丢掉。
+
+use crate::diagram::{bar_mark, dash_mark, fingering_shapes, notation_marks, rest_marks, row_height, token_width, wrap_line, Mark, Shape, NOTE_BASELINE, NOTE_WIDTH, SPACE_WIDTH};
+use crate::finger::Hole;
+use crate::notation::parser::Token;
+use crate::optimizer::FingeringMode;
+use crate::tone::{fingering_lines, ConvertError, FingerTone};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use tiny_skia::{Color, FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Rect, Stroke, StrokeDash, Transform};
+
+/// 边距（像素）
+const MARGIN: u32 = 24;
+/// PNG输出设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngOptions {
+    /// 图片宽度，按96dpi计的像素
+    pub width: u32,
+    /// 分辨率，图片实际像素为`width * dpi / 96`
+    pub dpi: u32,
+    /// 透明背景
+    pub transparent: bool,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            width: 794,
+            dpi: 96,
+            transparent: false,
+        }
+    }
+}
+
+/// PNG输出错误
+#[derive(Debug)]
+pub enum PngError {
+    Convert(ConvertError),
+    Encode(png::EncodingError),
+    /// 图片尺寸为0或过大
+    Size,
+    /// 内置字体画不了的文字行
+    Text(String),
+}
+
+impl Display for PngError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            PngError::Convert(e) => e.fmt(f),
+            PngError::Encode(e) => e.fmt(f),
+            PngError::Size => "图片尺寸有误".fmt(f),
+            PngError::Text(line) => format!("PNG输出不支持文字行，请先去掉：{}", line).fmt(f),
+        }
+    }
+}
+
+impl From<ConvertError> for PngError {
+    fn from(e: ConvertError) -> PngError {
+        Self::Convert(e)
+    }
+}
+
+impl From<png::EncodingError> for PngError {
+    fn from(e: png::EncodingError) -> PngError {
+        Self::Encode(e)
+    }
+}
+
+impl Error for PngError {}
+
+/// 竖笛数字简谱可视化为一张PNG图片
+pub fn visualize_png(content: &str, finger_tone: FingerTone, mode: FingeringMode, options: &PngOptions) -> Result<Vec<u8>, PngError> {
+    let lines = fingering_lines(content, finger_tone, mode)?;
+    let max_width = options.width.saturating_sub(2 * MARGIN);
+
+    if let Some((token, _)) = lines.iter().flatten().find(|(token, _)| token.is_text()) {
+        return Err(PngError::Text(token.to_string()));
+    }
+    let rows = lines.iter()
+        .flat_map(|line| wrap_line(line, max_width))
+        .collect::<Vec<_>>();
+    let height = 2 * MARGIN + rows.iter().map(|row| row_height(row)).sum::<u32>();
+
+    let scale = options.dpi as f32 / 96.0;
+    let pixel_width = (options.width as f32 * scale).round() as u32;
+    let pixel_height = (height as f32 * scale).round() as u32;
+    let mut pixmap = Pixmap::new(pixel_width, pixel_height).ok_or(PngError::Size)?;
+    if !options.transparent {
+        pixmap.fill(Color::WHITE);
+    }
+
+    let mut y = MARGIN;
+    for row in rows {
+        let mut x = MARGIN;
+        for (token, fingering) in row {
+            let transform = Transform::from_scale(scale, scale).pre_translate(x as f32, y as f32);
+            let (cx, baseline) = ((NOTE_WIDTH / 2) as f32, NOTE_BASELINE as f32);
+            let marks = match (token, fingering) {
+                (Token::Notation(n), Some(fingering)) => {
+                    for shape in fingering_shapes(fingering, cx) {
+                        draw_shape(&mut pixmap, &shape, transform);
+                    }
+                    notation_marks(n, cx, baseline)
+                }
+                (Token::Rest(rest), _) => rest_marks(rest, cx, baseline),
+                (Token::Dash, _) => vec![dash_mark(cx, baseline)],
+                (Token::Bar, _) => vec![bar_mark((SPACE_WIDTH / 2) as f32, baseline)],
+                _ => Vec::new(),
+            };
+            for mark in marks {
+                draw_mark(&mut pixmap, &mark, transform);
+            }
+            x += token_width(token);
+        }
+        y += row_height(row);
+    }
+
+    encode(&pixmap, options.dpi)
+}
+
+fn encode(pixmap: &Pixmap, dpi: u32) -> Result<Vec<u8>, PngError> {
+    let data = pixmap.pixels().iter().flat_map(|pixel| {
+        let color = pixel.demultiply();
+        [color.red(), color.green(), color.blue(), color.alpha()]
+    }).collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, pixmap.width(), pixmap.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // 每米像素数
+    let pixels_per_meter = (dpi as f64 / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+fn paint(r: u8, g: u8, b: u8) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, 255);
+    paint.anti_alias = true;
+    paint
+}
+
+fn draw_shape(pixmap: &mut Pixmap, shape: &Shape, transform: Transform) {
+    match *shape {
+        Shape::Body { x, y, width, height } => {
+            let r = width / 2.0;
+            let mut pb = PathBuilder::new();
+            pb.move_to(x, y + r);
+            pb.line_to(x, y + height - r);
+            pb.quad_to(x, y + height, x + r, y + height);
+            pb.quad_to(x + width, y + height, x + width, y + height - r);
+            pb.line_to(x + width, y + r);
+            pb.quad_to(x + width, y, x + r, y);
+            pb.quad_to(x, y, x, y + r);
+            pb.close();
+            if let Some(path) = pb.finish() {
+                pixmap.stroke_path(&path, &paint(0x99, 0x99, 0x99), &Stroke::default(), transform, None);
+            }
+        }
+        Shape::Separator { x1, x2, y } => {
+            let mut pb = PathBuilder::new();
+            pb.move_to(x1, y);
+            pb.line_to(x2, y);
+            let stroke = Stroke {
+                dash: StrokeDash::new(vec![2.0, 2.0], 0.0),
+                ..Default::default()
+            };
+            if let Some(path) = pb.finish() {
+                pixmap.stroke_path(&path, &paint(0x99, 0x99, 0x99), &stroke, transform, None);
+            }
+        }
+        Shape::Hole { cx, cy, r, hole } => {
+            if let Some(path) = PathBuilder::from_circle(cx, cy, r) {
+                let fill = if hole == Hole::Close { paint(0, 0, 0) } else { paint(0xff, 0xff, 0xff) };
+                pixmap.fill_path(&path, &fill, FillRule::Winding, transform, None);
+                pixmap.stroke_path(&path, &paint(0, 0, 0), &Stroke::default(), transform, None);
+            }
+            if hole == Hole::Half {
+                let mut pb = PathBuilder::new();
+                pb.move_to(cx, cy - r);
+                pb.cubic_to(cx - r * 0.5523, cy - r, cx - r, cy - r * 0.5523, cx - r, cy);
+                pb.cubic_to(cx - r, cy + r * 0.5523, cx - r * 0.5523, cy + r, cx, cy + r);
+                pb.close();
+                if let Some(path) = pb.finish() {
+                    pixmap.fill_path(&path, &paint(0, 0, 0), FillRule::Winding, transform, None);
+                }
+            }
+        }
+    }
+}
+
+fn draw_mark(pixmap: &mut Pixmap, mark: &Mark, transform: Transform) {
+    match mark {
+        Mark::Text { cx, y, size, text } => draw_text(pixmap, text, *cx, *y, *size, transform),
+        Mark::Dot { cx, cy, r } => {
+            if let Some(path) = PathBuilder::from_circle(*cx, *cy, *r) {
+                pixmap.fill_path(&path, &paint(0, 0, 0), FillRule::Winding, transform, None);
+            }
+        }
+        Mark::Line { x1, x2, y } => {
+            let mut pb = PathBuilder::new();
+            pb.move_to(*x1, *y);
+            pb.line_to(*x2, *y);
+            if let Some(path) = pb.finish() {
+                pixmap.stroke_path(&path, &paint(0, 0, 0), &Stroke::default(), transform, None);
+            }
+        }
+        Mark::Bar { x, y1, y2 } => {
+            let mut pb = PathBuilder::new();
+            pb.move_to(*x, *y1);
+            pb.line_to(*x, *y2);
+            if let Some(path) = pb.finish() {
+                pixmap.stroke_path(&path, &paint(0, 0, 0), &Stroke::default(), transform, None);
+            }
+        }
+    }
+}
+
+/// 笔画字体的路径片段，单位为字号的十分之一，y轴向上，基线为0
+#[derive(Debug, Clone, Copy)]
+enum Seg {
+    M(f32, f32),
+    L(f32, f32),
+    C(f32, f32, f32, f32, f32, f32),
+    /// 实心圆点：圆心和半径
+    Dot(f32, f32, f32),
+}
+
+use self::Seg::{C, Dot, L, M};
+
+/// 字形：宽度和笔画
+fn glyph(c: char) -> Option<(f32, &'static [Seg])> {
+    Some(match c {
+        '0' => (6.0, &[M(3.0, 7.0), C(0.7, 7.0, 0.7, 0.0, 3.0, 0.0), C(5.3, 0.0, 5.3, 7.0, 3.0, 7.0)]),
+        '1' => (6.0, &[M(1.6, 5.6), L(3.2, 7.0), L(3.2, 0.0)]),
+        '2' => (6.0, &[M(0.9, 5.4), C(1.2, 7.4, 5.1, 7.4, 5.1, 5.2), C(5.1, 3.8, 3.6, 3.0, 0.9, 0.0), L(5.3, 0.0)]),
+        '3' => (6.0, &[
+            M(0.9, 6.2), C(2.0, 7.4, 5.0, 7.4, 5.0, 5.4), C(5.0, 4.1, 3.8, 3.7, 2.6, 3.7),
+            M(2.6, 3.7), C(4.1, 3.7, 5.2, 3.0, 5.2, 1.8), C(5.2, -0.5, 1.6, -0.5, 0.8, 1.0),
+        ]),
+        '4' => (6.0, &[M(4.1, 0.0), L(4.1, 7.0), L(0.6, 2.0), L(5.5, 2.0)]),
+        '5' => (6.0, &[M(5.0, 7.0), L(1.3, 7.0), L(1.0, 3.9), C(2.6, 4.8, 5.2, 4.5, 5.2, 2.2), C(5.2, -0.6, 1.5, -0.4, 0.8, 1.0)]),
+        '6' => (6.0, &[M(4.8, 6.6), C(3.0, 7.6, 0.8, 6.3, 0.8, 3.0), C(0.8, -0.6, 5.2, -0.6, 5.2, 2.2), C(5.2, 4.8, 1.2, 4.9, 0.8, 2.6)]),
+        '7' => (6.0, &[M(0.8, 7.0), L(5.2, 7.0), L(2.2, 0.0)]),
+        '#' => (6.0, &[
+            M(1.9, 0.4), L(2.5, 6.6), M(3.7, 0.4), L(4.3, 6.6),
+            M(0.8, 2.3), L(5.2, 2.3), M(1.0, 4.6), L(5.4, 4.6),
+        ]),
+        '.' => (3.0, &[Dot(1.5, 0.8, 0.8)]),
+        _ => return None,
+    })
+}
+
+/// 用内置笔画字体画一行字，`cx`为水平中心，`baseline`为基线
+fn draw_text(pixmap: &mut Pixmap, text: &str, cx: f32, baseline: f32, size: f32, transform: Transform) {
+    let unit = size / 10.0;
+    let glyphs = text.chars().filter_map(glyph).collect::<Vec<_>>();
+    let width = glyphs.iter().map(|(advance, _)| advance).sum::<f32>() * unit;
+
+    let stroke = Stroke {
+        width: unit * 0.9,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..Default::default()
+    };
+    let black = paint(0, 0, 0);
+
+    let mut x = cx - width / 2.0;
+    for (advance, segs) in glyphs {
+        let point = |gx: f32, gy: f32| (x + gx * unit, baseline - gy * unit);
+        let mut pb = PathBuilder::new();
+        for seg in segs {
+            match *seg {
+                M(gx, gy) => {
+                    let (px, py) = point(gx, gy);
+                    pb.move_to(px, py);
+                }
+                L(gx, gy) => {
+                    let (px, py) = point(gx, gy);
+                    pb.line_to(px, py);
+                }
+                C(x1, y1, x2, y2, gx, gy) => {
+                    let (p1x, p1y) = point(x1, y1);
+                    let (p2x, p2y) = point(x2, y2);
+                    let (px, py) = point(gx, gy);
+                    pb.cubic_to(p1x, p1y, p2x, p2y, px, py);
+                }
+                Dot(gx, gy, r) => {
+                    let (px, py) = point(gx, gy);
+                    if let Some(rect) = Rect::from_xywh(px - r * unit, py - r * unit, 2.0 * r * unit, 2.0 * r * unit) {
+                        pb.push_oval(rect);
+                    }
+                }
+            }
+        }
+        if let Some(path) = pb.finish() {
+            if segs.iter().all(|seg| matches!(seg, Dot(..))) {
+                pixmap.fill_path(&path, &black, FillRule::Winding, transform, None);
+            } else {
+                pixmap.stroke_path(&path, &black, &stroke, transform, None);
+            }
+        }
+        x += advance * unit;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagram::ROW_HEIGHT;
+
+    fn decode(data: &[u8]) -> (png::OutputInfo, Option<png::PixelDimensions>, Vec<u8>) {
+        let decoder = png::Decoder::new(data);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        let dims = reader.info().pixel_dims;
+        (info, dims, buf)
+    }
+
+    fn pixel(info: &png::OutputInfo, buf: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let offset = (y * info.line_size as u32 + x * 4) as usize;
+        [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]]
+    }
+
+    #[test]
+    fn test_visualize_png() {
+        let data = visualize_png("1 2\n\n3", FingerTone::C, FingeringMode::Standard, &PngOptions::default()).unwrap();
+        let (info, dims, buf) = decode(&data);
+        assert_eq!(info.width, 794);
+        assert_eq!(info.height, 2 * MARGIN + 2 * ROW_HEIGHT);
+        assert_eq!(dims.map(|d| d.xppu), Some(3780));
+        // 白色背景，第一个音符的拇指孔按住
+        assert_eq!(pixel(&info, &buf, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&info, &buf, MARGIN + NOTE_WIDTH / 2, MARGIN + 9), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_text_rows() {
+        for &(content, text) in [("1=C\n1 2", "1=C"), ("1 2\n// 注释", "// 注释"), ("1 2\nw: 孟 姜", "w: 孟 姜")].iter() {
+            match visualize_png(content, FingerTone::C, FingeringMode::Standard, &PngOptions::default()) {
+                Err(PngError::Text(line)) => assert_eq!(line, text),
+                result => panic!("{}: {:?}", content, result.map(|data| data.len())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dpi_and_transparent() {
+        let options = PngOptions { width: 200, dpi: 192, transparent: true };
+        let data = visualize_png("[2]", FingerTone::C, FingeringMode::Standard, &options).unwrap();
+        let (info, dims, buf) = decode(&data);
+        assert_eq!(info.width, 400);
+        assert_eq!(info.height, 2 * (2 * MARGIN + ROW_HEIGHT));
+        assert_eq!(dims.map(|d| d.xppu), Some(7559));
+        assert_eq!(pixel(&info, &buf, 0, 0)[3], 0);
+        // [2]的拇指孔全开：白色填充
+        assert_eq!(pixel(&info, &buf, 2 * (MARGIN + NOTE_WIDTH / 2), 2 * (MARGIN + 9)), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_glyphs() {
+        for c in "01234567#.".chars() {
+            assert!(glyph(c).is_some(), "{}", c);
+        }
+        assert!(glyph('注').is_none());
+        assert!(glyph('(').is_none());
+    }
+}
@@ -0,0 +1,376 @@
+//! 乐谱模型
+//!
+//! 把简谱解析成带绝对音高和时值的事件序列，供MusicXML等乐谱格式使用，也可以反过来写成简谱。
+//! 不带减时线的音符为四分音符，`-`把前一个音符或休止符延长一拍。
+
+use crate::notation::parser::{Header, Meter, Parser, Token};
+use crate::key::read_header;
+use crate::tone::{ConvertError, FingerTone, Tone};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// 每个四分音符的时值单位数，能表示到三十二分附点音符和三连音
+pub const DIVISIONS: u32 = 48;
+
+/// 默认拍号
+pub const DEFAULT_METER: Meter = Meter { beats: 4, beat_type: 4 };
+
+/// 乐谱事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// 音符，`lyrics`按段落排列，没有歌词的段落为`None`
+    Note { tone: Tone, duration: u32, lyrics: Vec<Option<String>> },
+    Rest { duration: u32 },
+    /// 小节线
+    Bar,
+}
+
+impl Event {
+    /// 时值，小节线为0
+    pub fn duration(&self) -> u32 {
+        match self {
+            Event::Note { duration, .. } | Event::Rest { duration } => *duration,
+            Event::Bar => 0,
+        }
+    }
+}
+
+/// 单声部乐谱
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub finger_tone: FingerTone,
+    pub meter: Option<Meter>,
+    /// 每分钟四分音符数
+    pub tempo: Option<u32>,
+    pub events: Vec<Event>,
+}
+
+impl Score {
+    /// 解析简谱。调、拍号和速度以第一个调号行为准，`finger_tone`不为`None`时覆盖调号行的调
+    pub fn parse(content: &str, finger_tone: Option<FingerTone>) -> Result<Score, ConvertError> {
+        let lines = Parser::from_str(content)?.lines();
+        let header = lines.iter().flatten().find_map(|token| match token {
+            Token::Header(header) => Some(header),
+            _ => None,
+        });
+
+        let finger_tone = match (finger_tone, header) {
+            (Some(finger_tone), _) => finger_tone,
+            (None, Some(header)) => read_header(header).map(|(one, _)| one)
+                .map_err(|_| ConvertError::NotFound(format!("调号有误：{}", header)))?,
+            (None, None) => return Err(ConvertError::NotFound("没有指定调".to_owned())),
+        };
+
+        let mut score = Score {
+            finger_tone,
+            meter: header.and_then(|header| header.meter),
+            tempo: header.and_then(|header| header.tempo),
+            events: Vec::new(),
+        };
+
+        // 上一行简谱的音符在events中的位置，歌词行按顺序对应
+        let mut line_notes = Vec::new();
+        let mut verse = 0;
+
+        for line in &lines {
+            if line.iter().any(|token| !token.is_text() && *token != Token::Whitespace) {
+                line_notes.clear();
+                verse = 0;
+            }
+
+            for token in line {
+                match token {
+                    Token::Notation(n) => {
+                        let s = n.pitch().to_string();
+                        let tone = Tone::from_notation(n, finger_tone)
+                            .ok_or_else(|| ConvertError::NotFound(format!("出现未知音符：{}", s)))?;
+                        line_notes.push(score.events.len());
+                        score.events.push(Event::Note { tone, duration: duration(n.underline(), n.dotted()), lyrics: Vec::new() });
+                    }
+                    Token::Rest(rest) => {
+                        score.events.push(Event::Rest { duration: duration(rest.underline, rest.dotted) });
+                    }
+                    Token::Dash => match score.events.last_mut() {
+                        Some(Event::Note { duration, .. }) | Some(Event::Rest { duration }) => *duration += DIVISIONS,
+                        _ => return Err(ConvertError::NotFound("增时线前面没有音符".to_owned())),
+                    },
+                    Token::Bar => score.events.push(Event::Bar),
+                    Token::Lyrics(words) => {
+                        for (&index, word) in line_notes.iter().zip(words) {
+                            // `*`表示这个音符不唱
+                            if let (Event::Note { lyrics, .. }, false) = (&mut score.events[index], word == "*") {
+                                lyrics.resize(verse + 1, None);
+                                lyrics[verse] = Some(word.clone());
+                            }
+                        }
+                        verse += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(score)
+    }
+
+    /// 每小节的时值
+    pub fn measure_duration(&self) -> u32 {
+        let meter = self.meter.unwrap_or(DEFAULT_METER);
+        meter.beats as u32 * DIVISIONS * 4 / meter.beat_type as u32
+    }
+
+    /// 按小节分组的音符和休止符。有小节线时按小节线分，否则按拍号填满一小节再换下一小节
+    pub fn measures(&self) -> Vec<Vec<&Event>> {
+        let mut measures = Vec::new();
+        let mut measure = Vec::new();
+
+        if self.events.contains(&Event::Bar) {
+            for event in &self.events {
+                if *event == Event::Bar {
+                    measures.push(std::mem::take(&mut measure));
+                } else {
+                    measure.push(event);
+                }
+            }
+        } else {
+            let max = self.measure_duration();
+            let mut total = 0;
+            for event in &self.events {
+                if total + event.duration() > max && !measure.is_empty() {
+                    measures.push(std::mem::take(&mut measure));
+                    total = 0;
+                }
+                total += event.duration();
+                measure.push(event);
+            }
+        }
+
+        if !measure.is_empty() {
+            measures.push(measure);
+        }
+        measures
+    }
+}
+
+/// 让竖笛能吹的音最多的八度移动（半音数），相同时移动越少越好。
+/// 导入的乐谱常按实际音高低一个八度记谱，`midis`为所有音符的MIDI编号
+pub fn fit_octave(midis: impl Iterator<Item = i32> + Clone) -> i32 {
+    [0, 12, -12, 24, -24].iter().copied().max_by_key(|&shift| {
+        let playable = midis.clone().filter(|midi| {
+            u8::try_from(midi + shift).ok()
+                .and_then(Tone::from_midi)
+                .and_then(Tone::to_finger)
+                .is_some()
+        }).count();
+        // max_by_key取最后一个最大值，所以把移动少的排在后面
+        (playable, -shift.abs())
+    }).unwrap_or(0)
+}
+
+/// 简谱默认每行的小节数
+pub const MEASURES_PER_LINE: usize = 4;
+
+/// 简谱能直接写出的短时值：`(时值, 减时线, 附点)`
+const SHORT_DURATIONS: [(u32, u8, bool); 8] = [
+    (72, 0, true),
+    (48, 0, false),
+    (36, 1, true),
+    (24, 1, false),
+    (18, 2, true),
+    (12, 2, false),
+    (9, 3, true),
+    (6, 3, false),
+];
+
+impl Score {
+    /// 写成简谱文本：调号行在最前，每行`measures_per_line`小节，歌词行跟在对应的简谱行后面。
+    /// 简谱没有连音线，写不出的时值拆成几个同音高的音符，后面的音符不配歌词；
+    /// 拆不开的时值（例如三连音）报错
+    pub fn to_jianpu(&self, measures_per_line: usize) -> Result<String, ConvertError> {
+        let header = Header { key: self.finger_tone.to_string(), tonality: None, meter: self.meter, tempo: self.tempo };
+        let mut lines = vec![header.to_string()];
+
+        let measures = self.measures();
+        for chunk in measures.chunks(measures_per_line.max(1)) {
+            let mut items = Vec::new();
+            let mut lyrics: Vec<Vec<String>> = Vec::new();
+            let mut notes = 0;
+
+            for measure in chunk {
+                for event in measure {
+                    match event {
+                        Event::Note { tone, duration, lyrics: words } => {
+                            let notation = tone.to_notation(self.finger_tone).to_string();
+                            let pieces = split_duration(*duration)?;
+                            for &piece in &pieces {
+                                items.push(write_duration(&notation, piece, false)?);
+                            }
+                            for (verse, word) in words.iter().enumerate() {
+                                if lyrics.len() <= verse {
+                                    lyrics.resize(verse + 1, Vec::new());
+                                }
+                                let verse = &mut lyrics[verse];
+                                verse.resize(notes, "*".to_owned());
+                                verse.push(word.clone().unwrap_or_else(|| "*".to_owned()));
+                            }
+                            notes += pieces.len();
+                        }
+                        Event::Rest { duration } => {
+                            for piece in split_duration(*duration)? {
+                                items.push(write_duration("0", piece, true)?);
+                            }
+                        }
+                        Event::Bar => {}
+                    }
+                }
+                items.push("|".to_owned());
+            }
+
+            lines.push(items.join(" "));
+            for verse in lyrics {
+                lines.push(format!("w: {}", verse.join(" ")));
+            }
+        }
+
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+/// 拆成简谱能直接写出的时值：先写整拍，剩下不到一拍的部分从长到短拆开
+fn split_duration(duration: u32) -> Result<Vec<u32>, ConvertError> {
+    if duration == 0 {
+        return Err(unwritable(duration));
+    }
+    if SHORT_DURATIONS.iter().any(|&(value, _, _)| value == duration) {
+        return Ok(vec![duration]);
+    }
+
+    let mut pieces = Vec::new();
+    if duration >= DIVISIONS {
+        pieces.push(duration / DIVISIONS * DIVISIONS);
+    }
+    let mut rest = duration % DIVISIONS;
+    // 剩下的部分要么为0，要么还能再拆：6和9能拼出不小于6的所有3的倍数
+    let writable = |rest: u32| rest == 0 || (rest >= 6 && rest / 3 * 3 == rest);
+    while rest > 0 {
+        let value = SHORT_DURATIONS.iter()
+            .map(|&(value, _, _)| value)
+            .find(|&value| value <= rest && writable(rest - value))
+            .ok_or_else(|| unwritable(duration))?;
+        pieces.push(value);
+        rest -= value;
+    }
+    Ok(pieces)
+}
+
+fn unwritable(duration: u32) -> ConvertError {
+    ConvertError::NotFound(format!("简谱写不出的时值：{}/{}拍", duration, DIVISIONS))
+}
+
+/// 带时值的音符或休止符，超过一拍的音符用增时线，休止符重复写`0`；时值要先用`split_duration`拆开
+fn write_duration(notation: &str, duration: u32, is_rest: bool) -> Result<String, ConvertError> {
+    if let Some(&(_, underline, dotted)) = SHORT_DURATIONS.iter().find(|&&(value, _, _)| value == duration) {
+        return Ok(format!("{}{}{}", notation, "_".repeat(underline as usize), if dotted { "." } else { "" }));
+    }
+    if duration == 0 || duration / DIVISIONS * DIVISIONS != duration {
+        return Err(unwritable(duration));
+    }
+    let beats = (duration / DIVISIONS) as usize;
+    let extend = if is_rest { "0" } else { "-" };
+    let mut parts = vec![notation];
+    parts.resize(beats, extend);
+    Ok(parts.join(" "))
+}
+
+/// 减时线和附点对应的时值
+fn duration(underline: u8, dotted: bool) -> u32 {
+    let duration = DIVISIONS >> underline;
+    if dotted {
+        duration * 3 / 2
+    } else {
+        duration
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let score = Score::parse("1=D 3/4 ♩=90\n1_ 2_ 3. | 0 - 5 |\nw: 一 * 二 三\n// 注释\nw: a b", None).unwrap();
+        assert_eq!(score.finger_tone, FingerTone::D);
+        assert_eq!(score.meter, Some(Meter { beats: 3, beat_type: 4 }));
+        assert_eq!(score.tempo, Some(90));
+        assert_eq!(score.events[0], Event::Note { tone: Tone::D, duration: 24, lyrics: vec![Some("一".to_owned()), Some("a".to_owned())] });
+        assert_eq!(score.events[1], Event::Note { tone: Tone::E, duration: 24, lyrics: vec![None, Some("b".to_owned())] });
+        assert_eq!(score.events[2], Event::Note { tone: Tone::SF, duration: 72, lyrics: vec![Some("二".to_owned())] });
+        assert_eq!(score.events[4], Event::Rest { duration: 96 });
+        assert_eq!(score.events[5], Event::Note { tone: Tone::A, duration: 48, lyrics: vec![Some("三".to_owned())] });
+        assert_eq!(score.measures().len(), 2);
+        assert_eq!(score.measure_duration(), 144);
+
+        // 参数覆盖调号行
+        assert_eq!(Score::parse("1=D\n1", Some(FingerTone::C)).unwrap().events[0].duration(), 48);
+        assert!(Score::parse("1", None).is_err());
+        assert!(Score::parse("1=H\n1", None).is_err());
+        assert!(Score::parse("- 1", Some(FingerTone::C)).is_err());
+    }
+
+    #[test]
+    fn test_to_jianpu() {
+        let content = "1=D 3/4 ♩=90\n1_ 2_ 3. | 0 - 5 | 0__ 0 [1] - - - | 2 |\nw: 一 * 二 三 四\nw: a b\n5 |\n";
+        let jianpu = Score::parse(content, None).unwrap().to_jianpu(MEASURES_PER_LINE).unwrap();
+        assert_eq!(jianpu, "1=D 3/4 ♩=90\n1_ 2_ 3. | 0 0 5 | 0__ 0 [1] - - - | 2 |\nw: 一 * 二 三 四\nw: a b\n5 |\n");
+        assert_eq!(Score::parse(&jianpu, None).unwrap().events.len(), Score::parse(content, None).unwrap().events.len() + 1);
+
+        // 两拍半拆成两个音符，时值不变，歌词只配第一个
+        let note = |tone, duration, word: &str| Event::Note { tone, duration, lyrics: vec![Some(word.to_owned())] };
+        let score = Score {
+            finger_tone: FingerTone::C,
+            meter: None,
+            tempo: None,
+            events: vec![note(Tone::C, 120, "啊"), note(Tone::D, 24, "哦"), Event::Bar, Event::Rest { duration: 120 }, Event::Bar, note(Tone::E, 48, "呀"), Event::Bar],
+        };
+        let jianpu = score.to_jianpu(2).unwrap();
+        assert_eq!(jianpu, "1=C\n1 - 1_ 2_ | 0 0 0_ |\nw: 啊 * 哦\n3 |\nw: 呀\n");
+        let again = Score::parse(&jianpu, None).unwrap();
+        assert_eq!(again.measures().iter().map(|m| m.iter().map(|e| e.duration()).sum::<u32>()).collect::<Vec<_>>(), vec![144, 120, 48]);
+
+        let triplet = Score { events: vec![Event::Note { tone: Tone::C, duration: 16, lyrics: Vec::new() }], ..score };
+        assert!(triplet.to_jianpu(MEASURES_PER_LINE).is_err());
+    }
+
+    #[test]
+    fn test_fit_octave() {
+        assert_eq!(fit_octave([72, 74, 76].iter().copied()), 0);
+        assert_eq!(fit_octave([60, 62, 64].iter().copied()), 12);
+        assert_eq!(fit_octave([108, 110].iter().copied()), -12);
+        assert_eq!(fit_octave([].iter().copied()), 0);
+    }
+
+    #[test]
+    fn test_split_duration() {
+        assert_eq!(split_duration(48), Ok(vec![48]));
+        assert_eq!(split_duration(144), Ok(vec![144]));
+        // 两拍半
+        assert_eq!(split_duration(120), Ok(vec![96, 24]));
+        assert_eq!(split_duration(30), Ok(vec![24, 6]));
+        assert_eq!(split_duration(15), Ok(vec![9, 6]));
+        assert!(split_duration(16).is_err());
+        assert!(split_duration(100).is_err());
+        assert!(split_duration(0).is_err());
+        assert_eq!(write_duration("(5)", 144, false), Ok("(5) - -".to_owned()));
+        assert_eq!(write_duration("0", 96, true), Ok("0 0".to_owned()));
+        assert_eq!(write_duration("1", 18, false), Ok("1__.".to_owned()));
+        assert!(write_duration("1", 0, false).is_err());
+        assert!(write_duration("1", 60, false).is_err());
+    }
+
+    #[test]
+    fn test_measures_by_meter() {
+        let score = Score::parse("1 2 3 4 5 6 7 [1]. 1_", Some(FingerTone::C)).unwrap();
+        let measures = score.measures();
+        assert_eq!(measures.iter().map(|m| m.len()).collect::<Vec<_>>(), vec![4, 3, 2]);
+    }
+}
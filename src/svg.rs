@@ -0,0 +1,218 @@
+//! SVG指法图
+
+use crate::diagram::{bar_mark, dash_mark, fingering_shapes, notation_marks, rest_marks, row_height, token_width, wrap_line, Mark, Row, Shape, NOTE_BASELINE, NOTE_SIZE, NOTE_WIDTH, SPACE_WIDTH};
+use crate::finger::{Fingering, Hole};
+use crate::notation::parser::Token;
+use crate::optimizer::FingeringMode;
+use crate::tone::{fingering_lines, ConvertError, FingerTone};
+use std::fmt::Write;
+
+/// 页边距
+const MARGIN: u32 = 40;
+
+pub(crate) const STYLE: &str = ".body{fill:none;stroke:#999;stroke-width:1}\
+.sep{stroke:#999;stroke-width:1;stroke-dasharray:2,2}\
+.open{fill:#fff;stroke:#000;stroke-width:1}\
+.close{fill:#000;stroke:#000;stroke-width:1}\
+.note{font-family:monospace;font-size:16px;text-anchor:middle}\
+.sharp{font-family:monospace;font-size:10px;text-anchor:middle}\
+.dur{stroke:#000;stroke-width:1}\
+.raw{font-family:sans-serif;font-size:14px}";
+
+/// SVG页面设置，单位为像素
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgOptions {
+    pub page_width: u32,
+    pub page_height: u32,
+}
+
+impl Default for SvgOptions {
+    /// A4纸，96dpi
+    fn default() -> Self {
+        Self {
+            page_width: 794,
+            page_height: 1123,
+        }
+    }
+}
+
+/// 竖笛数字简谱可视化为SVG，每页一个独立的SVG文档
+pub fn visualize_svg(content: &str, finger_tone: FingerTone, mode: FingeringMode, options: &SvgOptions) -> Result<Vec<String>, ConvertError> {
+    let lines = fingering_lines(content, finger_tone, mode)?;
+    let max_width = options.page_width.saturating_sub(2 * MARGIN);
+
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut y = MARGIN;
+
+    for row in lines.iter().flat_map(|line| wrap_line(line, max_width)) {
+        let height = row_height(row);
+        if y + height > options.page_height.saturating_sub(MARGIN) && !page.is_empty() {
+            pages.push(finish_page(&page, options));
+            page.clear();
+            y = MARGIN;
+        }
+        render_row(&mut page, row, MARGIN, y);
+        y += height;
+    }
+
+    if !page.is_empty() || pages.is_empty() {
+        pages.push(finish_page(&page, options));
+    }
+
+    Ok(pages)
+}
+
+fn render_row(out: &mut String, row: Row, x: u32, y: u32) {
+    let mut x = x;
+    let (cx, baseline) = ((NOTE_WIDTH / 2) as f32, NOTE_BASELINE as f32);
+    for (token, fingering) in row {
+        let marks = match (token, fingering) {
+            (Token::Notation(n), Some(fingering)) => {
+                let _ = write!(out, r#"<g transform="translate({},{})">"#, x, y);
+                for shape in fingering_shapes(fingering, cx) {
+                    render_shape(out, &shape);
+                }
+                notation_marks(n, cx, baseline)
+            }
+            (Token::Rest(rest), _) => {
+                let _ = write!(out, r#"<g transform="translate({},{})">"#, x, y);
+                rest_marks(rest, cx, baseline)
+            }
+            (Token::Dash, _) => {
+                let _ = write!(out, r#"<g transform="translate({},{})">"#, x, y);
+                vec![dash_mark(cx, baseline)]
+            }
+            (Token::Bar, _) => {
+                let _ = write!(out, r#"<g transform="translate({},{})">"#, x, y);
+                vec![bar_mark((SPACE_WIDTH / 2) as f32, baseline)]
+            }
+            (token, _) if token.is_text() => {
+                let _ = write!(out, r#"<text class="raw" x="{}" y="{}">{}</text>"#, x, y + 20, escape(&token.to_string()));
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        if !marks.is_empty() {
+            for mark in marks {
+                render_mark(out, &mark);
+            }
+            out.push_str("</g>");
+        }
+        x += token_width(token);
+    }
+}
+
+fn render_mark(out: &mut String, mark: &Mark) {
+    let _ = match mark {
+        Mark::Text { cx, y, size, text } => {
+            let class = if *size < NOTE_SIZE { "sharp" } else { "note" };
+            write!(out, r#"<text class="{}" x="{}" y="{}">{}</text>"#, class, cx, y, escape(text))
+        }
+        Mark::Dot { cx, cy, r } => write!(out, r#"<circle class="close" cx="{}" cy="{}" r="{}"/>"#, cx, cy, r),
+        Mark::Line { x1, x2, y } => write!(out, r#"<line class="dur" x1="{}" y1="{}" x2="{}" y2="{}"/>"#, x1, y, x2, y),
+        Mark::Bar { x, y1, y2 } => write!(out, r#"<line class="dur" x1="{}" y1="{}" x2="{}" y2="{}"/>"#, x, y1, x, y2),
+    };
+}
+
+/// 单个指法图的`<svg>`元素，样式由外部的CSS提供
+pub fn fingering_svg(fingering: &Fingering) -> String {
+    let mut out = format!(r#"<svg class="fingering" width="{w}" height="130" viewBox="0 0 {w} 130">"#, w = NOTE_WIDTH);
+    for shape in fingering_shapes(fingering, (NOTE_WIDTH / 2) as f32) {
+        render_shape(&mut out, &shape);
+    }
+    out.push_str("</svg>");
+    out
+}
+
+fn render_shape(out: &mut String, shape: &Shape) {
+    let _ = match *shape {
+        Shape::Body { x, y, width, height } => write!(
+            out,
+            r#"<rect class="body" x="{}" y="{}" width="{}" height="{}" rx="{}"/>"#,
+            x, y, width, height, width / 2.0
+        ),
+        Shape::Separator { x1, x2, y } => write!(out, r#"<line class="sep" x1="{}" y1="{}" x2="{}" y2="{}"/>"#, x1, y, x2, y),
+        Shape::Hole { cx, cy, r, hole } => {
+            let class = if hole == Hole::Close { "close" } else { "open" };
+            let _ = write!(out, r#"<circle class="{}" cx="{}" cy="{}" r="{}"/>"#, class, cx, cy, r);
+            if hole == Hole::Half {
+                let _ = write!(out, r#"<path class="close" d="M{},{} A{},{} 0 0,0 {},{} Z"/>"#, cx, cy - r, r, r, cx, cy + r);
+            }
+            Ok(())
+        }
+    };
+}
+
+fn finish_page(body: &str, options: &SvgOptions) -> String {
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">
+<style>{style}</style>
+<rect width="100%" height="100%" fill="#fff"/>
+{body}
+</svg>
+"##,
+        w = options.page_width,
+        h = options.page_height,
+        style = STYLE,
+        body = body
+    )
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagram::ROW_HEIGHT;
+
+    fn visualize(content: &str, options: &SvgOptions) -> Vec<String> {
+        visualize_svg(content, FingerTone::C, FingeringMode::Standard, options).unwrap()
+    }
+
+    #[test]
+    fn test_visualize_svg() {
+        let pages = visualize("1 #1 2\n// <注释>", &SvgOptions::default());
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+        assert!(page.starts_with("<?xml"));
+        assert!(page.trim_end().ends_with("</svg>"));
+        assert_eq!(page.matches(r#"<text class="note""#).count(), 3);
+        assert!(page.contains(r#"<text class="sharp" x="8" y="146">#</text>"#));
+        assert!(!page.contains("(1)"));
+        assert!(page.contains("// &lt;注释&gt;"));
+        // #1的第七孔半按：只按住大孔
+        assert_eq!(page.matches("<path").count(), 0);
+    }
+
+    #[test]
+    fn test_half_hole() {
+        let pages = visualize("[3]", &SvgOptions::default());
+        assert_eq!(pages[0].matches("<path").count(), 1);
+    }
+
+    #[test]
+    fn test_octave_dots() {
+        let page = &visualize_svg("(7)_ [1]. 2 - -", FingerTone::D, FingeringMode::Standard, &SvgOptions::default()).unwrap()[0];
+        assert!(!page.contains(">(7)"));
+        assert!(!page.contains(">[1]"));
+        // 一条减时线、两条增时线
+        assert_eq!(page.matches(r#"<line class="dur""#).count(), 3);
+        // 低音点、高音点、附点
+        assert_eq!(page.matches(r#"r="1.5""#).count(), 3);
+        assert_eq!(page.matches(r#"<text class="note""#).count(), 3);
+    }
+
+    #[test]
+    fn test_wrap_and_paginate() {
+        let options = SvgOptions { page_width: 2 * MARGIN + 3 * NOTE_WIDTH + 2 * SPACE_WIDTH, page_height: 2 * MARGIN + 2 * ROW_HEIGHT };
+        assert_eq!(visualize("1 2 3", &options).len(), 1);
+        assert_eq!(visualize("1 2 3 4 5 6", &options).len(), 1);
+        assert_eq!(visualize("1 2 3 4 5 6 7", &options).len(), 2);
+        assert_eq!(visualize("1 2 3\n4 5 6\n7", &options).len(), 2);
+        assert_eq!(visualize("", &options).len(), 1);
+    }
+}
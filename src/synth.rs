@@ -0,0 +1,224 @@
+//! 竖笛音色的离线合成，输出WAV
+//!
+//! 加法合成：基音很强，泛音以奇次为主且很弱，接近竖笛的音色。每个音开头有一小段噪声和二次泛音的“吐音”（chiff），
+//! 整个发音过程叠加经过滤波的气息噪声。噪声用固定种子的伪随机数，同样的输入得到同样的输出。
+
+use crate::midi::DEFAULT_TEMPO;
+use crate::score::{Event, Score, DIVISIONS};
+use std::f64::consts::PI;
+
+/// 各次泛音的振幅，从基音开始
+const HARMONICS: [f64; 6] = [1.0, 0.08, 0.22, 0.04, 0.07, 0.02];
+
+/// 起音时间（秒）
+const ATTACK: f64 = 0.02;
+
+/// 吐音的持续时间（秒）
+const CHIFF: f64 = 0.03;
+
+/// 每个音结尾的收音时间（秒），音与音之间因此有一点断开，像吐音演奏
+const RELEASE: f64 = 0.03;
+
+/// 气息噪声的振幅
+const BREATH: f64 = 0.2;
+
+/// 气息噪声的低通截止频率
+const BREATH_CUTOFF: f64 = 1500.0;
+
+/// 合成设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SynthOptions {
+    pub sample_rate: u32,
+    /// A4的频率
+    pub a4: f64,
+    /// 简谱没有调号行速度时使用的速度
+    pub tempo: u32,
+    /// 总音量，0到1
+    pub volume: f64,
+}
+
+impl Default for SynthOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            a4: 440.0,
+            tempo: DEFAULT_TEMPO,
+            volume: 0.5,
+        }
+    }
+}
+
+/// 合成整首乐谱，返回-1到1之间的采样
+pub fn synthesize(score: &Score, options: &SynthOptions) -> Vec<f32> {
+    let tempo = score.tempo.unwrap_or(options.tempo).max(1) as f64;
+    let rate = options.sample_rate as f64;
+    let seconds = |duration: u32| duration as f64 / DIVISIONS as f64 * 60.0 / tempo;
+    let mut noise = Noise::new(0x2545_f491);
+    let mut samples = Vec::new();
+
+    for event in &score.events {
+        let length = (seconds(event.duration()) * rate).round() as usize;
+        match event {
+            Event::Note { tone, .. } => {
+                let frequency = tone.frequency(options.a4);
+                samples.extend(note(frequency, length, rate, options.volume, &mut noise));
+            }
+            Event::Rest { .. } => samples.resize(samples.len() + length, 0.0),
+            Event::Bar => {}
+        }
+    }
+    samples
+}
+
+/// 一个音的采样
+fn note(frequency: f64, length: usize, rate: f64, volume: f64, noise: &mut Noise) -> Vec<f32> {
+    let release = (RELEASE * rate).min(length as f64 / 2.0);
+    let attack = (ATTACK * rate).min(length as f64 / 2.0);
+    let chiff = CHIFF * rate;
+    // 低于奈奎斯特频率的泛音
+    let harmonics = HARMONICS.iter().enumerate()
+        .filter(|&(index, _)| frequency * ((index + 1) as f64) < rate / 2.0)
+        .map(|(index, &amplitude)| ((index + 1) as f64, amplitude))
+        .collect::<Vec<_>>();
+    let norm = HARMONICS.iter().sum::<f64>();
+    // 两级低通滤波后的气息噪声
+    let mut breath = [0.0; 2];
+    let smoothing = (-2.0 * PI * BREATH_CUTOFF / rate).exp();
+
+    (0..length).map(|i| {
+        let t = i as f64 / rate;
+        let position = i as f64;
+        let envelope = (position / attack.max(1.0)).min(1.0) * ((length as f64 - position) / release.max(1.0)).min(1.0);
+
+        let mut value = harmonics.iter()
+            .map(|&(n, amplitude)| amplitude * (2.0 * PI * frequency * n * t).sin())
+            .sum::<f64>() / norm;
+
+        breath[0] = smoothing * breath[0] + (1.0 - smoothing) * noise.next();
+        breath[1] = smoothing * breath[1] + (1.0 - smoothing) * breath[0];
+        value += BREATH * breath[1];
+
+        if position < chiff {
+            let decay = 1.0 - position / chiff;
+            value += decay * (0.5 * noise.next() + 0.3 * (2.0 * PI * frequency * 2.0 * t).sin());
+        }
+
+        (value * envelope * volume).clamp(-1.0, 1.0) as f32
+    }).collect()
+}
+
+/// 单声道16位PCM的WAV文件
+pub fn write_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_length = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_length as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_length).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    // PCM，单声道
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        out.extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16).to_le_bytes());
+    }
+    out
+}
+
+/// xorshift伪随机数，输出-1到1之间的白噪声
+struct Noise(u32);
+
+impl Noise {
+    fn new(seed: u32) -> Self {
+        Noise(seed.max(1))
+    }
+
+    fn next(&mut self) -> f64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x as f64 / u32::MAX as f64 * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tone::FingerTone;
+
+    /// Goertzel算法求某个频率的能量
+    fn power(samples: &[f32], frequency: f64, rate: f64) -> f64 {
+        let coefficient = 2.0 * (2.0 * PI * frequency / rate).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &sample in samples {
+            let s = sample as f64 + coefficient * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+        (s1 * s1 + s2 * s2 - coefficient * s1 * s2) / samples.len() as f64
+    }
+
+    fn render(content: &str, options: &SynthOptions) -> Vec<f32> {
+        synthesize(&Score::parse(content, Some(FingerTone::C)).unwrap(), options)
+    }
+
+    #[test]
+    fn test_spectrum() {
+        let options = SynthOptions::default();
+        // 高音竖笛的6（A5）为880Hz
+        let samples = render("6 - - -", &options);
+        let steady = &samples[4410..samples.len() - 4410];
+        let fundamental = power(steady, 880.0, 44100.0);
+        assert!(fundamental > 100.0 * power(steady, 1760.0, 44100.0));
+        assert!(fundamental > 10.0 * power(steady, 2640.0, 44100.0));
+        assert!(power(steady, 2640.0, 44100.0) > power(steady, 1760.0, 44100.0));
+        assert!(fundamental > 1000.0 * power(steady, 1000.0, 44100.0));
+        assert!(fundamental > 1000.0 * power(steady, 440.0, 44100.0));
+
+        // 改变A4的参考频率
+        let samples = render("6 - - -", &SynthOptions { a4: 415.0, ..options });
+        let steady = &samples[4410..samples.len() - 4410];
+        assert!(power(steady, 830.0, 44100.0) > 100.0 * power(steady, 880.0, 44100.0));
+    }
+
+    #[test]
+    fn test_chiff() {
+        // 开头的吐音比稳定部分有更多的高频噪声
+        let samples = render("1 - - -", &SynthOptions::default());
+        let high = |samples: &[f32]| power(samples, 7000.0, 44100.0) + power(samples, 9000.0, 44100.0);
+        assert!(high(&samples[0..1300]) > 10.0 * high(&samples[20000..21300]));
+        // 气息噪声一直存在
+        assert!(power(&samples[20000..40000], 500.0, 44100.0) > 0.0);
+    }
+
+    #[test]
+    fn test_timing() {
+        let options = SynthOptions { sample_rate: 8000, ..Default::default() };
+        // 120拍每分钟，一拍0.5秒
+        assert_eq!(render("1 2_ 0_", &options).len(), 8000);
+        let samples = render("1=C ♩=60\n1 0", &options);
+        assert_eq!(samples.len(), 16000);
+        assert!(samples[8000..].iter().all(|&s| s == 0.0));
+        assert!(samples[..8000].iter().all(|s| s.abs() <= 1.0));
+        assert_eq!(samples.first(), Some(&0.0));
+        assert_eq!(render("1 2", &options), render("1 2", &options));
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let wav = write_wav(&[0.0, 1.0, -1.0], 8000);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(wav.len(), 50);
+        assert_eq!(&wav[4..8], &42u32.to_le_bytes());
+        assert_eq!(&wav[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &6u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[0, 0, 0xff, 0x7f, 0x01, 0x80]);
+    }
+}
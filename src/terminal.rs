@@ -0,0 +1,270 @@
+//! 紧凑的终端指法图
+//!
+//! 每行简谱只占9行（8个孔加音符），按终端宽度折行，可以用ANSI颜色区分开、半按、按住。
+//! 横排时每个音符占一行，孔位横向排开，适合窄屏阅读。
+
+use crate::diagram::{wrap_line_with, Row};
+use crate::finger::{Fingering, Hole};
+use crate::notation::parser::Token;
+use crate::optimizer::FingeringMode;
+use crate::tone::{fingering_lines, ConvertError, FingerTone};
+
+/// 排列方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Layout {
+    /// 音符横向排开，孔位竖向排列
+    #[default]
+    Vertical,
+    /// 每个音符占一行，孔位横向排开
+    Horizontal,
+}
+
+/// 终端输出设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerminalOptions {
+    /// 终端宽度（字符数）
+    pub width: usize,
+    /// 是否使用ANSI颜色
+    pub color: bool,
+    /// 是否只用ASCII字符：`o`开、`h`半按、`x`按住
+    pub ascii: bool,
+    /// 用Unicode组合附加符号画八度点和减时线，代替括号写法
+    pub dots: bool,
+    pub layout: Layout,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            color: true,
+            ascii: false,
+            dots: false,
+            layout: Layout::Vertical,
+        }
+    }
+}
+
+impl TerminalOptions {
+    fn hole(&self, hole: Hole) -> String {
+        let symbol = match (hole, self.ascii) {
+            (Hole::Open, false) => "○",
+            (Hole::Half, false) => "◐",
+            (Hole::Close, false) => "●",
+            (Hole::Open, true) => "o",
+            (Hole::Half, true) => "h",
+            (Hole::Close, true) => "x",
+        };
+        if !self.color {
+            return symbol.to_owned();
+        }
+        let color = match hole {
+            Hole::Open => "90",
+            Hole::Half => "33",
+            Hole::Close => "1;34",
+        };
+        format!("\x1b[{}m{}\x1b[0m", color, symbol)
+    }
+
+    fn text(&self, token: &Token) -> String {
+        match token {
+            Token::Notation(n) if self.dots => n.to_unicode(),
+            token => token.to_string(),
+        }
+    }
+}
+
+/// 显示宽度，组合附加符号不占位置，汉字和全角符号占两格
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if is_combining(c) { 0 } else if is_wide(c) { 2 } else { 1 }).sum()
+}
+
+/// 东亚宽字符：谚文字母、中日韩符号和文字、全角符号
+fn is_wide(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{115f}'
+        | '\u{2e80}'..='\u{303e}'
+        | '\u{3041}'..='\u{33ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{a000}'..='\u{a4cf}'
+        | '\u{ac00}'..='\u{d7a3}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff00}'..='\u{ff60}'
+        | '\u{ffe0}'..='\u{ffe6}'
+        | '\u{20000}'..='\u{2fffd}'
+        | '\u{30000}'..='\u{3fffd}')
+}
+
+fn is_combining(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036f}' | '\u{20d0}'..='\u{20ff}')
+}
+
+/// 竖笛数字简谱可视化为紧凑的终端输出
+pub fn visualize_terminal(content: &str, finger_tone: FingerTone, mode: FingeringMode, options: &TerminalOptions) -> Result<String, ConvertError> {
+    let lines = fingering_lines(content, finger_tone, mode)?;
+    let mut out = Vec::new();
+
+    for line in &lines {
+        if line.is_empty() {
+            continue;
+        }
+        match options.layout {
+            Layout::Vertical => {
+                for row in wrap_line_with(line, options.width as u32, |token| display_width(&options.text(token)) as u32) {
+                    render_vertical(&mut out, row, options);
+                    out.push(String::new());
+                }
+            }
+            Layout::Horizontal => {
+                render_horizontal(&mut out, line, options);
+                out.push(String::new());
+            }
+        }
+    }
+
+    Ok(out.join("\n"))
+}
+
+fn render_vertical(out: &mut Vec<String>, row: Row, options: &TerminalOptions) {
+    let mut rows = vec![String::new(); 9];
+
+    for (token, fingering) in row {
+        let text = options.text(token);
+        let width = display_width(&text);
+        match (token, fingering) {
+            (Token::Notation(_), Some(fingering)) => {
+                // 孔位对齐到数字下方
+                let left = text.chars().filter(|c| !is_combining(*c)).position(|c| c.is_ascii_digit()).unwrap_or(0);
+                let right = width - 1 - left;
+                for (index, hole) in fingering.holes().iter().enumerate() {
+                    rows[index].push_str(&" ".repeat(left));
+                    rows[index].push_str(&options.hole(*hole));
+                    rows[index].push_str(&" ".repeat(right));
+                }
+            }
+            (token, _) if token.is_text() => {}
+            _ => {
+                for item in rows.iter_mut().take(8) {
+                    item.push_str(&" ".repeat(width));
+                }
+            }
+        }
+        rows[8].push_str(&text);
+    }
+
+    if row.iter().all(|(_, fingering)| fingering.is_none()) {
+        out.push(rows.pop().unwrap_or_default());
+        return;
+    }
+    out.extend(rows.into_iter().map(|row| row.trim_end().to_owned()));
+}
+
+fn render_horizontal(out: &mut Vec<String>, line: &[(Token, Option<Fingering>)], options: &TerminalOptions) {
+    let width = line.iter()
+        .filter(|(token, _)| matches!(token, Token::Notation(_)))
+        .map(|(token, _)| display_width(&options.text(token)))
+        .max()
+        .unwrap_or(0);
+
+    for (token, fingering) in line {
+        match (token, fingering) {
+            (Token::Notation(_), Some(fingering)) => {
+                let holes = fingering.holes().iter().map(|hole| options.hole(*hole)).collect::<Vec<_>>();
+                let text = options.text(token);
+                out.push(format!(
+                    "{}{}  {} {} {}",
+                    " ".repeat(width - display_width(&text)),
+                    text,
+                    holes[0],
+                    holes[1..4].concat(),
+                    holes[4..].concat(),
+                ));
+            }
+            (token, _) if token.is_text() => out.push(token.to_string()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(width: usize, layout: Layout) -> TerminalOptions {
+        TerminalOptions {
+            width,
+            color: false,
+            ascii: true,
+            dots: false,
+            layout,
+        }
+    }
+
+    fn visualize(content: &str, options: &TerminalOptions) -> String {
+        visualize_terminal(content, FingerTone::C, FingeringMode::Standard, options).unwrap()
+    }
+
+    #[test]
+    fn test_vertical() {
+        assert_eq!(visualize("1 #2  [3]\n// 注释", &options(80, Layout::Vertical)), "x  x   h
+x  x   x
+x  x   x
+x  x   x
+x  x   x
+x  x   x
+x  h   o
+x  o   o
+1 #2  [3]
+
+// 注释
+");
+    }
+
+    #[test]
+    fn test_vertical_wrap() {
+        let output = visualize("1 2 3 4 5", &options(5, Layout::Vertical));
+        assert_eq!(output.lines().filter(|line| line.starts_with(|c: char| c.is_ascii_digit())).collect::<Vec<_>>(), vec!["1 2 3", "4 5"]);
+        assert_eq!(output.lines().count(), 19);
+    }
+
+    #[test]
+    fn test_horizontal() {
+        assert_eq!(visualize("1 [#1]\n\n5", &options(80, Layout::Horizontal)), "   1  x xxx xxxx
+[#1]  o xxo oooo
+
+5  x xxx oooo
+");
+    }
+
+    #[test]
+    fn test_dots() {
+        let options = TerminalOptions { dots: true, ..options(80, Layout::Vertical) };
+        let output = visualize("1_ [#1] 2. -", &options);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[8], "1\u{0332} ♯1\u{0307} 2· -");
+        // 孔位仍然对齐到数字下方
+        assert_eq!(lines[0], "x  o x");
+
+        let horizontal = TerminalOptions { dots: true, ..self::options(80, Layout::Horizontal) };
+        assert_eq!(visualize("5 [#1]", &horizontal), " 5  x xxx oooo\n♯1\u{0307}  o xxo oooo\n");
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("w: 孟 姜 女"), 11);
+        assert_eq!(display_width("// 注释，（全角）"), 17);
+        assert_eq!(display_width("\u{0332}1\u{0307}"), 1);
+        assert_eq!(display_width("1\u{20d7}2\u{20e3}"), 2);
+    }
+
+    #[test]
+    fn test_color() {
+        let options = TerminalOptions { layout: Layout::Horizontal, ..Default::default() };
+        let output = visualize("[3]", &options);
+        assert!(output.contains("\x1b[33m◐\x1b[0m"));
+        assert!(output.contains("\x1b[1;34m●\x1b[0m"));
+        assert!(output.contains("\x1b[90m○\x1b[0m"));
+    }
+}
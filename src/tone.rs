@@ -1,13 +1,18 @@
-use crate::notation::{self, NOTATIONS_MAP};
+use crate::notation::Notation;
 use crate::finger::{Fingering, Hole};
+use crate::key::{read_header, write_header, Numbering};
 use num::FromPrimitive;
 use num_derive::FromPrimitive;
+use serde::Serialize;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::error::Error;
-use crate::notation::parser::{Parser, ParseError, Token};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use crate::notation::parser::{Header, Parser, ParseError, Token};
+use crate::optimizer::{optimize_fingerings, FingeringMode};
 
 /// 音调：竖笛的两个八度
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, FromPrimitive, Serialize)]
 #[repr(usize)]
 pub enum Tone {
     LLC = 1,
@@ -349,9 +354,69 @@ impl Tone {
             _ => None,
         }
     }
+
+    /// 德式竖笛指法，只有F和#F和英式不同
+    pub fn to_german_finger(self) -> Option<Fingering> {
+        match self {
+            Tone::F => Some(Fingering::new(
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Open,
+                Hole::Open,
+                Hole::Open,
+            )),
+            Tone::SF => Some(Fingering::new(
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Open,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+            )),
+            _ => self.to_finger(),
+        }
+    }
+
+    /// 所有可用指法，第一个为标准指法，其余为替代指法
+    pub fn to_fingers(self) -> Vec<Fingering> {
+        let mut fingers: Vec<_> = self.to_finger().into_iter().collect();
+        match self {
+            // 德式F指法
+            Tone::F => fingers.extend(self.to_german_finger()),
+            // 超吹低音D
+            Tone::HD => fingers.push(Fingering::new(
+                Hole::Half,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Open,
+            )),
+            // 超吹低音#D
+            Tone::HSD => fingers.push(Fingering::new(
+                Hole::Half,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Close,
+                Hole::Half,
+                Hole::Open,
+            )),
+            _ => {}
+        }
+        fingers
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(usize)]
 /// 音调指法，按顺序递增
 pub enum FingerTone {
@@ -370,38 +435,248 @@ pub enum FingerTone {
 }
 
 impl FingerTone {
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "#a" => Some(FingerTone::SA),
-            "a" => Some(FingerTone::A),
-            "#g" => Some(FingerTone::SG),
-            "g" => Some(FingerTone::G),
-            "#f" => Some(FingerTone::SF),
-            "f" => Some(FingerTone::F),
-            "e" => Some(FingerTone::E),
-            "#d" => Some(FingerTone::SD),
-            "d" => Some(FingerTone::D),
-            "#c" => Some(FingerTone::SC),
-            "c" => Some(FingerTone::C),
-            "b" => Some(FingerTone::B),
-            _ => None
+    /// 所有指法音调，按音高从C到B排列
+    pub const ALL: [FingerTone; 12] = [
+        FingerTone::C,
+        FingerTone::SC,
+        FingerTone::D,
+        FingerTone::SD,
+        FingerTone::E,
+        FingerTone::F,
+        FingerTone::SF,
+        FingerTone::G,
+        FingerTone::SG,
+        FingerTone::A,
+        FingerTone::SA,
+        FingerTone::B,
+    ];
+
+    /// 调号在五度圈上的位置：升号调为正，降号调为负，`#C`、`#D`、`#G`、`#A`按降号调记
+    pub fn fifths(self) -> i8 {
+        match self {
+            FingerTone::C => 0,
+            FingerTone::G => 1,
+            FingerTone::D => 2,
+            FingerTone::A => 3,
+            FingerTone::E => 4,
+            FingerTone::B => 5,
+            FingerTone::SF => 6,
+            FingerTone::SC => -5,
+            FingerTone::SG => -4,
+            FingerTone::SD => -3,
+            FingerTone::SA => -2,
+            FingerTone::F => -1,
+        }
+    }
+
+    /// 按五度圈上的位置返回调，`fifths()`的逆运算
+    pub fn from_fifths(fifths: i8) -> FingerTone {
+        FingerTone::ALL[(fifths as i32 * 7).rem_euclid(12) as usize]
+    }
+
+    /// 主音比C高几个半音，0到11
+    pub fn semitones(self) -> u8 {
+        match self {
+            FingerTone::C => 0,
+            FingerTone::SC => 1,
+            FingerTone::D => 2,
+            FingerTone::SD => 3,
+            FingerTone::E => 4,
+            FingerTone::F => 5,
+            FingerTone::SF => 6,
+            FingerTone::G => 7,
+            FingerTone::SG => 8,
+            FingerTone::A => 9,
+            FingerTone::SA => 10,
+            FingerTone::B => 11,
         }
     }
+
+    /// 简谱的`1`比`Tone::C`高几个半音：C到#A往上数，B调的`1`在C下面
+    pub(crate) fn do_offset(self) -> i32 {
+        match self.semitones() {
+            11 => -1,
+            semitones => semitones as i32,
+        }
+    }
+
+}
+
+/// 解析音名，例如`C`、`#c`、`C#`、`Db`、`♭E`，不分大小写。
+/// 返回音名比C高几个半音、升降（升为1，降为-1）和后面剩下的部分
+pub(crate) fn parse_pitch_name(s: &str) -> Option<(i32, i32, &str)> {
+    let mut chars = s.chars();
+    let (alter, letter) = match chars.next()? {
+        '#' | '♯' => (1, chars.next()?),
+        '♭' => (-1, chars.next()?),
+        letter => {
+            let rest = chars.as_str();
+            match chars.next() {
+                Some('#') | Some('♯') => (1, letter),
+                Some('b') | Some('♭') => (-1, letter),
+                _ => {
+                    chars = rest.chars();
+                    (0, letter)
+                }
+            }
+        }
+    };
+    let step = match letter.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    Some((step, alter, chars.as_str()))
+}
+
+/// 调名或音名有误
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneError(pub(crate) String);
+
+impl Display for ToneError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ToneError {}
+
+impl FromStr for FingerTone {
+    type Err = ToneError;
+
+    /// 调名，例如`C`、`#c`、`C#`、`Db`、`♭E`、`1=D`，不分大小写
+    fn from_str(s: &str) -> Result<Self, ToneError> {
+        let name = s.trim();
+        let name = name.strip_prefix("1=").unwrap_or(name).trim_start();
+        match parse_pitch_name(name) {
+            Some((step, alter, "")) => Ok(FingerTone::ALL[(step + alter).rem_euclid(12) as usize]),
+            _ => Err(ToneError(format!("无法识别的调：{}", s))),
+        }
+    }
+}
+
+impl Display for FingerTone {
+    /// 调名，例如`C`，`#A`
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            FingerTone::SA => "#A",
+            FingerTone::A => "A",
+            FingerTone::SG => "#G",
+            FingerTone::G => "G",
+            FingerTone::SF => "#F",
+            FingerTone::F => "F",
+            FingerTone::E => "E",
+            FingerTone::SD => "#D",
+            FingerTone::D => "D",
+            FingerTone::SC => "#C",
+            FingerTone::C => "C",
+            FingerTone::B => "B",
+        }.fmt(f)
+    }
 }
 
 impl Tone {
+    /// 从低到高的所有音调
+    pub fn all() -> impl Iterator<Item = Tone> {
+        (Tone::LLC as usize..=Tone::HHB as usize).filter_map(FromPrimitive::from_usize)
+    }
+
+    /// MIDI音符编号，按高音竖笛的实际音高，`Tone::C`为C5（72）
+    pub fn midi(self) -> u8 {
+        self as u8 + 47
+    }
+
+    /// MIDI音符编号转换成Tone，超出范围返回`None`
+    pub fn from_midi(midi: u8) -> Option<Tone> {
+        midi.checked_sub(47).and_then(FromPrimitive::from_u8)
+    }
+
+    /// 实际频率（Hz），`a4`为A4的频率
+    pub fn frequency(self, a4: f64) -> f64 {
+        a4 * 2f64.powf((self.midi() as f64 - 69.0) / 12.0)
+    }
+
+    /// 频率转换成最接近的Tone和偏差（音分，偏高为正），超出范围返回`None`
+    pub fn from_frequency(frequency: f64, a4: f64) -> Option<(Tone, f64)> {
+        if frequency <= 0.0 || a4 <= 0.0 {
+            return None;
+        }
+        let midi = 69.0 + 12.0 * (frequency / a4).log2();
+        let nearest = midi.round();
+        if !(0.0..=255.0).contains(&nearest) {
+            return None;
+        }
+        Tone::from_midi(nearest as u8).map(|tone| (tone, (midi - nearest) * 100.0))
+    }
+
     /// 返回数字符号
-    pub fn to_notation(self, finger_tone: FingerTone) -> &'static str {
-        let index = self as usize + finger_tone as usize + notation::TONE_C_START - FingerTone::C as usize - Tone::C as usize;
-        notation::NOTATIONS[index]
+    pub fn to_notation(self, finger_tone: FingerTone) -> Notation {
+        Notation::from_semitones(self as i32 - Tone::C as i32 - finger_tone.do_offset())
+    }
+
+    /// 实际音高的音名、升降和八度（C4为中央C），`flats`为真时黑键记成降号
+    pub fn spell(self, flats: bool) -> (&'static str, i8, u8) {
+        const SHARP_NAMES: [(&str, i8); 12] = [
+            ("C", 0), ("C", 1), ("D", 0), ("D", 1), ("E", 0), ("F", 0),
+            ("F", 1), ("G", 0), ("G", 1), ("A", 0), ("A", 1), ("B", 0),
+        ];
+        const FLAT_NAMES: [(&str, i8); 12] = [
+            ("C", 0), ("D", -1), ("D", 0), ("E", -1), ("E", 0), ("F", 0),
+            ("G", -1), ("G", 0), ("A", -1), ("A", 0), ("B", -1), ("B", 0),
+        ];
+
+        let midi = self.midi();
+        let names = if flats { &FLAT_NAMES } else { &SHARP_NAMES };
+        let (step, alter) = names[(midi % 12) as usize];
+        (step, alter, midi / 12 - 1)
+    }
+
+
+    /// 音符的音调，不管时值，超出范围返回`None`
+    pub fn from_notation(notation: &Notation, finger_tone: FingerTone) -> Option<Tone> {
+        FromPrimitive::from_i32(notation.semitones() + finger_tone.do_offset() + Tone::C as i32)
     }
 
     /// 数字符号返回Tone
     pub fn notation_to_tone(notation: &str, finger_tone: FingerTone) -> Option<Tone> {
-        NOTATIONS_MAP.get(notation).and_then(|index| {
-            let index = index + FingerTone::C as usize + Tone::C as usize - finger_tone as usize - notation::TONE_C_START;
-            FromPrimitive::from_usize(index)
-        })
+        notation.parse().ok().and_then(|notation| Tone::from_notation(&notation, finger_tone))
+    }
+}
+
+impl Display for Tone {
+    /// 实际音高的音名，例如`C5`，`F#4`
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let (step, alter, octave) = self.spell(false);
+        let sharp = if alter > 0 { "#" } else { "" };
+        format!("{}{}{}", step, sharp, octave).fmt(f)
+    }
+}
+
+impl FromStr for Tone {
+    type Err = ToneError;
+
+    /// 实际音高的音名，例如`C5`、`f#4`、`Bb5`、`♭E6`
+    fn from_str(s: &str) -> Result<Self, ToneError> {
+        let error = || ToneError(format!("无法识别的音：{}", s));
+        let (step, alter, octave) = parse_pitch_name(s.trim()).ok_or_else(error)?;
+        let octave: i32 = octave.parse().map_err(|_| error())?;
+        u8::try_from((octave + 1) * 12 + step + alter).ok()
+            .and_then(Tone::from_midi)
+            .ok_or_else(|| ToneError(format!("超出音域的音：{}", s)))
+    }
+}
+
+impl TryFrom<u8> for Tone {
+    type Error = ToneError;
+
+    /// MIDI音符编号转换成Tone
+    fn try_from(midi: u8) -> Result<Self, ToneError> {
+        Tone::from_midi(midi).ok_or_else(|| ToneError(format!("超出音域的MIDI音符：{}", midi)))
     }
 }
 
@@ -432,18 +707,21 @@ impl Error for ConvertError {}
 /// 转换简谱
 pub fn convert_tone(content: &str, from: FingerTone, to: FingerTone) -> Result<String, ConvertError> {
     let parser = Parser::from_str(content)?;
+    let shift = from.do_offset() - to.do_offset();
     let mut lines = Vec::new();
     for line in parser.lines() {
         let mut new_line = Vec::new();
         for token in line {
             let s = format!("{}", token);
             let s = match token {
-                Token::Notation(n) => {
-                    let tone = Tone::notation_to_tone(&s, from)
-                        .ok_or_else(|| ConvertError::NotFound("转换失败：出现未知音符".to_owned()))?;
-                    tone.to_notation(to).to_owned()
+                // 只按半音移动，不经过Tone，超出竖笛音域的音也能转；不移动时保留原来的写法
+                Token::Notation(n) if shift != 0 => n.transpose(shift).to_string(),
+                // 音高不变，调性也不变，只是`1`换了
+                Token::Header(header) => match read_header(&header) {
+                    Ok((_, tonality)) => write_header(header, to, tonality).to_string(),
+                    Err(_) => Header { key: to.to_string(), ..header }.to_string(),
                 },
-                t => s.to_owned(),
+                _ => s.to_owned(),
             };
             new_line.push(s);
         }
@@ -455,34 +733,47 @@ pub fn convert_tone(content: &str, from: FingerTone, to: FingerTone) -> Result<S
     }).collect::<Vec<_>>().join("\n"))
 }
 
+/// 按另一种记法重新记谱，音高不变：`Numbering::La`时A小调的主音记成`6`，`Numbering::Do`时记成`1`。
+/// 调性取第一个调号行，大调两种记法一样
+pub fn renumber(content: &str, numbering: Numbering) -> Result<String, ConvertError> {
+    let lines = Parser::from_str(content)?.lines();
+    let header = lines.iter().flatten().find_map(|token| match token {
+        Token::Header(header) => Some(header),
+        _ => None,
+    }).ok_or_else(|| ConvertError::NotFound("没有调号行，不知道是什么调".to_owned()))?;
+
+    match read_header(header).map_err(|e| ConvertError::NotFound(e.to_string()))? {
+        (one, Some(tonality)) => convert_tone(content, one, tonality.one(numbering)),
+        (_, None) => Ok(content.to_owned()),
+    }
+}
+
 /// 竖笛数字简谱可视化
 pub fn visualize_tone(content: &str, finger_tone: FingerTone) -> Result<String, ConvertError> {
-    let parser = Parser::from_str(content)?;
+    visualize_tone_with_mode(content, finger_tone, FingeringMode::Standard)
+}
+
+/// 竖笛数字简谱可视化，按指定方式选择指法
+pub fn visualize_tone_with_mode(content: &str, finger_tone: FingerTone, mode: FingeringMode) -> Result<String, ConvertError> {
     let mut lines = Vec::new();
 
-    for line in parser.lines() {
+    for line in fingering_lines(content, finger_tone, mode)? {
         if line.is_empty() {
             continue;
         }
 
         let mut fingers_list = vec![Vec::new(); 11];
 
-        for token in &line {
+        for (token, fingering) in &line {
             let finger: String;
             let s = token.to_string();
-            let fingers = match token {
-                Token::Notation(n) => {
-                    let tone = Tone::notation_to_tone(&s, finger_tone)
-                        .ok_or_else(|| ConvertError::NotFound(format!("出现未知音符：{}", &s)))?;
-                    finger = tone.to_finger()
-                        .ok_or_else(|| ConvertError::NotFound(format!("这个音调竖笛吹不了的音符：{}", &s)))?
-                        .to_string()
-                        .trim()
-                        .to_string();
+            let fingers = match (token, fingering) {
+                (Token::Notation(_), Some(fingering)) => {
+                    finger = fingering.to_string().trim().to_string();
                     let finger = finger.split('\n');
                     finger.collect::<Vec<_>>()
                 },
-                Token::Whitespace => vec!["     "; 11],
+                (Token::Whitespace, _) | (Token::Dash, _) | (Token::Rest(_), _) | (Token::Bar, _) => vec!["     "; 11],
                 _ => vec![&*s; 11],
             };
 
@@ -497,7 +788,7 @@ pub fn visualize_tone(content: &str, finger_tone: FingerTone) -> Result<String,
             lines.push(fingers);
         }
 
-        lines.push(line.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        lines.push(line.iter().map(|(s, _)| s.to_string()).collect::<Vec<_>>());
 
         lines.push(Vec::new());
     }
@@ -507,21 +798,65 @@ pub fn visualize_tone(content: &str, finger_tone: FingerTone) -> Result<String,
     }).collect::<Vec<_>>().join("\n"))
 }
 
+/// 带指法的一行简谱，只有音符带有指法
+pub type FingeringLine = Vec<(Token, Option<Fingering>)>;
+
+/// 解析简谱并给每个音符配上指法，供各种可视化输出使用
+pub fn fingering_lines(content: &str, finger_tone: FingerTone, mode: FingeringMode) -> Result<Vec<FingeringLine>, ConvertError> {
+    let parser = Parser::from_str(content)?;
+    let lines = parser.lines();
+    let mut fingerings = resolve_fingerings(&lines, finger_tone, mode)?.into_iter();
+
+    Ok(lines.into_iter().map(|line| {
+        line.into_iter().map(|token| {
+            let fingering = match token {
+                Token::Notation(_) => fingerings.next(),
+                _ => None,
+            };
+            (token, fingering)
+        }).collect()
+    }).collect())
+}
+
+/// 按顺序给出简谱中每个音符的指法
+fn resolve_fingerings(lines: &[Vec<Token>], finger_tone: FingerTone, mode: FingeringMode) -> Result<Vec<Fingering>, ConvertError> {
+    let mut tones = Vec::new();
+
+    for token in lines.iter().flatten() {
+        if let Token::Notation(n) = token {
+            let s = n.pitch().to_string();
+            let tone = Tone::from_notation(n, finger_tone)
+                .ok_or_else(|| ConvertError::NotFound(format!("出现未知音符：{}", &s)))?;
+            if tone.to_finger().is_none() {
+                return Err(ConvertError::NotFound(format!("这个音调竖笛吹不了的音符：{}", &s)));
+            }
+            tones.push(tone);
+        }
+    }
+
+    match mode {
+        FingeringMode::Standard => Ok(tones.iter().filter_map(|tone| tone.to_finger()).collect()),
+        FingeringMode::German => Ok(tones.iter().filter_map(|tone| tone.to_german_finger()).collect()),
+        FingeringMode::Auto(model) => optimize_fingerings(&tones, &model)
+            .ok_or_else(|| ConvertError::NotFound("找不到可用的指法".to_owned())),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn test_to_notation() {
-        assert_eq!(Tone::C.to_notation(FingerTone::C), "1");
-        assert_eq!(Tone::D.to_notation(FingerTone::C), "2");
-        assert_eq!(Tone::C.to_notation(FingerTone::D), "(#6)");
-        assert_eq!(Tone::SC.to_notation(FingerTone::SC), "1");
-        assert_eq!(Tone::HHD.to_notation(FingerTone::C), "[[2]]");
-        assert_eq!(Tone::C.to_notation(FingerTone::B), "#1");
-        assert_eq!(Tone::HD.to_notation(FingerTone::G), "5");
-        assert_eq!(Tone::HSD.to_notation(FingerTone::SG), "5");
-        assert_eq!(Tone::HHD.to_notation(FingerTone::B), "[[#2]]");
+        assert_eq!(Tone::C.to_notation(FingerTone::C).to_string(), "1");
+        assert_eq!(Tone::D.to_notation(FingerTone::C).to_string(), "2");
+        assert_eq!(Tone::C.to_notation(FingerTone::D).to_string(), "(#6)");
+        assert_eq!(Tone::SC.to_notation(FingerTone::SC).to_string(), "1");
+        assert_eq!(Tone::HHD.to_notation(FingerTone::C).to_string(), "[[2]]");
+        assert_eq!(Tone::C.to_notation(FingerTone::B).to_string(), "#1");
+        assert_eq!(Tone::HD.to_notation(FingerTone::G).to_string(), "5");
+        assert_eq!(Tone::HSD.to_notation(FingerTone::SG).to_string(), "5");
+        assert_eq!(Tone::HHD.to_notation(FingerTone::B).to_string(), "[[#2]]");
     }
 
     #[test]
@@ -534,8 +869,143 @@ mod test {
         assert_eq!(Tone::notation_to_tone("[1]", FingerTone::G), Some(Tone::HG));
         assert_eq!(Tone::notation_to_tone("(5)", FingerTone::C), Some(Tone::LG));
         assert_eq!(Tone::notation_to_tone("(7)", FingerTone::C), Some(Tone::LB));
-        assert_eq!(Tone::notation_to_tone("(#7)", FingerTone::C), None);
+        assert_eq!(Tone::notation_to_tone("(#7)", FingerTone::C), Some(Tone::C));
+        assert_eq!(Tone::notation_to_tone("((1))", FingerTone::C), Some(Tone::LLC));
+        assert_eq!(Tone::notation_to_tone("(((1)))", FingerTone::C), None);
+        assert_eq!(Tone::notation_to_tone("[[[1]]]", FingerTone::C), None);
+        assert_eq!(Tone::notation_to_tone("[[[1]]]", FingerTone::B), Some(Tone::HHB));
         assert_eq!(Tone::notation_to_tone("[[3]]", FingerTone::B), Some(Tone::HHSD));
     }
+
+    #[test]
+    fn test_visualize_tone_auto() {
+        let standard = visualize_tone("2 [2]", FingerTone::C).unwrap();
+        let auto = visualize_tone_with_mode("2 [2]", FingerTone::C, FingeringMode::Auto(Default::default())).unwrap();
+        assert_eq!(standard.lines().next(), Some("●     ○"));
+        assert_eq!(auto.lines().next(), Some("●     ◐"));
+        assert_eq!(standard.lines().last(), auto.lines().last());
+
+        let german = visualize_tone_with_mode("3 4 #4", FingerTone::C, FingeringMode::German).unwrap();
+        let standard = visualize_tone("3 4 #4", FingerTone::C).unwrap();
+        assert_eq!(german.lines().nth(9), Some("○     ○     ●"));
+        assert_eq!(standard.lines().nth(9), Some("○     ●     ○"));
+    }
+
+    #[test]
+    fn test_fifths() {
+        for &finger_tone in FingerTone::ALL.iter() {
+            assert_eq!(FingerTone::from_fifths(finger_tone.fifths()), finger_tone);
+        }
+        assert_eq!(FingerTone::from_fifths(-6), FingerTone::SF);
+        assert_eq!(FingerTone::from_fifths(7), FingerTone::SC);
+    }
+
+    #[test]
+    fn test_finger_tone_from_str() {
+        for &finger_tone in FingerTone::ALL.iter() {
+            assert_eq!(finger_tone.to_string().parse(), Ok(finger_tone));
+            assert_eq!(finger_tone.to_string().to_lowercase().parse(), Ok(finger_tone));
+        }
+        for s in &["#c", "C#", "c♯", "♯C", "Db", "db", "♭D", "D♭", "1=Db", " 1=#c "] {
+            assert_eq!(s.parse(), Ok(FingerTone::SC), "{}", s);
+        }
+        assert_eq!("bb".parse(), Ok(FingerTone::SA));
+        assert_eq!("Cb".parse(), Ok(FingerTone::B));
+        assert_eq!("E#".parse(), Ok(FingerTone::F));
+        for s in &["", "H", "#", "C##", "Cm", "1="] {
+            assert!(s.parse::<FingerTone>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_tone_traits() {
+        let tones = Tone::all().collect::<Vec<_>>();
+        assert_eq!(tones.len(), Tone::HHB as usize);
+        assert_eq!(tones.first(), Some(&Tone::LLC));
+        assert!(tones.windows(2).all(|pair| pair[0] < pair[1]));
+        for &tone in &tones {
+            assert_eq!(tone.to_string().parse(), Ok(tone));
+            assert_eq!(Tone::try_from(tone.midi()), Ok(tone));
+        }
+        assert_eq!(Tone::C.to_string(), "C5");
+        assert_eq!(Tone::LSF.to_string(), "F#4");
+        assert_eq!("Bb5".parse(), Ok(Tone::SA));
+        assert_eq!("♭e6".parse(), Ok(Tone::HSD));
+        assert_eq!("Cb5".parse(), Ok(Tone::LB));
+        assert!("C".parse::<Tone>().is_err());
+        assert!("C9".parse::<Tone>().is_err());
+        assert!(Tone::try_from(0).is_err());
+
+        let set = tones.iter().copied().collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), tones.len());
+    }
+
+    #[test]
+    fn test_convert_keeps_tonality() {
+        assert_eq!(convert_tone("1=G D徵\n5 6", FingerTone::G, FingerTone::C).unwrap(), "1=C D徵\n[2] [3]");
+        assert_eq!(convert_tone("1=C Am\n6 7", FingerTone::C, FingerTone::SA).unwrap(), "1=#A Am\n(7) #1");
+        assert_eq!(convert_tone("1=C Am\n6 7", FingerTone::C, FingerTone::A).unwrap(), "1=Am\n1 2");
+        assert_eq!(convert_tone("1=C\n1", FingerTone::C, FingerTone::SA).unwrap(), "1=#A\n(2)");
+    }
+
+    #[test]
+    fn test_renumber() {
+        let la_based = "1=C Am 2/4\n6 7 [1] [2] | [3] - |";
+        let do_based = "1=Am 2/4\n1 2 #2 4 | 5 - |";
+        assert_eq!(renumber(la_based, Numbering::Do).unwrap(), do_based);
+        assert_eq!(renumber(do_based, Numbering::La).unwrap(), la_based);
+        assert_eq!(renumber(do_based, Numbering::Do).unwrap(), do_based);
+
+        assert_eq!(renumber("1=G E羽\n6 [1] [2]", Numbering::Do).unwrap(), "1=E羽\n[1] [#2] [4]");
+        assert_eq!(renumber("1=Ebm\n1", Numbering::La).unwrap(), "1=#F Ebm\n(6)");
+        assert_eq!(renumber("1=D\n1 2", Numbering::Do).unwrap(), "1=D\n1 2");
+        assert!(renumber("1 2", Numbering::Do).is_err());
+    }
+
+    #[test]
+    fn test_midi() {
+        assert_eq!(Tone::C.midi(), 72);
+        assert_eq!(Tone::LLC.midi(), 48);
+        assert_eq!(Tone::from_midi(74), Some(Tone::D));
+        assert_eq!(Tone::from_midi(47), None);
+        assert_eq!(Tone::from_midi(120), None);
+        assert_eq!(Tone::C.spell(false), ("C", 0, 5));
+        assert_eq!(Tone::SA.spell(false), ("A", 1, 5));
+        assert_eq!(Tone::SA.spell(true), ("B", -1, 5));
+        assert_eq!(Tone::HD.spell(false), ("D", 0, 6));
+    }
+
+    #[test]
+    fn test_frequency() {
+        assert_eq!(Tone::A.frequency(440.0), 880.0);
+        assert!((Tone::C.frequency(440.0) - 523.251).abs() < 0.001);
+        assert_eq!(Tone::from_frequency(880.0, 440.0), Some((Tone::A, 0.0)));
+        let (tone, cents) = Tone::from_frequency(890.0, 440.0).unwrap();
+        assert_eq!(tone, Tone::A);
+        assert!((cents - 19.56).abs() < 0.01);
+        let (tone, cents) = Tone::from_frequency(880.0, 466.16).unwrap();
+        assert_eq!(tone, Tone::SG);
+        assert!((cents - 0.0).abs() < 0.1);
+        assert_eq!(Tone::from_frequency(100.0, 440.0), None);
+        assert_eq!(Tone::from_frequency(0.0, 440.0), None);
+    }
+
+    #[test]
+    fn test_convert_duration() {
+        assert_eq!(convert_tone("1_ 2__. - 3", FingerTone::C, FingerTone::D).unwrap(), "(#6)_ 1__. - 2");
+        assert_eq!(convert_tone("1=C 3/4\n1 0 | 2", FingerTone::C, FingerTone::D).unwrap(), "1=D 3/4\n(#6) 0 | 1");
+    }
+
+    #[test]
+    fn test_convert_same_tone() {
+        assert_eq!(convert_tone("1=C\n#3 #7 1 [#1]_", FingerTone::C, FingerTone::C).unwrap(), "1=C\n#3 #7 1 [#1]_");
+        assert_eq!(convert_tone("#3 #7", FingerTone::C, FingerTone::D).unwrap(), "#2 #6");
+    }
+
+    #[test]
+    fn test_convert_octaves() {
+        assert_eq!(convert_tone("((1))_ [[5]]", FingerTone::C, FingerTone::G).unwrap(), "(((4)))_ [[1]]");
+        assert_eq!(convert_tone("[[[1]]] (((#7)))", FingerTone::G, FingerTone::C).unwrap(), "[[[5]]] ((5))");
+    }
 }
 
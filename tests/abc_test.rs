@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::fs;
+use recorder::abc::{read_abc, write_abc, AbcOptions};
+use recorder::score::{Score, MEASURES_PER_LINE};
+
+/// 有三连音、简谱写不出来的曲子，只检查报错
+const WITHOUT_JIANPU: [&str; 1] = ["tests/abc/ode.abc"];
+
+/// `tests/abc`目录下的每首曲子
+fn corpus() -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir("tests/abc")? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "abc") {
+            files.push((path.display().to_string(), fs::read_to_string(&path)?));
+        }
+    }
+    files.sort();
+    assert!(!files.is_empty());
+    Ok(files)
+}
+
+#[test]
+fn test_abc_round_trip() -> Result<(), Box<dyn Error>> {
+    for (name, abc) in corpus()? {
+        let score = read_abc(&abc, &AbcOptions::default()).map_err(|e| format!("{}: {}", name, e))?;
+        let written = write_abc(&score, &name);
+        let again = read_abc(&written, &AbcOptions::default())?;
+        assert_eq!(again, score, "{}", name);
+        assert_eq!(write_abc(&again, &name), written, "{}", name);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_abc_to_jianpu() -> Result<(), Box<dyn Error>> {
+    let corpus = corpus()?;
+    for name in WITHOUT_JIANPU.iter() {
+        assert!(corpus.iter().any(|(file, _)| file == name), "{}", name);
+    }
+    for (name, abc) in corpus {
+        let score = read_abc(&abc, &AbcOptions::default())?;
+        // 简谱写不出三连音，报错而不是取近似的时值
+        if WITHOUT_JIANPU.contains(&name.as_str()) {
+            assert!(score.to_jianpu(MEASURES_PER_LINE).is_err(), "{}", name);
+            continue;
+        }
+        let jianpu = score.to_jianpu(MEASURES_PER_LINE).map_err(|e| format!("{}: {:?}", name, e))?;
+        let again = Score::parse(&jianpu, None)?;
+        assert_eq!(again.to_jianpu(MEASURES_PER_LINE)?, jianpu, "{}", name);
+        assert_eq!(again.finger_tone, score.finger_tone, "{}", name);
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use std::error::Error;
+use std::fs;
+use recorder::midi::{write_midi, MidiOptions};
+use recorder::score::Score;
+use recorder::tone::FingerTone;
+
+fn golden(source: &str, finger_tone: Option<FingerTone>, golden: &str) -> Result<(), Box<dyn Error>> {
+    let score = Score::parse(&fs::read_to_string(source)?, finger_tone)?;
+    assert_eq!(write_midi(&[score], &MidiOptions::default()), fs::read(golden)?, "{}", golden);
+    Ok(())
+}
+
+#[test]
+fn test_midi_golden() -> Result<(), Box<dyn Error>> {
+    // 没有节奏的简谱，每个音符为默认的四分音符
+    golden("tests/孟姜女.txt", Some(FingerTone::SA), "tests/midi/孟姜女.mid")?;
+    golden("tests/midi/rhythm.txt", None, "tests/midi/rhythm.mid")?;
+    Ok(())
+}
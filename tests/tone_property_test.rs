@@ -0,0 +1,149 @@
+use proptest::prelude::*;
+use proptest::sample::select;
+use recorder::key::{read_header, write_header, Key, Mode, Spelling};
+use recorder::notation::parser::{Header, Meter, Parser, Token};
+use recorder::notation::Notation;
+use recorder::tone::{convert_tone, FingerTone, Tone};
+
+/// 一个音符：任意八度、数字和升号，包括`#3`、`#7`这样的写法，带随机的时值
+fn notation() -> impl Strategy<Value = String> {
+    (-3..=3i8, 1..=7u8, any::<bool>(), 0..4usize, any::<bool>()).prop_map(|(octave, number, is_sharp, underline, dotted)| {
+        let pitch = Notation::new(octave, number, is_sharp);
+        format!("{}{}{}", pitch, "_".repeat(underline), if dotted { "." } else { "" })
+    })
+}
+
+/// 简谱行里的记号：音符、休止符、增时线和小节线
+fn item() -> impl Strategy<Value = String> {
+    prop_oneof![
+        6 => notation(),
+        1 => (0..4usize, any::<bool>()).prop_map(|(underline, dotted)| {
+            format!("0{}{}", "_".repeat(underline), if dotted { "." } else { "" })
+        }),
+        1 => Just("-".to_owned()),
+        1 => Just("|".to_owned()),
+    ]
+}
+
+/// 一行简谱，记号之间用一到两个空格隔开
+fn notes() -> impl Strategy<Value = String> {
+    prop::collection::vec((item(), 1..3usize), 1..12).prop_map(|items| {
+        items.iter().map(|(item, spaces)| format!("{}{}", item, " ".repeat(*spaces))).collect::<String>().trim_end().to_owned()
+    })
+}
+
+/// 若干行简谱，夹着`//`注释、`w:`歌词和空行，最后一行是简谱
+fn body() -> impl Strategy<Value = String> {
+    let line = prop_oneof![
+        4 => notes(),
+        1 => "//[ a-z0-9#|注释，]{0,12}".prop_map(|comment| comment.trim_end().to_owned()),
+        1 => prop::collection::vec("[a-z孟姜女*]{1,3}", 1..6).prop_map(|words| format!("w: {}", words.join(" "))),
+        1 => Just(String::new()),
+    ];
+    (prop::collection::vec(line, 0..5), notes()).prop_map(|(mut lines, last)| {
+        lines.push(last);
+        lines.join("\n")
+    })
+}
+
+fn finger_tone() -> impl Strategy<Value = FingerTone> {
+    select(FingerTone::ALL.to_vec())
+}
+
+fn tonality() -> impl Strategy<Value = Option<Key>> {
+    prop::option::of((0..12u8, select(vec![Spelling::Sharp, Spelling::Flat]), select(Mode::ALL.to_vec()))
+        .prop_map(|(tonic, spelling, mode)| Key::new(tonic, spelling, mode)))
+}
+
+/// 按`1`和调性写出的调号行，带拍号和速度
+fn header(one: FingerTone, tonality: Option<Key>) -> String {
+    let header = Header { key: String::new(), tonality: None, meter: Some(Meter { beats: 3, beat_type: 4 }), tempo: Some(90) };
+    Token::Header(write_header(header, one, tonality)).to_string()
+}
+
+/// 按`Notation::from_semitones`的写法重写音符，例如`#3`写成`4`，转调后都是这种写法
+fn normalize(content: &str) -> String {
+    content.parse::<Parser>().unwrap().lines().iter().map(|line| line.iter().map(|token| match token {
+        Token::Notation(n) => n.transpose(0).to_string(),
+        token => token.to_string(),
+    }).collect::<String>()).collect::<Vec<_>>().join("\n")
+}
+
+fn notations(content: &str) -> Vec<Notation> {
+    content.parse::<Parser>().unwrap().lines().into_iter().flatten().filter_map(|token| match token {
+        Token::Notation(n) => Some(n),
+        _ => None,
+    }).collect()
+}
+
+proptest! {
+    #[test]
+    fn test_convert_round_trip(body in body(), tonality in tonality()) {
+        for &from in FingerTone::ALL.iter() {
+            let content = format!("{}\n{}", header(from, tonality), body);
+            // 同一个调不改写法，换调后音符都按升号重写
+            prop_assert_eq!(convert_tone(&content, from, from).unwrap(), content.clone());
+            for &to in FingerTone::ALL.iter().filter(|&&to| to != from) {
+                let converted = convert_tone(&content, from, to).unwrap();
+                prop_assert_eq!(&converted, &convert_tone(&normalize(&content), from, to).unwrap(), "{} -> {}", from, to);
+                prop_assert_eq!(convert_tone(&converted, to, from).unwrap(), normalize(&content), "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert_keeps_pitch(body in body(), from in finger_tone(), to in finger_tone()) {
+        let converted = convert_tone(&body, from, to).unwrap();
+        let before = notations(&body);
+        let after = notations(&converted);
+        prop_assert_eq!(before.len(), after.len());
+
+        for (a, b) in before.iter().zip(after.iter()) {
+            prop_assert_eq!(Tone::from_notation(a, from), Tone::from_notation(b, to));
+            prop_assert_eq!((a.underline(), a.dotted()), (b.underline(), b.dotted()));
+        }
+        // 音程不变
+        for (a, b) in before.windows(2).zip(after.windows(2)) {
+            prop_assert_eq!(a[1].semitones() - a[0].semitones(), b[1].semitones() - b[0].semitones());
+        }
+    }
+
+    #[test]
+    fn test_convert_keeps_tonality(one in finger_tone(), tonality in tonality(), to in finger_tone()) {
+        let converted = convert_tone(&header(one, tonality), one, to).unwrap();
+        let header = match converted.parse::<Parser>().unwrap().lines().remove(0).remove(0) {
+            Token::Header(header) => header,
+            token => panic!("不是调号行：{:?}", token),
+        };
+        prop_assert_eq!(read_header(&header).unwrap(), (to, tonality));
+    }
+
+    #[test]
+    fn test_parser_round_trip(one in finger_tone(), tonality in tonality(), body in body()) {
+        let content = format!("{}\n{}", header(one, tonality), body);
+        let lines = content.parse::<Parser>().unwrap().lines();
+        let printed = lines.iter().map(|line| line.iter().map(Token::to_string).collect::<String>()).collect::<Vec<_>>().join("\n");
+        prop_assert_eq!(&printed, &content);
+        prop_assert_eq!(printed.parse::<Parser>().unwrap().lines(), lines);
+    }
+
+    #[test]
+    fn test_notation_round_trip(semitones in -100..100i32, shift in -50..50i32) {
+        let notation = Notation::from_semitones(semitones);
+        prop_assert_eq!(notation.to_string().parse::<Notation>(), Ok(notation));
+        prop_assert_eq!(notation.transpose(shift).transpose(-shift), notation);
+        prop_assert_eq!(notation.transpose(shift).semitones(), semitones + shift);
+    }
+
+    #[test]
+    fn test_notation_spelling(s in notation(), shift in -50..50i32) {
+        let notation = s.parse::<Notation>().unwrap();
+        prop_assert_eq!(notation.to_string(), s);
+        // 移调后按升号重写，`#3`变成`4`，`#7`变成高八度的`1`
+        let normalized = notation.transpose(0);
+        prop_assert_eq!(normalized.semitones(), notation.semitones());
+        prop_assert_eq!((normalized.underline(), normalized.dotted()), (notation.underline(), notation.dotted()));
+        prop_assert!(!normalized.is_sharp() || ![3, 7].contains(&normalized.number()));
+        prop_assert_eq!(notation.transpose(shift).transpose(-shift), normalized);
+    }
+}